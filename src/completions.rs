@@ -0,0 +1,277 @@
+//! Shell completion scripts and the man page, generated from the command table and `help()`.
+
+/// A dm command as the shells complete it.
+pub struct Command {
+    pub short: Option<char>,
+    pub long: String,
+    /// Long options, without the leading dashes.
+    pub options: Vec<String>,
+    /// What to offer for the positional argument.
+    pub argument: Argument,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Argument {
+    None,
+    Files,
+    /// Files and folders in HOME, whatever the working directory. A word that already is an
+    /// absolute, `~`-relative or explicitly relative path completes as it is.
+    HomeFiles,
+    /// A value from `dm --complete <kind>`.
+    Dynamic(&'static str),
+    Words(&'static [&'static str]),
+}
+
+impl Command {
+    fn flags(&self) -> Vec<String> {
+        let mut flags: Vec<String> = self.short.iter().map(|c| format!("-{c}")).collect();
+        flags.push(format!("--{}", self.long));
+        flags
+    }
+}
+
+const GLOBAL_OPTIONS: [&str; 2] = ["--home", "--data-dir"];
+
+fn all_flags(commands: &[Command]) -> String {
+    let mut flags: Vec<String> = commands.iter().flat_map(|c| c.flags()).collect();
+    flags.extend(GLOBAL_OPTIONS.iter().map(|o| o.to_string()));
+    flags.join(" ")
+}
+
+/// A `case` branch of the bash and zsh scripts, empty without anything to complete.
+fn case(command: &Command, lines: &[String]) -> String {
+    if lines.is_empty() {
+        return String::new();
+    }
+    let mut case = format!("        {})\n", command.flags().join("|"));
+    for line in lines {
+        case += &format!("            {line}\n");
+    }
+    case + "            ;;\n"
+}
+
+pub fn bash(commands: &[Command]) -> String {
+    let mut cases = String::new();
+    for command in commands {
+        let mut lines: Vec<String> = vec![];
+        if !command.options.is_empty() {
+            let options: Vec<String> = command.options.iter().map(|o| format!("--{o}")).collect();
+            lines.push(format!(
+                "words+=\"$(printf '%s\\n' {})\"",
+                options.join(" ")
+            ));
+        }
+        match command.argument {
+            Argument::None => {}
+            Argument::Files => lines.push("COMPREPLY+=($(compgen -f -- \"$cur\"))".to_string()),
+            Argument::HomeFiles => lines.extend(
+                [
+                    "case \"$cur\" in",
+                    "    /*|\\~*|./*|../*) COMPREPLY+=($(compgen -f -- \"$cur\")) ;;",
+                    "    *) COMPREPLY+=($(compgen -f -- \"$HOME/$cur\")) ;;",
+                    "esac",
+                ]
+                .map(|l| l.to_string()),
+            ),
+            Argument::Dynamic(kind) => lines.push(format!(
+                "words+=$'\\n'\"$(dm \"${{COMP_WORDS[@]:1:i-1}}\" --complete {kind} 2>/dev/null)\""
+            )),
+            Argument::Words(list) => lines.push(format!(
+                "words+=$'\\n'\"$(printf '%s\\n' {})\"",
+                list.join(" ")
+            )),
+        }
+        cases += &case(command, &lines);
+    }
+    format!(
+        r#"# bash completion for dm
+_dm() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" words="" i=1
+    while [[ "${{COMP_WORDS[i]}}" == --home || "${{COMP_WORDS[i]}}" == --data-dir ]]; do
+        i=$((i + 2))
+    done
+    if [ "$COMP_CWORD" -le "$i" ]; then
+        COMPREPLY=($(compgen -W "{flags}" -- "$cur"))
+        return
+    fi
+    COMPREPLY=()
+    case "${{COMP_WORDS[i]}}" in
+{cases}    esac
+    local IFS=$'\n'
+    COMPREPLY+=($(compgen -W "$words" -- "$cur"))
+}}
+complete -o filenames -o bashdefault -F _dm dm
+"#,
+        flags = all_flags(commands),
+    )
+}
+
+pub fn zsh(commands: &[Command]) -> String {
+    let mut cases = String::new();
+    for command in commands {
+        let mut lines: Vec<String> = vec![];
+        if !command.options.is_empty() {
+            let options: Vec<String> = command.options.iter().map(|o| format!("--{o}")).collect();
+            lines.push(format!("compadd -- {}", options.join(" ")));
+        }
+        match command.argument {
+            Argument::None => {}
+            Argument::Files => lines.push("_files".to_string()),
+            // Relative words are taken from HOME and completed as `~/...`, which dm resolves.
+            Argument::HomeFiles => lines.extend(
+                [
+                    "[[ $PREFIX == (/|\\~|./|../)* ]] || PREFIX=\"~/$PREFIX\"",
+                    "_files",
+                ]
+                .map(|l| l.to_string()),
+            ),
+            Argument::Dynamic(kind) => lines.push(format!(
+                "compadd -- ${{(f)\"$(dm ${{words[2,i-1]}} --complete {kind} 2>/dev/null)\"}}"
+            )),
+            Argument::Words(list) => lines.push(format!("compadd -- {}", list.join(" "))),
+        }
+        cases += &case(command, &lines);
+    }
+    format!(
+        r#"#compdef dm
+_dm() {{
+    local i=2
+    while [[ "${{words[i]}}" == --home || "${{words[i]}}" == --data-dir ]]; do
+        (( i += 2 ))
+    done
+    if (( CURRENT <= i )); then
+        compadd -- {flags}
+        return
+    fi
+    case "${{words[i]}}" in
+{cases}    esac
+}}
+compdef _dm dm
+"#,
+        flags = all_flags(commands),
+    )
+}
+
+pub fn fish(commands: &[Command]) -> String {
+    let mut script = String::from(
+        r#"# fish completion for dm
+function __dm_index
+    set -l tokens (commandline -opc)
+    set -l i 2
+    while contains -- "$tokens[$i]" --home --data-dir
+        set i (math $i + 2)
+    end
+    echo $i
+end
+function __dm_command
+    set -l tokens (commandline -opc)
+    set -l i (__dm_index)
+    if test $i -le (count $tokens)
+        echo $tokens[$i]
+    end
+end
+function __dm_home_files
+    set -l token (commandline -ct)
+    switch $token
+        case '/*' '~*' './*' '../*'
+            __fish_complete_path $token
+        case '*'
+            __fish_complete_path "~/$token"
+    end
+end
+function __dm_globals
+    set -l tokens (commandline -opc)
+    set -l i (__dm_index)
+    if test $i -gt 2
+        printf '%s\n' $tokens[2..(math $i - 1)]
+    end
+end
+complete -c dm -f
+"#,
+    );
+    script += &format!(
+        "complete -c dm -n 'test -z \"$(__dm_command)\"' -a '{}'\n",
+        all_flags(commands)
+    );
+    for command in commands {
+        let condition = command
+            .flags()
+            .iter()
+            .map(|f| format!("test \"$(__dm_command)\" = {f}"))
+            .collect::<Vec<String>>()
+            .join("; or ");
+        for option in command.options.iter() {
+            script += &format!("complete -c dm -n '{condition}' -a '--{option}'\n");
+        }
+        let argument = match command.argument {
+            Argument::None => continue,
+            Argument::Files => "-F".to_string(),
+            Argument::HomeFiles => "-a '(__dm_home_files)'".to_string(),
+            Argument::Dynamic(kind) => {
+                format!("-a '(dm (__dm_globals) --complete {kind} 2>/dev/null)'")
+            }
+            Argument::Words(list) => format!("-a '{}'", list.join(" ")),
+        };
+        script += &format!("complete -c dm -n '{condition}' {argument}\n");
+    }
+    script
+}
+
+/// The indentation of the lines that continue an option's description in `help()`.
+const CONTINUATION: &str = "                      ";
+
+/// Converts the untagged `help()` text into a man page.
+pub fn man(help: &str) -> String {
+    let mut page =
+        String::from(".TH DM 1\n.SH NAME\ndm \\- manage dotfiles in a bare git repository\n");
+    let mut in_item = false;
+    let mut description = String::new();
+    for line in help.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if in_item && line.starts_with(CONTINUATION) {
+            page += &format!("{}\n", escape(trimmed));
+        } else if let Some(usage) = trimmed.strip_prefix("Usage:") {
+            page += &format!(".SH SYNOPSIS\n{}\n", escape(usage.trim()));
+            page += &format!(".SH DESCRIPTION\n{description}");
+        } else if !line.starts_with(' ') && trimmed.ends_with(':') {
+            let title = trimmed.trim_end_matches(':').to_uppercase();
+            page += &format!(".SH {}\n", escape(&title));
+            in_item = false;
+        } else if trimmed.starts_with('-') {
+            // Flags and their arguments run up to the first capitalised word of the description.
+            let words: Vec<&str> = trimmed.split_whitespace().collect();
+            let split = words
+                .iter()
+                .position(|w| w.starts_with(|c: char| c.is_uppercase()))
+                .unwrap_or(words.len());
+            let flags = words[..split]
+                .iter()
+                .map(|w| match w.strip_suffix(',').unwrap_or(w) {
+                    flag if flag.starts_with('-') => {
+                        format!("\\fB{}\\fR{}", escape(flag), &w[flag.len()..])
+                    }
+                    _ => escape(w),
+                })
+                .collect::<Vec<String>>()
+                .join(" ");
+            page += &format!(".TP\n{flags}\n{}\n", escape(&words[split..].join(" ")));
+            in_item = true;
+        } else {
+            // The introduction comes before the usage line, but belongs after the synopsis.
+            description += &format!("{}\n", escape(trimmed));
+        }
+    }
+    page
+}
+
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{text}")
+    } else {
+        text
+    }
+}
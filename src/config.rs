@@ -0,0 +1,61 @@
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use std::fs;
+
+/// Settings read from `$DATA/config.toml`. Every key is optional.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub snapshots: SnapshotPolicy,
+    pub system: SystemConfig,
+}
+
+/// How many automatic snapshots are kept. Named snapshots are only removed explicitly.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotPolicy {
+    /// Number of automatic snapshots to keep, newest first.
+    pub keep: usize,
+    /// Automatic snapshots older than this many days are dropped, 0 keeps them regardless of age.
+    pub max_age_days: u64,
+}
+
+impl Default for SnapshotPolicy {
+    fn default() -> Self {
+        SnapshotPolicy {
+            keep: 20,
+            max_age_days: 0,
+        }
+    }
+}
+
+/// How dm writes the files of system entries, those outside HOME.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SystemConfig {
+    /// The command writes run through, e.g. `sudo` or `doas`. Empty runs them as is.
+    pub escalate: String,
+}
+
+impl Default for SystemConfig {
+    fn default() -> Self {
+        SystemConfig {
+            escalate: "sudo".to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads `file`, falling back to the defaults when it does not exist.
+    pub fn load(file: &str) -> Result<Config> {
+        let read = match fs::read_to_string(file) {
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(Error::io(format!("could not read '{file}'"), e)),
+        };
+        toml::from_str(&read).map_err(|e| Error::Config {
+            file: file.to_string(),
+            message: e.message().to_string(),
+        })
+    }
+}
@@ -0,0 +1,365 @@
+//! A structured view of `git diff` output: files, hunks and lines with their line numbers.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileStatus {
+    Added,
+    Deleted,
+    Modified,
+    Renamed,
+    Copied,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineKind {
+    Context,
+    Added,
+    Removed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffLine {
+    pub kind: LineKind,
+    pub content: String,
+    /// Line number in the old file, for context and removed lines.
+    pub old_no: Option<usize>,
+    /// Line number in the new file, for context and added lines.
+    pub new_no: Option<usize>,
+    /// The line is the last one of its file and lacks a trailing newline.
+    pub no_newline: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    /// The text after the second `@@`, usually the enclosing function or section.
+    pub section: String,
+    pub lines: Vec<DiffLine>,
+}
+
+impl Hunk {
+    pub fn header(&self) -> String {
+        format!(
+            "@@ -{},{} +{},{} @@",
+            self.old_start, self.old_len, self.new_start, self.new_len
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileDiff {
+    /// Path before the change, `None` for added files.
+    pub old_path: Option<String>,
+    /// Path after the change, `None` for deleted files.
+    pub new_path: Option<String>,
+    pub status: FileStatus,
+    pub old_mode: Option<String>,
+    pub new_mode: Option<String>,
+    /// Similarity percentage of a rename or copy.
+    pub similarity: Option<u8>,
+    pub binary: bool,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FileDiff {
+    fn new(old_path: Option<String>, new_path: Option<String>) -> FileDiff {
+        FileDiff {
+            old_path,
+            new_path,
+            status: FileStatus::Modified,
+            old_mode: None,
+            new_mode: None,
+            similarity: None,
+            binary: false,
+            hunks: vec![],
+        }
+    }
+
+    /// The path the change is best known by: the new path, or the old one for deletions.
+    pub fn path(&self) -> &str {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or_default()
+    }
+
+    pub fn mode_changed(&self) -> bool {
+        self.old_mode.is_some() && self.new_mode.is_some() && self.old_mode != self.new_mode
+    }
+
+    /// Number of added and removed lines.
+    pub fn stats(&self) -> (usize, usize) {
+        let mut added = 0;
+        let mut removed = 0;
+        for line in self.hunks.iter().flat_map(|h| h.lines.iter()) {
+            match line.kind {
+                LineKind::Added => added += 1,
+                LineKind::Removed => removed += 1,
+                LineKind::Context => {}
+            }
+        }
+        (added, removed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hunks.is_empty() && !self.binary && !self.mode_changed() && self.similarity.is_none()
+    }
+
+    /// Whether single hunks of this change can be applied on their own.
+    pub fn is_splittable(&self) -> bool {
+        self.status == FileStatus::Modified && !self.binary && !self.hunks.is_empty()
+    }
+
+    /// A patch for `git apply` holding only the hunks at the given indices. The new-side start
+    /// of each hunk is its old-side start shifted by the lines the included hunks before it
+    /// add or remove.
+    pub fn patch(&self, hunks: &[usize]) -> String {
+        let path = self.path();
+        let mut patch = format!("diff --git a/{path} b/{path}\n");
+        if self.mode_changed() {
+            let (old, new) = (self.old_mode.as_deref(), self.new_mode.as_deref());
+            patch += &format!("old mode {}\nnew mode {}\n", old.unwrap(), new.unwrap());
+        }
+        patch += &format!("--- a/{path}\n+++ b/{path}\n");
+
+        let mut offset: isize = 0;
+        for (i, hunk) in self.hunks.iter().enumerate() {
+            if !hunks.contains(&i) {
+                continue;
+            }
+            let new_start = (hunk.old_start as isize + offset).max(0) as usize;
+            offset += hunk.new_len as isize - hunk.old_len as isize;
+            patch += &format!(
+                "@@ -{},{} +{},{} @@\n",
+                hunk.old_start, hunk.old_len, new_start, hunk.new_len
+            );
+            for line in hunk.lines.iter() {
+                let sign = match line.kind {
+                    LineKind::Context => ' ',
+                    LineKind::Added => '+',
+                    LineKind::Removed => '-',
+                };
+                patch += &format!("{sign}{}\n", line.content);
+                if line.no_newline {
+                    patch += "\\ No newline at end of file\n";
+                }
+            }
+        }
+        patch
+    }
+}
+
+/// Parses the output of `git diff` (without color, with `core.quotePath=false`).
+pub fn parse(text: &str) -> Vec<FileDiff> {
+    let mut files: Vec<FileDiff> = vec![];
+    let mut lines = text.split('\n').peekable();
+    let mut old_remaining: usize = 0;
+    let mut new_remaining: usize = 0;
+    let mut old_no = 0;
+    let mut new_no = 0;
+
+    while let Some(line) = lines.next() {
+        if let Some(rest) = line.strip_prefix("diff --git ") {
+            let (a, b) = split_header_paths(rest);
+            files.push(FileDiff::new(a, b));
+            old_remaining = 0;
+            new_remaining = 0;
+            continue;
+        }
+        let Some(file) = files.last_mut() else {
+            continue;
+        };
+
+        // `\ No newline at end of file` can follow the old side in the middle of a hunk, and
+        // is not one of its lines.
+        if line.starts_with('\\') {
+            if let Some(last) = file.hunks.last_mut().and_then(|h| h.lines.last_mut()) {
+                last.no_newline = true;
+            }
+            continue;
+        }
+        if old_remaining > 0 || new_remaining > 0 {
+            let hunk = file.hunks.last_mut().unwrap();
+            let (kind, content) = match line.chars().next() {
+                Some('+') => (LineKind::Added, &line[1..]),
+                Some('-') => (LineKind::Removed, &line[1..]),
+                Some(' ') => (LineKind::Context, &line[1..]),
+                // Some tools strip the space off empty context lines.
+                None if lines.peek().is_some() => (LineKind::Context, ""),
+                _ => {
+                    old_remaining = 0;
+                    new_remaining = 0;
+                    continue;
+                }
+            };
+            let (old, new) = match kind {
+                LineKind::Context => {
+                    old_no += 1;
+                    new_no += 1;
+                    old_remaining = old_remaining.saturating_sub(1);
+                    new_remaining = new_remaining.saturating_sub(1);
+                    (Some(old_no - 1), Some(new_no - 1))
+                }
+                LineKind::Removed => {
+                    old_no += 1;
+                    old_remaining = old_remaining.saturating_sub(1);
+                    (Some(old_no - 1), None)
+                }
+                LineKind::Added => {
+                    new_no += 1;
+                    new_remaining = new_remaining.saturating_sub(1);
+                    (None, Some(new_no - 1))
+                }
+            };
+            hunk.lines.push(DiffLine {
+                kind,
+                content: content.to_string(),
+                old_no: old,
+                new_no: new,
+                no_newline: false,
+            });
+            continue;
+        }
+
+        if line.starts_with("@@ ") {
+            if let Some(hunk) = parse_hunk_header(line) {
+                old_no = hunk.old_start.max(1);
+                new_no = hunk.new_start.max(1);
+                old_remaining = hunk.old_len;
+                new_remaining = hunk.new_len;
+                file.hunks.push(hunk);
+            }
+        } else if let Some(mode) = line.strip_prefix("new file mode ") {
+            file.status = FileStatus::Added;
+            file.old_path = None;
+            file.new_mode = Some(mode.to_string());
+        } else if let Some(mode) = line.strip_prefix("deleted file mode ") {
+            file.status = FileStatus::Deleted;
+            file.new_path = None;
+            file.old_mode = Some(mode.to_string());
+        } else if let Some(mode) = line.strip_prefix("old mode ") {
+            file.old_mode = Some(mode.to_string());
+        } else if let Some(mode) = line.strip_prefix("new mode ") {
+            file.new_mode = Some(mode.to_string());
+        } else if let Some(p) = line.strip_prefix("similarity index ") {
+            file.similarity = p.trim_end_matches('%').parse().ok();
+        } else if let Some(p) = line.strip_prefix("rename from ") {
+            file.status = FileStatus::Renamed;
+            file.old_path = Some(unquote(p));
+        } else if let Some(p) = line.strip_prefix("rename to ") {
+            file.new_path = Some(unquote(p));
+        } else if let Some(p) = line.strip_prefix("copy from ") {
+            file.status = FileStatus::Copied;
+            file.old_path = Some(unquote(p));
+        } else if let Some(p) = line.strip_prefix("copy to ") {
+            file.new_path = Some(unquote(p));
+        } else if let Some(rest) = line.strip_prefix("index ") {
+            // `index abc..def 100644` carries the mode when it did not change.
+            if let Some(mode) = rest.split(' ').nth(1) {
+                if file.old_mode.is_none() && file.status == FileStatus::Modified {
+                    file.old_mode = Some(mode.to_string());
+                    file.new_mode = Some(mode.to_string());
+                }
+            }
+        } else if line.starts_with("Binary files ") || line == "GIT binary patch" {
+            file.binary = true;
+        } else if let Some(p) = line.strip_prefix("--- ") {
+            if p != "/dev/null" {
+                file.old_path = Some(strip_prefix(&unquote(p), "a/"));
+            }
+        } else if let Some(p) = line.strip_prefix("+++ ") {
+            if p != "/dev/null" {
+                file.new_path = Some(strip_prefix(&unquote(p), "b/"));
+            }
+        }
+    }
+    files
+}
+
+fn parse_hunk_header(line: &str) -> Option<Hunk> {
+    let rest = line.strip_prefix("@@ ")?;
+    let end = rest.find(" @@")?;
+    let ranges = &rest[..end];
+    let section = rest[end + 3..].trim_start().to_string();
+    let mut parts = ranges.split(' ');
+    let (old_start, old_len) = parse_range(parts.next()?.strip_prefix('-')?)?;
+    let (new_start, new_len) = parse_range(parts.next()?.strip_prefix('+')?)?;
+    Some(Hunk {
+        old_start,
+        old_len,
+        new_start,
+        new_len,
+        section,
+        lines: vec![],
+    })
+}
+
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+/// Splits `a/x b/y` from a `diff --git` header. Ambiguous for paths containing ` b/`, which
+/// is why the `---`/`+++` and rename lines take precedence when present.
+fn split_header_paths(rest: &str) -> (Option<String>, Option<String>) {
+    let rest = rest.trim();
+    if let Some(quoted) = rest.strip_prefix('"') {
+        if let Some(end) = quoted.find("\" ") {
+            let a = unquote(&rest[..end + 2]);
+            let b = unquote(rest[end + 3..].trim());
+            return (Some(strip_prefix(&a, "a/")), Some(strip_prefix(&b, "b/")));
+        }
+    }
+    match rest.find(" b/") {
+        Some(i) => (
+            Some(strip_prefix(&rest[..i], "a/")),
+            Some(strip_prefix(&unquote(&rest[i + 1..]), "b/")),
+        ),
+        None => (None, None),
+    }
+}
+
+fn strip_prefix(path: &str, prefix: &str) -> String {
+    path.strip_prefix(prefix).unwrap_or(path).to_string()
+}
+
+/// Undoes git's C-style quoting of paths with special characters.
+fn unquote(path: &str) -> String {
+    let path = path.trim_end_matches('\t');
+    let Some(inner) = path.strip_prefix('"').and_then(|p| p.strip_suffix('"')) else {
+        return path.to_string();
+    };
+    let mut bytes = vec![];
+    let mut chars = inner.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some(d @ '0'..='7') => {
+                let mut value = d.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    if let Some(d) = chars.peek().and_then(|d| d.to_digit(8)) {
+                        value = value * 8 + d;
+                        chars.next();
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            Some(c) => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            None => {}
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
@@ -0,0 +1,239 @@
+use crate::paths::trim_slash;
+use crate::tracking::TrackingList;
+use std::fs;
+use std::time::SystemTime;
+
+/// Folders with more files than this are too big to be configuration.
+const MAX_FILES: usize = 1000;
+/// Folders larger than this, in bytes, are too big to be configuration.
+const MAX_SIZE: u64 = 20 * 1024 * 1024;
+
+/// Folders in HOME that hold caches, toolchains or application data rather than configuration.
+const SKIPPED: [&str; 22] = [
+    ".cache",
+    ".local",
+    ".config",
+    ".var",
+    ".npm",
+    ".cargo",
+    ".rustup",
+    ".gradle",
+    ".m2",
+    ".nvm",
+    ".pyenv",
+    ".mozilla",
+    ".thunderbird",
+    ".steam",
+    ".wine",
+    ".dotnet",
+    ".nuget",
+    ".vscode",
+    ".Trash",
+    ".git",
+    ".pki",
+    ".java",
+];
+
+/// Files that are state or secrets rather than configuration.
+const STATE: [&str; 6] = [
+    ".Xauthority",
+    ".ICEauthority",
+    ".xsession-errors",
+    ".sudo_as_admin_successful",
+    ".viminfo",
+    ".DS_Store",
+];
+
+/// The application a candidate belongs to, used to group the picker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Group {
+    Shell,
+    Editor,
+    WindowManager,
+    Terminal,
+    Git,
+    Other,
+}
+
+impl Group {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Group::Shell => "shell",
+            Group::Editor => "editor",
+            Group::WindowManager => "wm",
+            Group::Terminal => "terminal",
+            Group::Git => "git",
+            Group::Other => "other",
+        }
+    }
+
+    /// Guesses the group from a file or folder name, with or without its leading dot.
+    fn of(name: &str) -> Group {
+        let name = name.trim_start_matches('.').to_lowercase();
+        let groups: [(Group, &[&str]); 5] = [
+            (
+                Group::Shell,
+                &[
+                    "bash",
+                    "zsh",
+                    "zprofile",
+                    "zlogin",
+                    "profile",
+                    "inputrc",
+                    "fish",
+                    "starship",
+                    "oh-my-zsh",
+                    "aliases",
+                ],
+            ),
+            (
+                Group::Editor,
+                &[
+                    "vim", "nvim", "emacs", "doom", "helix", "micro", "nano", "kak", "zed",
+                    "sublime",
+                ],
+            ),
+            (
+                Group::WindowManager,
+                &[
+                    "i3",
+                    "sway",
+                    "hypr",
+                    "bspwm",
+                    "sxhkd",
+                    "awesome",
+                    "openbox",
+                    "qtile",
+                    "xinit",
+                    "xresources",
+                    "xprofile",
+                    "polybar",
+                    "waybar",
+                    "picom",
+                    "rofi",
+                    "dunst",
+                    "wofi",
+                ],
+            ),
+            (
+                Group::Terminal,
+                &[
+                    "alacritty",
+                    "kitty",
+                    "wezterm",
+                    "foot",
+                    "tmux",
+                    "terminator",
+                    "ghostty",
+                    "urxvt",
+                ],
+            ),
+            (Group::Git, &["git", "lazygit", "gh", "tig", "delta"]),
+        ];
+        groups
+            .iter()
+            .find(|(_, names)| names.iter().any(|n| name.starts_with(n)))
+            .map_or(Group::Other, |(group, _)| *group)
+    }
+}
+
+/// A file or folder that looks like configuration worth tracking.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    /// Absolute path.
+    pub path: String,
+    pub group: Group,
+    pub is_dir: bool,
+    /// Total size of the files, in bytes.
+    pub size: u64,
+    pub files: usize,
+    /// The latest modification of any of its files.
+    pub modified: Option<SystemTime>,
+    /// The path or a folder above it is in the tracking list.
+    pub tracked: bool,
+}
+
+/// Scans the dotfiles in `home` and the folders in `~/.config` for likely configuration,
+/// leaving out caches, state files and folders too big to be configuration. Candidates are
+/// sorted by group, then path.
+pub fn discover(home: &str, list: &TrackingList) -> Vec<Candidate> {
+    let home = trim_slash(home);
+    let config = format!("{home}/.config");
+    let mut candidates = vec![];
+    for (dir, dotted) in [(home, true), (config.as_str(), false)] {
+        let Ok(read) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in read.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if (dotted && !name.starts_with('.')) || is_skipped(&name) {
+                continue;
+            }
+            let path = format!("{dir}/{name}");
+            let Some(mut candidate) = measure(&path) else {
+                continue;
+            };
+            candidate.group = Group::of(&name);
+            candidate.tracked = list.entry_for(&path).is_some();
+            candidates.push(candidate);
+        }
+    }
+    candidates.sort_by(|a, b| (a.group, &a.path).cmp(&(b.group, &b.path)));
+    candidates
+}
+
+fn is_skipped(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    SKIPPED.contains(&name)
+        || STATE.contains(&name)
+        || lower.contains("cache")
+        || lower.ends_with("history")
+        || lower.ends_with("hst")
+        || lower.ends_with("hsts")
+        || lower.ends_with(".lock")
+}
+
+/// Sizes up a file or folder, or `None` if it is unreadable, empty or too big.
+fn measure(path: &str) -> Option<Candidate> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    let mut candidate = Candidate {
+        path: path.to_string(),
+        group: Group::Other,
+        is_dir: metadata.is_dir(),
+        size: 0,
+        files: 0,
+        modified: None,
+        tracked: false,
+    };
+    if !add_up(path, &mut candidate) || candidate.files == 0 {
+        return None;
+    }
+    Some(candidate)
+}
+
+/// Adds the files below `path` to `candidate`, returning false once it grows too big.
+fn add_up(path: &str, candidate: &mut Candidate) -> bool {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return true;
+    };
+    if metadata.is_dir() {
+        let Ok(read) = fs::read_dir(path) else {
+            return true;
+        };
+        for entry in read.flatten() {
+            if !add_up(&entry.path().to_string_lossy(), candidate) {
+                return false;
+            }
+        }
+        return true;
+    }
+    if !metadata.is_file() && !metadata.is_symlink() {
+        return true;
+    }
+    candidate.files += 1;
+    candidate.size += metadata.len();
+    if let Ok(modified) = metadata.modified() {
+        candidate.modified = candidate.modified.max(Some(modified));
+    }
+    candidate.files <= MAX_FILES && candidate.size <= MAX_SIZE
+}
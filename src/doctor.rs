@@ -0,0 +1,402 @@
+use crate::error::{Error, Result};
+use crate::farm::LinkState;
+use crate::modes::MODES_FILE;
+use crate::paths;
+use crate::repo::DotRepo;
+use crate::tracking::TrackingList;
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// Files dm keeps in the repository for itself, which no entry needs to cover.
+const OWN_FILES: [&str; 2] = [".github/README.md", MODES_FILE];
+
+/// Folders whose files are secrets, wherever they are below HOME.
+const SENSITIVE_DIRS: [&str; 5] = [".ssh", ".gnupg", ".aws", ".kube", ".password-store"];
+
+/// Parts of file names that mark secrets.
+const SENSITIVE_NAMES: [&str; 4] = [".netrc", "credentials", "secret", "token"];
+
+/// Extensions of key files.
+const KEY_EXTENSIONS: [&str; 3] = [".pem", ".key", ".p12"];
+
+/// A problem found by [`DotRepo::doctor`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    /// The area that was checked, e.g. `remote` or `index`.
+    pub check: &'static str,
+    pub message: String,
+    /// What to do about it, in words.
+    pub suggestion: String,
+    /// What `--fix` does about it, if it can.
+    pub repair: Option<Repair>,
+}
+
+/// An automatic fix for a [`Problem`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Repair {
+    CreateList,
+    /// Hides files outside the tracking list from `git status`.
+    HideUntracked,
+    /// Sets the upstream of the current branch to `origin/<branch>`.
+    SetUpstream(String),
+    /// Drops an entry from the tracking list, its last occurrence if it is listed twice.
+    DropEntry(String),
+    /// Stages the files of an entry git does not know yet.
+    StageEntry(String),
+    /// Removes HOME-relative files no entry covers from the index, keeping them in HOME.
+    Unstage(Vec<String>),
+    /// Sets the permission bits of a file.
+    Chmod(String, u32),
+    /// Points the repository at the checkout of symlink-farm mode and the checkout back at it.
+    SetUpCheckout,
+    /// Creates the missing symlinks into the checkout and fixes those pointing elsewhere.
+    PlaceLinks,
+}
+
+impl DotRepo {
+    /// Checks the data directory, the bare repository's configuration, the remote and its
+    /// upstream branch, whether the tracking list and the index agree, the modes entries
+    /// require and, in symlink-farm mode, the links into the checkout. Nothing is changed; see [`DotRepo::repair`].
+    pub fn doctor(&self) -> Result<Vec<Problem>> {
+        let mut problems = vec![];
+        if !Path::new(self.data_dir()).is_dir() || !self.is_initialized() {
+            problems.push(Problem {
+                check: "data",
+                message: format!("no dotfile repository in '{}'", self.data_dir()),
+                suggestion: "run 'dm --init <url>' or 'dm --clone <url>'".to_string(),
+                repair: None,
+            });
+            return Ok(problems);
+        }
+        if !Path::new(self.list_file()).is_file() {
+            problems.push(Problem {
+                check: "data",
+                message: format!("the tracking list '{}' is missing", self.list_file()),
+                suggestion: "create an empty tracking list".to_string(),
+                repair: Some(Repair::CreateList),
+            });
+        }
+        self.check_config(&mut problems)?;
+        self.check_remote(&mut problems)?;
+        self.check_list(&mut problems)?;
+        if self.is_farm() {
+            self.check_links(&mut problems)?;
+        }
+        self.check_permissions(&mut problems)?;
+        if !on_path("less") {
+            problems.push(Problem {
+                check: "system",
+                message: "'less' was not found on PATH".to_string(),
+                suggestion: "install less, dm pages diffs and logs through it".to_string(),
+                repair: None,
+            });
+        }
+        Ok(problems)
+    }
+
+    /// Applies the fix of a problem found by [`DotRepo::doctor`].
+    pub fn repair(&self, repair: &Repair) -> Result<()> {
+        let git = self.git();
+        match repair {
+            Repair::CreateList => TrackingList::default().save(self.list_file()),
+            Repair::HideUntracked => {
+                git.output(&["config", "--local", "status.showUntrackedFiles", "no"])?;
+                Ok(())
+            }
+            Repair::SetUpstream(branch) => {
+                git.output(&[
+                    "branch",
+                    "--quiet",
+                    &format!("--set-upstream-to=origin/{branch}"),
+                    branch,
+                ])?;
+                Ok(())
+            }
+            Repair::DropEntry(entry) => {
+                let mut list = self.tracking_list()?;
+                if let Some(i) = list.entries.iter().rposition(|e| e.path == *entry) {
+                    list.entries.remove(i);
+                }
+                list.save(self.list_file())
+            }
+            Repair::StageEntry(entry) => self.stage_entry(entry),
+            Repair::Unstage(files) => {
+                let mut args = vec!["rm", "--cached", "-q", "--"];
+                args.extend(files.iter().map(|f| f.as_str()));
+                git.output(&args)?;
+                Ok(())
+            }
+            Repair::Chmod(path, mode) => {
+                fs::set_permissions(path, fs::Permissions::from_mode(*mode))
+                    .map_err(|e| Error::io(format!("could not change the mode of '{path}'"), e))
+            }
+            Repair::SetUpCheckout => self.set_up_checkout(),
+            Repair::PlaceLinks => self.place_links().map(|_| ()),
+        }
+    }
+
+    fn check_config(&self, problems: &mut Vec<Problem>) -> Result<()> {
+        let git = self.git();
+        let bare = git.output_lossy(&["config", "--local", "core.bare"])?;
+        if !self.is_farm() && bare.trim() != "true" {
+            problems.push(Problem {
+                check: "config",
+                message: "the repository is not bare".to_string(),
+                suggestion: "run 'dm --git config --local core.bare true'".to_string(),
+                repair: None,
+            });
+        }
+        // In symlink-farm mode the checkout is a normal work-tree git finds from inside it.
+        let tree = self.work_tree();
+        if self.is_farm() && (bare.trim() != "false" || !Path::new(tree).join(".git").is_file()) {
+            problems.push(Problem {
+                check: "config",
+                message: format!("'{tree}' is not set up as the checkout of the repository"),
+                suggestion: "set up the checkout again".to_string(),
+                repair: Some(Repair::SetUpCheckout),
+            });
+        }
+        let untracked = git.output_lossy(&["config", "--local", "status.showUntrackedFiles"])?;
+        if untracked.trim() != "no" {
+            problems.push(Problem {
+                check: "config",
+                message: "git status lists every untracked file in HOME".to_string(),
+                suggestion: "set status.showUntrackedFiles to 'no'".to_string(),
+                repair: Some(Repair::HideUntracked),
+            });
+        }
+        Ok(())
+    }
+
+    fn check_remote(&self, problems: &mut Vec<Problem>) -> Result<()> {
+        let git = self.git();
+        let url = git.output_lossy(&["config", "--get", "remote.origin.url"])?;
+        if url.trim().is_empty() {
+            problems.push(Problem {
+                check: "remote",
+                message: "there is no 'origin' remote".to_string(),
+                suggestion: "run 'dm --git remote add origin <url>'".to_string(),
+                repair: None,
+            });
+            return Ok(());
+        }
+        if !git.succeeds(&["ls-remote", "--quiet", "origin"])? {
+            problems.push(Problem {
+                check: "remote",
+                message: format!("the remote '{}' cannot be reached", url.trim()),
+                suggestion: "check the url with 'dm --git remote -v' and your network".to_string(),
+                repair: None,
+            });
+        }
+        let branch = git.output_lossy(&["symbolic-ref", "--short", "-q", "HEAD"])?;
+        let branch = branch.trim();
+        if branch.is_empty() || git.succeeds(&["rev-parse", "-q", "--verify", "@{upstream}"])? {
+            return Ok(());
+        }
+        let remote_branch = format!("refs/remotes/origin/{branch}");
+        let fetched = git.succeeds(&["rev-parse", "-q", "--verify", &remote_branch])?;
+        problems.push(Problem {
+            check: "remote",
+            message: format!("the branch '{branch}' has no upstream"),
+            suggestion: match fetched {
+                true => format!("track 'origin/{branch}'"),
+                false => format!("run 'dm --git push -u origin {branch}'"),
+            },
+            repair: fetched.then(|| Repair::SetUpstream(branch.to_string())),
+        });
+        Ok(())
+    }
+
+    fn check_list(&self, problems: &mut Vec<Problem>) -> Result<()> {
+        let list = self.tracking_list()?;
+        let paths = list.paths();
+        // Empty folders are tracked through the recorded modes, not by git.
+        let dirs = self.modes_in(self.git(), None)?.dirs;
+        let mut seen: Vec<&str> = vec![];
+        for entry in paths.iter() {
+            let shown = self.shown(entry);
+            if let Some(outer) = seen.iter().find(|s| paths::is_within(entry, s)) {
+                problems.push(Problem {
+                    check: "list",
+                    message: match *outer == entry {
+                        true => format!("'{shown}' is listed twice"),
+                        false => format!("'{shown}' is already covered by '{}'", self.shown(outer)),
+                    },
+                    suggestion: "drop the duplicate entry".to_string(),
+                    repair: Some(Repair::DropEntry(entry.clone())),
+                });
+                continue;
+            }
+            seen.push(entry);
+            let indexed = self
+                .git()
+                .output(&["ls-files", "--", &self.pathspec(entry)])?;
+            if fs::symlink_metadata(self.source_path(entry)?).is_err() {
+                let (place, argument) = match self.is_system(entry) {
+                    true => ("", entry.clone()),
+                    false => (" in HOME", format!("~/{}", self.home_relative(entry))),
+                };
+                problems.push(Problem {
+                    check: "list",
+                    message: format!("'{shown}' no longer exists{place}"),
+                    suggestion: format!(
+                        "bring it back with 'dm --restore {argument}' or drop the entry"
+                    ),
+                    repair: Some(Repair::DropEntry(entry.clone())),
+                });
+            } else if indexed.is_empty()
+                && !dirs
+                    .iter()
+                    .any(|d| paths::is_within(&d.path, &self.repo_path(entry)))
+            {
+                problems.push(Problem {
+                    check: "index",
+                    message: format!("'{shown}' is listed but git does not track it"),
+                    suggestion: "stage the entry".to_string(),
+                    repair: Some(Repair::StageEntry(entry.clone())),
+                });
+            }
+        }
+
+        let indexed = self.git().output(&["ls-files", "--full-name", "-z"])?;
+        let orphans: Vec<String> = indexed
+            .split('\0')
+            .filter(|f| !f.is_empty() && !OWN_FILES.contains(f))
+            .filter(|f| list.entry_for(&self.live_path(f)).is_none())
+            .map(|f| f.to_string())
+            .collect();
+        if !orphans.is_empty() {
+            let shown: Vec<String> = orphans.iter().take(5).map(|f| format!("/{f}")).collect();
+            problems.push(Problem {
+                check: "index",
+                message: format!(
+                    "{} file(s) in the repository are not covered by any entry: {}{}",
+                    orphans.len(),
+                    shown.join(", "),
+                    if orphans.len() > shown.len() {
+                        ", …"
+                    } else {
+                        ""
+                    }
+                ),
+                suggestion: "add their entries again or remove them from the repository"
+                    .to_string(),
+                repair: Some(Repair::Unstage(orphans)),
+            });
+        }
+        Ok(())
+    }
+
+    fn check_links(&self, problems: &mut Vec<Problem>) -> Result<()> {
+        let tree = self.work_tree();
+        for link in self.links()? {
+            let shown = self.shown(&link.path);
+            let (message, repair) = match link.state {
+                LinkState::Linked => continue,
+                LinkState::Missing => (format!("'{shown}' is not linked into '{tree}'"), true),
+                LinkState::Misdirected(target) => (
+                    format!("'{shown}' links to '{target}' instead of '{tree}'"),
+                    true,
+                ),
+                LinkState::Conflict => (
+                    format!("'{shown}' is a real file or folder where a link into '{tree}' goes"),
+                    false,
+                ),
+            };
+            problems.push(Problem {
+                check: "links",
+                message,
+                suggestion: match repair {
+                    true => "link it into the checkout".to_string(),
+                    false => format!(
+                        "compare it with '{}', then move it aside and run 'dm --doctor --fix'",
+                        link.target
+                    ),
+                },
+                repair: repair.then_some(Repair::PlaceLinks),
+            });
+        }
+        Ok(())
+    }
+
+    /// An entry as the problems show it: `/`-prefixed HOME-relative, or absolute for system
+    /// entries.
+    fn shown(&self, entry: &str) -> String {
+        match self.is_system(entry) {
+            true => entry.to_string(),
+            false => format!("/{}", self.home_relative(entry)),
+        }
+    }
+
+    fn check_permissions(&self, problems: &mut Vec<Problem>) -> Result<()> {
+        // Entries that require a mode get it, secret files a private one.
+        let mut required = vec![];
+        for entry in self.tracking_list()?.entries {
+            let Some(mode) = entry.required_mode() else {
+                continue;
+            };
+            for file in self.indexed(self.git(), &self.repo_path(&entry.path))? {
+                let path = self.live_path(&file);
+                let Ok(metadata) = fs::metadata(&path) else {
+                    continue;
+                };
+                let current = metadata.permissions().mode() & 0o7777;
+                if current != mode {
+                    problems.push(Problem {
+                        check: "permissions",
+                        message: format!(
+                            "'{}' has mode {current:04o}, its entry requires {mode:04o}",
+                            self.shown(&path)
+                        ),
+                        suggestion: format!("chmod {mode:04o} {path}"),
+                        repair: Some(Repair::Chmod(path, mode)),
+                    });
+                }
+                required.push(file);
+            }
+        }
+        let indexed = self.git().output(&["ls-files", "--full-name", "-z"])?;
+        for file in indexed
+            .split('\0')
+            .filter(|f| is_sensitive(f) && !required.iter().any(|r| r == f))
+        {
+            let path = self.live_path(file);
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let mode = metadata.permissions().mode() & 0o7777;
+            if mode & 0o077 == 0 {
+                continue;
+            }
+            problems.push(Problem {
+                check: "permissions",
+                message: format!("'/{file}' looks secret but is readable by others ({mode:04o})"),
+                suggestion: format!("chmod {:04o} ~/{file}", mode & 0o7700),
+                repair: Some(Repair::Chmod(path, mode & 0o7700)),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn is_sensitive(file: &str) -> bool {
+    let parts: Vec<&str> = file.split('/').collect();
+    let name = parts.last().unwrap_or(&"").to_lowercase();
+    !file.is_empty()
+        && (parts.iter().any(|p| SENSITIVE_DIRS.contains(p))
+            || SENSITIVE_NAMES.iter().any(|s| name.contains(s))
+            || KEY_EXTENSIONS.iter().any(|e| name.ends_with(e))
+            || name.starts_with("id_"))
+        && !name.ends_with(".pub")
+}
+
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH").is_some_and(|path| {
+        env::split_paths(&path).any(|dir| {
+            fs::metadata(dir.join(program))
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+    })
+}
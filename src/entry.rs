@@ -0,0 +1,95 @@
+use crate::log::LogEntry;
+
+/// How a tracking entry compares to the last commit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryState {
+    Clean,
+    Modified,
+    /// The path no longer exists in HOME.
+    Missing,
+}
+
+impl EntryState {
+    pub fn title(&self) -> &'static str {
+        match self {
+            EntryState::Clean => "clean",
+            EntryState::Modified => "modified",
+            EntryState::Missing => "missing",
+        }
+    }
+}
+
+/// A tracking entry as shown by `dm list`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryInfo {
+    /// Absolute path.
+    pub path: String,
+    /// Folder or file. A missing entry is a folder if git tracks files below it.
+    pub is_dir: bool,
+    pub state: EntryState,
+    /// HOME-relative paths of the files git tracks for the entry.
+    pub files: Vec<String>,
+    /// Total size of the live files, in bytes.
+    pub size: u64,
+    /// Short date of the last commit touching the entry.
+    pub last_commit: Option<String>,
+    pub tags: Vec<String>,
+    /// Where the entry points if it is a symlink.
+    pub link: Option<String>,
+    /// The repository keeps the content of the link's target rather than the link.
+    pub follow: bool,
+}
+
+/// Whether dm manages a path, and why not, as `dm which` reports it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Coverage {
+    /// Covered by the given tracking entry.
+    Tracked(String),
+    /// Inside a tracking entry, but ignored by git through `rule`, given as
+    /// `source:line:pattern`.
+    Ignored { entry: String, rule: String },
+    /// No tracking entry covers the path.
+    NotTracked,
+    /// The path lies outside HOME, which is the work-tree.
+    OutsideHome,
+    /// The path is part of dm's own data directory.
+    DataDir,
+}
+
+impl Coverage {
+    pub fn entry(&self) -> Option<&str> {
+        match self {
+            Coverage::Tracked(entry) | Coverage::Ignored { entry, .. } => Some(entry),
+            _ => None,
+        }
+    }
+}
+
+/// A file or folder as shown by `dm info`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathInfo {
+    /// Absolute path.
+    pub path: String,
+    pub coverage: Coverage,
+    /// `None` when git neither tracks the path nor an entry covers it.
+    pub state: Option<EntryState>,
+    pub is_dir: bool,
+    /// Size of the live file, or of the live files git tracks below a folder, in bytes.
+    pub size: u64,
+    /// Permission bits of the live path.
+    pub mode: Option<u32>,
+    /// The mode git records for a file, e.g. `100644`.
+    pub index_mode: Option<String>,
+    /// Number of files git tracks at or below the path.
+    pub files: usize,
+    pub last_commit: Option<LogEntry>,
+    /// The hosts that committed changes to the path, from the `Dm-Host` trailer.
+    pub hosts: Vec<String>,
+    /// The covering entry stores its files encrypted.
+    pub encrypt: bool,
+    /// The covering entry's files are templates rendered per host.
+    pub template: bool,
+    /// The hosts the covering entry has a version for, from its `hosts`; empty when one
+    /// version serves every host.
+    pub variants: Vec<String>,
+}
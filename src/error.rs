@@ -0,0 +1,186 @@
+use std::{fmt, io};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// HOME or the data directory could not be determined.
+    MissingDirectory(&'static str),
+    /// The data directory holds no repository; `init` has not been run.
+    NotInitialized(String),
+    /// `clone` into a data directory that already holds a repository.
+    AlreadyInitialized(String),
+    PathNotFound(String),
+    AlreadyTracked(String),
+    /// The path lives inside an existing tracking entry.
+    NestedEntry {
+        path: String,
+        entry: String,
+    },
+    NotTracked(String),
+    /// `follow` for a path that is not a symlink to a file.
+    NotAFileLink(String),
+    /// The checkout for symlink-farm mode is not an absolute path, or HOME lies within it.
+    InvalidFarm(String),
+    /// A path to track that is the checkout of symlink-farm mode, lies within it or holds it.
+    InFarm(String),
+    /// A file or folder is in the way of a symlink into the checkout, or already in the
+    /// checkout where a file from HOME should go.
+    LinkConflict(String),
+    NoChanges(String),
+    UnknownRevision(String),
+    /// The path does not exist in the given revision.
+    NotInRevision {
+        path: String,
+        rev: String,
+    },
+    UnknownSnapshot(String),
+    /// No tracking entry carries the tag.
+    UnknownTag(String),
+    /// A tag that is empty or holds characters other than letters, digits, `-`, `_` and `.`.
+    InvalidTag(String),
+    /// A snapshot name is taken or not usable as a ref name.
+    InvalidSnapshotName(String),
+    /// The configuration file could not be parsed.
+    Config {
+        file: String,
+        message: String,
+    },
+    /// The tracking list could not be parsed or migrated.
+    InvalidList {
+        file: String,
+        message: String,
+    },
+    /// The journal of operations could not be parsed or written.
+    InvalidJournal {
+        file: String,
+        message: String,
+    },
+    /// The modes recorded in the repository could not be parsed.
+    InvalidModes(String),
+    /// A write through the escalation command, e.g. `sudo install`, failed.
+    Escalation {
+        command: String,
+        stderr: String,
+    },
+    NothingToUndo,
+    /// The last operation cannot be undone safely, for the given reason.
+    CannotUndo(String),
+    /// `checkout --return` without a preceding `checkout --at`.
+    NoCheckout,
+    /// A git invocation exited unsuccessfully.
+    Git {
+        command: String,
+        stderr: String,
+    },
+    Io {
+        context: String,
+        source: io::Error,
+    },
+}
+
+impl Error {
+    pub fn io(context: impl Into<String>, source: io::Error) -> Error {
+        Error::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// The path an error refers to, if any.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Error::PathNotFound(p)
+            | Error::AlreadyTracked(p)
+            | Error::NotTracked(p)
+            | Error::NotAFileLink(p)
+            | Error::InvalidFarm(p)
+            | Error::InFarm(p)
+            | Error::LinkConflict(p)
+            | Error::NoChanges(p)
+            | Error::NotInitialized(p)
+            | Error::AlreadyInitialized(p) => Some(p),
+            Error::NestedEntry { path, .. } | Error::NotInRevision { path, .. } => Some(path),
+            Error::Config { file, .. }
+            | Error::InvalidList { file, .. }
+            | Error::InvalidJournal { file, .. } => Some(file),
+            _ => None,
+        }
+    }
+
+    /// Whether the error merely means there is nothing to do.
+    pub fn is_warning(&self) -> bool {
+        matches!(
+            self,
+            Error::AlreadyTracked(_) | Error::NestedEntry { .. } | Error::NoChanges(_)
+        )
+    }
+
+    /// The message without the path, in the form `print_path_error` expects.
+    pub fn message(&self) -> String {
+        match self {
+            Error::MissingDirectory(d) => format!("Could not find {d}"),
+            Error::NotInitialized(_) => "no dotfile repository found, run 'dm --init <url>'".into(),
+            Error::AlreadyInitialized(_) => "a dotfile repository already exists here".into(),
+            Error::PathNotFound(_) => "did not match any files or folders".into(),
+            Error::AlreadyTracked(_) => "is already in the tracking list".into(),
+            Error::NestedEntry { entry, .. } => format!("entry exists at lower depth: '{entry}'"),
+            Error::NotTracked(_) => {
+                "did not match any files or folders in the tracking list".into()
+            }
+            Error::NotAFileLink(_) => "is not a symlink to a file".into(),
+            Error::InvalidFarm(_) => "must be an absolute path that does not hold HOME".into(),
+            Error::InFarm(_) => "overlaps the checkout HOME links into".into(),
+            Error::LinkConflict(_) => "is in the way, move it aside first".into(),
+            Error::NoChanges(_) => "did not find any changes".into(),
+            Error::UnknownRevision(rev) => format!("unknown revision '{rev}'"),
+            Error::NotInRevision { rev, .. } => format!("does not exist in '{rev}'"),
+            Error::UnknownSnapshot(id) => format!("unknown snapshot '{id}'"),
+            Error::UnknownTag(tag) => format!("no tracking entry is tagged '{tag}'"),
+            Error::InvalidTag(tag) => {
+                format!("invalid tag '{tag}', use letters, digits, '-', '_' and '.'")
+            }
+            Error::InvalidSnapshotName(name) => {
+                format!("'{name}' is taken or not a valid snapshot name")
+            }
+            Error::Config { message, .. } => format!("invalid configuration: {}", message.trim()),
+            Error::InvalidList { message, .. } => {
+                format!("invalid tracking list: {}", message.trim())
+            }
+            Error::InvalidJournal { message, .. } => {
+                format!("invalid journal: {}", message.trim())
+            }
+            Error::InvalidModes(message) => {
+                format!("invalid modes in the repository: {}", message.trim())
+            }
+            Error::Escalation { command, stderr } => {
+                format!("'{command}' failed: {}", stderr.trim())
+            }
+            Error::NothingToUndo => "there is no operation to undo".into(),
+            Error::CannotUndo(reason) => format!("cannot undo: {reason}"),
+            Error::NoCheckout => "there is no rolled back checkout to return from".into(),
+            Error::Git { command, stderr } => {
+                format!("'git {command}' failed: {}", stderr.trim())
+            }
+            Error::Io { context, source } => format!("{context}: {source}"),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path() {
+            Some(p) => write!(f, "'{p}': {}", self.message()),
+            None => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use crate::error::{Error, Result};
+use crate::paths::{self, trim_slash};
+use crate::repo::DotRepo;
+use std::fs;
+use std::os::unix::fs::symlink;
+
+/// Where the checkout HOME links into is kept in the data directory, in symlink-farm mode.
+/// Without it, HOME is the work-tree of the bare repository.
+pub const FARM_FILE: &str = "farm";
+
+/// The checkout HOME links into if the data directory `data` is in symlink-farm mode.
+pub(crate) fn farm_dir(data: &str) -> Option<String> {
+    let dir = fs::read_to_string(format!("{data}/{FARM_FILE}")).ok()?;
+    Some(trim_slash(dir.trim()).to_string()).filter(|d| !d.is_empty())
+}
+
+/// Whether the path of a tracking entry in HOME is the symlink into the checkout it should be.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LinkState {
+    Linked,
+    /// Nothing is at the path.
+    Missing,
+    /// A symlink that points somewhere else, given as it reads.
+    Misdirected(String),
+    /// A real file or folder is in the way.
+    Conflict,
+}
+
+/// The symlink of a tracking entry in symlink-farm mode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    /// Absolute path in HOME.
+    pub path: String,
+    /// Absolute path in the checkout the link should point to.
+    pub target: String,
+    pub state: LinkState,
+}
+
+impl DotRepo {
+    /// Whether HOME holds symlinks into a separate checkout rather than being the work-tree.
+    pub fn is_farm(&self) -> bool {
+        self.work_tree() != self.home()
+    }
+
+    /// Puts a data directory without a repository into symlink-farm mode, with the checkout at
+    /// `dir`, and opens it that way. `init` and `clone` then set the checkout up.
+    pub fn into_farm(self, dir: &str) -> Result<DotRepo> {
+        if self.is_initialized() {
+            return Err(Error::AlreadyInitialized(self.data_dir().to_string()));
+        }
+        let dir = trim_slash(dir);
+        if !dir.starts_with('/') || paths::is_within(self.home(), dir) {
+            return Err(Error::InvalidFarm(dir.to_string()));
+        }
+        fs::create_dir_all(self.data_dir())
+            .map_err(|e| Error::io(format!("could not create '{}'", self.data_dir()), e))?;
+        let file = format!("{}/{FARM_FILE}", self.data_dir());
+        fs::write(&file, format!("{dir}\n"))
+            .map_err(|e| Error::io(format!("could not write '{file}'"), e))?;
+        Ok(DotRepo::with_paths(self.home(), self.data_dir()))
+    }
+
+    /// Makes the checkout a normal git checkout: git run inside it finds the repository
+    /// through a `.git` file, and the repository knows its work-tree.
+    pub(crate) fn set_up_checkout(&self) -> Result<()> {
+        let dir = self.work_tree();
+        fs::create_dir_all(dir).map_err(|e| Error::io(format!("could not create '{dir}'"), e))?;
+        let gitfile = format!("{dir}/.git");
+        fs::write(&gitfile, format!("gitdir: {}\n", self.git_dir()))
+            .map_err(|e| Error::io(format!("could not write '{gitfile}'"), e))?;
+        let git = self.git();
+        git.output(&["config", "--local", "core.bare", "false"])?;
+        git.output(&["config", "--local", "core.worktree", dir])?;
+        Ok(())
+    }
+
+    /// Where the file or folder at the live path `path` really is: the path itself, or in
+    /// symlink-farm mode its copy in the checkout. System entries and followed symlinks always
+    /// live in place.
+    pub(crate) fn source_path(&self, path: &str) -> Result<String> {
+        let path = trim_slash(path);
+        Ok(match self.is_linked(path)? {
+            true => self.tree_path(&self.repo_path(path)),
+            false => path.to_string(),
+        })
+    }
+
+    /// Whether the live path `path` is, or lies in, a symlink into the checkout, which all
+    /// paths in HOME but followed symlinks are in symlink-farm mode.
+    fn is_linked(&self, path: &str) -> Result<bool> {
+        Ok(self.is_farm() && !self.is_system(path) && !self.is_followed(path)?)
+    }
+
+    /// Moves the file or folder at the HOME path `path` into the checkout and links it back.
+    /// A path that already links there is left as it is; one that is in the checkout already
+    /// is a conflict.
+    pub(crate) fn adopt(&self, path: &str) -> Result<()> {
+        let path = trim_slash(path);
+        let target = self.tree_path(&self.repo_path(path));
+        if fs::read_link(path).is_ok_and(|t| t.to_string_lossy() == target) {
+            return Ok(());
+        }
+        if fs::symlink_metadata(&target).is_ok() {
+            return Err(Error::LinkConflict(target));
+        }
+        if let Some((parent, _)) = target.rsplit_once('/') {
+            fs::create_dir_all(parent)
+                .map_err(|e| Error::io(format!("could not create '{parent}'"), e))?;
+        }
+        fs::rename(path, &target)
+            .map_err(|e| Error::io(format!("could not move '{path}' to '{target}'"), e))?;
+        symlink(&target, path).map_err(|e| Error::io(format!("could not link '{path}'"), e))
+    }
+
+    /// Moves the file or folder the HOME path `path` links to in the checkout back in place of
+    /// the link, the reverse of [`DotRepo::adopt`]. Anything else at `path` is left alone.
+    pub(crate) fn release(&self, path: &str) -> Result<()> {
+        let path = trim_slash(path);
+        let target = self.tree_path(&self.repo_path(path));
+        if fs::read_link(path).is_ok_and(|t| t.to_string_lossy() == target) {
+            // A folder cannot be renamed over the link, so the link goes first.
+            fs::remove_file(path)
+                .map_err(|e| Error::io(format!("could not remove '{path}'"), e))?;
+            fs::rename(&target, path)
+                .map_err(|e| Error::io(format!("could not move '{target}' to '{path}'"), e))?;
+        }
+        Ok(())
+    }
+
+    /// The symlinks the tracking entries need in symlink-farm mode, for those whose files are
+    /// in the checkout, and whether each is in place.
+    pub fn links(&self) -> Result<Vec<Link>> {
+        let mut links = vec![];
+        for entry in self.tracking_list()?.entries {
+            if !self.is_linked(&entry.path)? {
+                continue;
+            }
+            let target = self.source_path(&entry.path)?;
+            if fs::symlink_metadata(&target).is_err() {
+                continue;
+            }
+            let state = match fs::read_link(&entry.path) {
+                Ok(t) if t.to_string_lossy() == target => LinkState::Linked,
+                Ok(t) => LinkState::Misdirected(t.to_string_lossy().to_string()),
+                Err(_) if fs::symlink_metadata(&entry.path).is_ok() => LinkState::Conflict,
+                Err(_) => LinkState::Missing,
+            };
+            links.push(Link {
+                path: entry.path,
+                target,
+                state,
+            });
+        }
+        Ok(links)
+    }
+
+    /// Creates the missing symlinks and points the misdirected ones into the checkout.
+    /// Returns the links still not in place, those with a real file in the way.
+    pub fn place_links(&self) -> Result<Vec<Link>> {
+        let mut conflicts = vec![];
+        for link in self.links()? {
+            match link.state {
+                LinkState::Linked => {}
+                LinkState::Conflict => conflicts.push(link),
+                LinkState::Missing | LinkState::Misdirected(_) => {
+                    let path = &link.path;
+                    if matches!(link.state, LinkState::Misdirected(_)) {
+                        fs::remove_file(path)
+                            .map_err(|e| Error::io(format!("could not remove '{path}'"), e))?;
+                    }
+                    if let Some((parent, _)) = path.rsplit_once('/') {
+                        fs::create_dir_all(parent)
+                            .map_err(|e| Error::io(format!("could not create '{parent}'"), e))?;
+                    }
+                    symlink(&link.target, path)
+                        .map_err(|e| Error::io(format!("could not link '{path}'"), e))?;
+                }
+            }
+        }
+        Ok(conflicts)
+    }
+}
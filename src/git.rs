@@ -0,0 +1,126 @@
+use crate::error::{Error, Result};
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+pub const GIT_BIN: &str = "/bin/git";
+
+/// Runs git against the repository with HOME, or the checkout of symlink-farm mode, as its
+/// work-tree.
+#[derive(Clone, Debug)]
+pub struct Git {
+    git_dir: String,
+    work_tree: String,
+    /// Alternative index file, passed as `GIT_INDEX_FILE`.
+    index_file: Option<String>,
+}
+
+impl Git {
+    pub fn new(git_dir: &str, work_tree: &str) -> Git {
+        Git {
+            git_dir: git_dir.to_string(),
+            work_tree: work_tree.to_string(),
+            index_file: None,
+        }
+    }
+
+    /// The same repository, but reading and writing the index at `path` instead of its own.
+    pub fn with_index_file(&self, path: &str) -> Git {
+        Git {
+            index_file: Some(path.to_string()),
+            ..self.clone()
+        }
+    }
+
+    pub fn git_dir(&self) -> &str {
+        &self.git_dir
+    }
+
+    pub fn work_tree(&self) -> &str {
+        &self.work_tree
+    }
+
+    /// A `git` command with `--git-dir`/`--work-tree` already applied.
+    pub fn command(&self) -> Command {
+        let mut cmd = Command::new(GIT_BIN);
+        cmd.arg(format!("--git-dir={}", self.git_dir))
+            .arg(format!("--work-tree={}", self.work_tree));
+        if let Some(index) = &self.index_file {
+            cmd.env("GIT_INDEX_FILE", index);
+        }
+        cmd
+    }
+
+    /// Runs git and returns its stdout, failing on a non-zero exit.
+    pub fn output(&self, args: &[&str]) -> Result<String> {
+        let output = self.raw(args)?;
+        check(args, &output)?;
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Runs git and returns its stdout regardless of the exit status.
+    pub fn output_lossy(&self, args: &[&str]) -> Result<String> {
+        let output = self.raw(args)?;
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Runs git and returns its raw stdout, e.g. a binary blob, failing on a non-zero exit.
+    pub fn bytes(&self, args: &[&str]) -> Result<Vec<u8>> {
+        let output = self.raw(args)?;
+        check(args, &output)?;
+        Ok(output.stdout)
+    }
+
+    /// Runs git and only reports whether it succeeded.
+    pub fn succeeds(&self, args: &[&str]) -> Result<bool> {
+        Ok(self.raw(args)?.status.success())
+    }
+
+    /// Runs git with `input` on its stdin and returns its stdout.
+    pub fn output_with_input(&self, args: &[&str], input: &[u8]) -> Result<String> {
+        let mut child = self
+            .command()
+            .args(args)
+            .current_dir(&self.work_tree)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::io("failed to spawn git", e))?;
+        let mut stdin = child.stdin.take().unwrap();
+        stdin
+            .write_all(input)
+            .map_err(|e| Error::io("could not write to git", e))?;
+        drop(stdin);
+        let output = child
+            .wait_with_output()
+            .map_err(|e| Error::io("failed to execute git", e))?;
+        check(args, &output)?;
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Runs from the work-tree, so paths relative to it mean the same wherever dm is run.
+    fn raw(&self, args: &[&str]) -> Result<Output> {
+        self.command()
+            .args(args)
+            .current_dir(&self.work_tree)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| Error::io("failed to execute git", e))
+    }
+}
+
+fn check(args: &[&str], output: &Output) -> Result<()> {
+    if output.status.success() {
+        return Ok(());
+    }
+    let mut stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    if stderr.trim().is_empty() {
+        stderr = String::from_utf8_lossy(&output.stdout).to_string();
+    }
+    Err(Error::Git {
+        command: args.join(" "),
+        stderr,
+    })
+}
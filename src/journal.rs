@@ -0,0 +1,127 @@
+use crate::error::{Error, Result};
+use crate::tracking::Entry;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{fs, io::Write};
+
+/// How many operations the journal keeps; appending more drops the oldest.
+pub const MAX_OPERATIONS: usize = 100;
+
+/// One dm command as recorded in `$DATA/journal.toml`: the state it started from and what it
+/// changed, so `undo` can put things back.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Operation {
+    /// The command line, without the program name.
+    pub command: String,
+    /// Seconds since the epoch.
+    pub time: u64,
+    #[serde(deserialize_with = "entries")]
+    pub list_before: Vec<Entry>,
+    #[serde(deserialize_with = "entries")]
+    pub list_after: Vec<Entry>,
+    /// Tree of the index before and after.
+    pub index_before: String,
+    pub index_after: String,
+    pub head_before: String,
+    pub head_after: String,
+    /// The command made commits, rather than only moving HEAD as a pull does.
+    pub committed: bool,
+    /// The command pushed HEAD to the remote.
+    pub pushed: bool,
+    /// The automatic snapshot taken before the command changed HOME.
+    pub snapshot: Option<String>,
+    pub undone: bool,
+}
+
+impl Operation {
+    pub fn changed_list(&self) -> bool {
+        self.list_before != self.list_after
+    }
+
+    pub fn changed_index(&self) -> bool {
+        self.index_before != self.index_after
+    }
+
+    pub fn moved_head(&self) -> bool {
+        self.head_before != self.head_after
+    }
+
+    pub fn is_noop(&self) -> bool {
+        !self.changed_list() && !self.changed_index() && !self.moved_head() && !self.pushed
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Journal {
+    #[serde(default)]
+    pub operation: Vec<Operation>,
+}
+
+impl Journal {
+    pub fn load(file: &str) -> Result<Journal> {
+        let read = match fs::read_to_string(file) {
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Journal::default()),
+            Err(e) => return Err(Error::io(format!("could not read '{file}'"), e)),
+        };
+        toml::from_str(&read).map_err(|e| Error::InvalidJournal {
+            file: file.to_string(),
+            message: e.message().to_string(),
+        })
+    }
+
+    pub fn save(&self, file: &str) -> Result<()> {
+        fs::write(file, serialize(self, file)?)
+            .map_err(|e| Error::io(format!("could not write '{file}'"), e))
+    }
+
+    /// Appends `operation` without rewriting the entries before it, unless the journal is
+    /// full: then it is rewritten without its oldest operations.
+    pub fn append(file: &str, operation: Operation) -> Result<()> {
+        let mut journal = Journal::load(file)?;
+        if journal.operation.len() >= MAX_OPERATIONS {
+            journal.operation.push(operation);
+            let excess = journal.operation.len() - MAX_OPERATIONS;
+            journal.operation.drain(..excess);
+            return journal.save(file);
+        }
+        let record = serialize(
+            &Journal {
+                operation: vec![operation],
+            },
+            file,
+        )?;
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file)
+            .and_then(|mut f| writeln!(f, "{record}"))
+            .map_err(|e| Error::io(format!("could not write '{file}'"), e))
+    }
+}
+
+/// Reads tracking list entries, which journals written before the list carried metadata
+/// store as plain paths.
+fn entries<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<Entry>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Recorded {
+        Path(String),
+        Entry(Entry),
+    }
+    Ok(Vec::<Recorded>::deserialize(deserializer)?
+        .into_iter()
+        .map(|r| match r {
+            Recorded::Path(path) => Entry::new(&path),
+            Recorded::Entry(entry) => entry,
+        })
+        .collect())
+}
+
+fn serialize(journal: &Journal, file: &str) -> Result<String> {
+    toml::to_string(journal).map_err(|e| Error::InvalidJournal {
+        file: file.to_string(),
+        message: e.to_string(),
+    })
+}
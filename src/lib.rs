@@ -0,0 +1,42 @@
+//! Dotmanager keeps dotfiles in a bare git repository whose work-tree is HOME.
+//!
+//! [`DotRepo`] is the entry point: it owns the tracking list and runs git against the
+//! repository. Its methods return data instead of printing, so the `dm` binary is only one
+//! of the possible front ends.
+
+pub mod config;
+pub mod diff;
+mod discover;
+mod doctor;
+mod entry;
+mod error;
+mod farm;
+mod git;
+mod journal;
+mod links;
+mod log;
+mod modes;
+pub mod paths;
+pub mod render;
+mod repo;
+mod snapshot;
+mod status;
+mod system;
+mod tracking;
+
+pub use diff::FileDiff;
+pub use discover::{Candidate, Group};
+pub use doctor::{Problem, Repair};
+pub use entry::{Coverage, EntryInfo, EntryState, PathInfo};
+pub use error::{Error, Result};
+pub use farm::{Link, LinkState, FARM_FILE};
+pub use git::Git;
+pub use journal::{Operation, MAX_OPERATIONS};
+pub use log::LogEntry;
+pub use modes::{FileMode, ModeDrift, Modes, MODES_FILE};
+pub use render::{Layout, Renderer};
+pub use repo::{Checkout, DiffTarget, DotRepo, Pick, Restored, Undone};
+pub use snapshot::Snapshot;
+pub use status::{Change, LinkChange, Status, StatusEntry};
+pub use system::SYSTEM_PREFIX;
+pub use tracking::{Entry, TrackingList, LIST_VERSION};
@@ -0,0 +1,145 @@
+use crate::error::{Error, Result};
+use crate::git::Git;
+use crate::paths;
+use crate::repo::{split_z, DotRepo};
+use crate::status::LinkChange;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+/// The mode git records symlinks with.
+const LINK_MODE: &str = "120000";
+
+impl DotRepo {
+    /// Whether `path` is a symlink entry whose target's content the repository keeps.
+    pub(crate) fn is_followed(&self, path: &str) -> Result<bool> {
+        Ok(self.tracking_list()?.get(path).is_some_and(|e| e.follow))
+    }
+
+    /// Stages the content of the target of the followed symlink `entry` into the index of
+    /// `git`, at the path of the link. The path is skip-worktree and outside the sparse
+    /// checkout, so git never replaces the link with a file. A dangling link keeps what was
+    /// staged before.
+    pub(crate) fn stage_followed(&self, git: &Git, entry: &str) -> Result<()> {
+        self.enable_sparse()?;
+        let Ok(metadata) = fs::metadata(entry) else {
+            return Ok(());
+        };
+        let hash = git.output(&["hash-object", "-w", "--no-filters", "--", entry])?;
+        let mode = match metadata.permissions().mode() & 0o111 != 0 {
+            true => "100755",
+            false => "100644",
+        };
+        let rel = self.repo_path(entry);
+        let cacheinfo = format!("{mode},{},{rel}", hash.trim());
+        git.output(&["update-index", "--add", "--cacheinfo", &cacheinfo])?;
+        git.output(&["update-index", "--skip-worktree", "--", &rel])?;
+        Ok(())
+    }
+
+    /// Sparse checkout patterns that keep the followed symlinks out of git's hands.
+    pub(crate) fn followed_patterns(&self) -> Result<String> {
+        Ok(self
+            .tracking_list()?
+            .followed()
+            .iter()
+            .filter(|e| !self.is_system(&e.path))
+            .map(|e| format!("!/{}\n", escape_pattern(&self.repo_path(&e.path))))
+            .collect())
+    }
+
+    /// Writes the content recorded in `rev` through the followed symlinks at or below the
+    /// repository path `prefix`, or through all of them, where it differs from the live one,
+    /// and updates the index to match. Where the link is gone, a plain file takes its place.
+    /// Returns the paths written.
+    pub(crate) fn write_followed(&self, rev: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+        let git = self.git();
+        let mut written = vec![];
+        for entry in self.tracking_list()?.followed() {
+            let rel = self.repo_path(&entry.path);
+            if self.is_system(&entry.path) || prefix.is_some_and(|p| !paths::is_within(&rel, p)) {
+                continue;
+            }
+            let tree =
+                git.output_lossy(&["ls-tree", "--full-tree", rev, "--", &self.tree_path(&rel)])?;
+            let Some((meta, _)) = tree.trim_end().split_once('\t') else {
+                continue;
+            };
+            let fields: Vec<&str> = meta.split(' ').collect();
+            // A commit from before the entry was followed holds the link itself.
+            if fields[0] == LINK_MODE {
+                continue;
+            }
+            let content = git.bytes(&["cat-file", "blob", fields[2]])?;
+            if fs::read(&entry.path).ok().as_ref() != Some(&content) {
+                if let Some((parent, _)) = entry.path.rsplit_once('/') {
+                    fs::create_dir_all(parent)
+                        .map_err(|e| Error::io(format!("could not create '{parent}'"), e))?;
+                }
+                fs::write(&entry.path, &content)
+                    .map_err(|e| Error::io(format!("could not write '{}'", entry.path), e))?;
+                written.push(entry.path.clone());
+            }
+            let cacheinfo = format!("{},{},{rel}", fields[0], fields[2]);
+            git.output(&["update-index", "--add", "--cacheinfo", &cacheinfo])?;
+            git.output(&["update-index", "--skip-worktree", "--", &rel])?;
+        }
+        Ok(written)
+    }
+
+    /// The staged changes that involve a symlink, keyed by their path in the repository, with
+    /// the targets on either side.
+    pub(crate) fn link_changes(&self) -> Result<Vec<(String, LinkChange)>> {
+        let git = self.git();
+        let raw = split_z(&git.output_lossy(&[
+            "-c",
+            "core.quotePath=false",
+            "diff",
+            "--cached",
+            "--raw",
+            "--no-abbrev",
+            "-M",
+            "-z",
+        ])?);
+        let target = |mode: &str, hash: &str| -> Result<Option<String>> {
+            match mode == LINK_MODE {
+                true => Ok(Some(git.output_lossy(&["cat-file", "blob", hash])?)),
+                false => Ok(None),
+            }
+        };
+        let mut changes = vec![];
+        let mut fields = raw.iter();
+        while let Some(meta) = fields.next() {
+            let meta: Vec<&str> = meta.trim_start_matches(':').split(' ').collect();
+            if meta.len() < 5 {
+                continue;
+            }
+            let mut path = fields.next();
+            if meta[4].starts_with('R') || meta[4].starts_with('C') {
+                path = fields.next();
+            }
+            let Some(path) = path else {
+                break;
+            };
+            if meta[0] == LINK_MODE || meta[1] == LINK_MODE {
+                let change = LinkChange {
+                    from: target(meta[0], meta[2])?,
+                    to: target(meta[1], meta[3])?,
+                };
+                changes.push((path.clone(), change));
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// `path` with the characters that are special in sparse checkout patterns escaped.
+fn escape_pattern(path: &str) -> String {
+    let mut escaped = String::new();
+    for c in path.chars() {
+        if matches!(c, '*' | '?' | '[' | '\\' | '!' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
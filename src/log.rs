@@ -0,0 +1,91 @@
+/// Trailer dm adds to its commits, naming the machine they were made on.
+pub const HOST_TRAILER: &str = "Dm-Host";
+
+/// Format passed to `git log`, parsed by `LogEntry::parse`.
+pub(crate) const FORMAT: &str =
+    "--format=%x1e%H%x1f%h%x1f%ad%x1f%(trailers:key=Dm-Host,valueonly,separator=%x2c)%x1f%s";
+
+/// A commit as shown by `dm log`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    pub hash: String,
+    pub short: String,
+    /// Author date as `YYYY-MM-DD HH:MM`.
+    pub date: String,
+    /// The `Dm-Host` trailer, empty for commits made outside dm.
+    pub host: String,
+    pub subject: String,
+    /// HOME-relative paths the commit touched, under their name at that commit.
+    pub files: Vec<String>,
+}
+
+impl LogEntry {
+    /// Parses `git log` output produced with `FORMAT`, `--name-only` and `-z`.
+    pub(crate) fn parse(output: &str) -> Vec<LogEntry> {
+        records(output)
+            .map(|(mut entry, names)| {
+                entry.files = names.map(|n| n.to_string()).collect();
+                entry
+            })
+            .collect()
+    }
+
+    /// Parses `git log` output produced with `FORMAT`, `--name-status` and `-z`, giving every
+    /// commit with the paths it touched as `(old path, path)` pairs. The two differ only for
+    /// renames and copies; the entry's `files` is left empty.
+    pub(crate) fn parse_changes(output: &str) -> Vec<(LogEntry, Vec<(String, String)>)> {
+        records(output)
+            .map(|(entry, mut fields)| {
+                let mut changes = vec![];
+                while let Some(status) = fields.next() {
+                    let Some(old) = fields.next() else { break };
+                    let new = match status.starts_with(['R', 'C']) {
+                        true => fields.next().unwrap_or(old),
+                        false => old,
+                    };
+                    changes.push((old.to_string(), new.to_string()));
+                }
+                (entry, changes)
+            })
+            .collect()
+    }
+}
+
+/// The commits in `git log` output produced with `FORMAT` and `-z`, each with the fields that
+/// follow its header.
+fn records(output: &str) -> impl Iterator<Item = (LogEntry, impl Iterator<Item = &str>)> {
+    output
+        .split('\u{1e}')
+        .filter(|r| !r.is_empty())
+        .filter_map(|record| {
+            let (header, names) = record.split_once('\0').unwrap_or((record, ""));
+            let fields: Vec<&str> = header.split('\u{1f}').collect();
+            if fields.len() < 5 {
+                return None;
+            }
+            let entry = LogEntry {
+                hash: fields[0].to_string(),
+                short: fields[1].to_string(),
+                date: fields[2].to_string(),
+                host: fields[3].trim().to_string(),
+                subject: fields[4].to_string(),
+                files: vec![],
+            };
+            let names = names
+                .split('\0')
+                .map(|n| n.trim_start_matches('\n'))
+                .filter(|n| !n.is_empty());
+            Some((entry, names))
+        })
+}
+
+/// The name of this machine, for the `Dm-Host` trailer.
+pub(crate) fn hostname() -> String {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .filter_map(|f| std::fs::read_to_string(f).ok())
+        .chain(std::env::var("HOSTNAME").ok())
+        .map(|h| h.trim().to_string())
+        .find(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
@@ -1,60 +1,116 @@
-use color_print::{cformat, cprintln};
-use crossterm::{
-    cursor,
-    terminal::{self, disable_raw_mode, enable_raw_mode},
-    ExecutableCommand, QueueableCommand,
-};
-use dialoguer::{
-    console::{style, Key, Term},
-    theme::ColorfulTheme,
-    Select,
+use color_print::{cformat, cprintln, untagged};
+use crossterm::{cursor, terminal, ExecutableCommand, QueueableCommand};
+use dialoguer::{console::style, theme::ColorfulTheme, Confirm, MultiSelect, Select};
+use dotmanager::{
+    paths, Candidate, Change, Coverage, DiffTarget, DotRepo, EntryInfo, EntryState, Error,
+    FileMode, Layout, Link, LinkChange, LinkState, LogEntry, ModeDrift, Pick, Renderer, Snapshot,
+    Status, StatusEntry,
 };
 use prettytable::{Cell, Row, Table};
 use std::{
-    cmp::max,
     env,
-    fs::{self, metadata},
-    io::{stdout, Write},
+    io::{stdout, IsTerminal, Write},
     process::{exit, Command, Stdio},
 };
 
+mod completions;
+mod tui;
 mod util;
+use completions::Argument;
 use util::functions::{
-    file_to_vec, new_table, print_path_error, read_input, sanitise_args, split_cmd, validate_args,
+    age, extract_global_options, human_size, new_table, parse_options, print_error, read_input,
+    resolve_path, sanitise_args, validate_args, Options,
 };
-use util::user_paths::{GIT, HOME, LIST};
 use util::StatusInfo;
 
+/// Short flags, then long commands, in the format of `validate_args`.
+const VALID_INPUTS: [&str; 2] = [
+    "hsludp;i:a;r:",
+    "help, status, status-summary, list, update, pull, diff;, init:, add;, remove:, restore:, log;, checkout, snapshot:, clone:, undo, env, which:, info:, doctor, git;, exec:, completions:, man, complete:",
+];
+
 fn main() {
-    let valid_inputs = vec![
-        "hslud;i:a:r:",
-        "help, status, status-summary, list, update, diff;, init:, add:, remove:",
-    ];
-    handle_input(&valid_inputs);
+    handle_input(&VALID_INPUTS);
 }
 
 fn handle_input(valid_inputs: &[&str]) {
-    let args: &Vec<String> = &env::args().collect();
-    if args.len() <= 1 || args.len() > 3 {
+    let mut args: Vec<String> = env::args().collect();
+    if let Err(e) = extract_global_options(&mut args) {
+        cprintln!("<red,bold>error</><bold>:</> {}", e);
+        exit(2);
+    }
+    let args = &args;
+    if args.len() <= 1 {
         help();
         exit(2);
     }
 
-    let sargs: (String, String) = sanitise_args(args);
-    if !validate_args(&sargs, valid_inputs) {
+    let cmd = sanitise_args(args).0;
+    if matches!(cmd.as_str(), "git" | "exec") {
+        let repo = or_exit(DotRepo::open());
+        passthrough(&repo, &cmd, &args[2..]);
+    }
+    let options = match parse_options(&args[2..], option_spec(&cmd)) {
+        Ok(options) => options,
+        Err(e) => {
+            cprintln!("<red,bold>error</><bold>:</> {}", e);
+            exit(2);
+        }
+    };
+    let sargs = (cmd, options.positional.first().cloned().unwrap_or_default());
+    let many = matches!(sargs.0.as_str(), "d" | "diff" | "snapshot");
+    if !validate_args(&sargs, valid_inputs) || (options.positional.len() > 1 && !many) {
         help();
         exit(2);
     }
 
+    let repo = or_exit(DotRepo::open());
+    // Commands that change the list, index, commits or HOME are journaled for `undo`.
+    let journaled = matches!(
+        sargs.0.as_str(),
+        "u" | "update"
+            | "p"
+            | "pull"
+            | "d"
+            | "diff"
+            | "a"
+            | "add"
+            | "r"
+            | "remove"
+            | "restore"
+            | "checkout"
+            | "snapshot"
+            | "doctor"
+    );
+    let rebuild = matches!(sargs.0.as_str(), "l" | "list") && options.flag("rebuild");
+    let operation = match (journaled || rebuild) && repo.is_initialized() {
+        true => Some(or_exit(repo.begin_operation(&args[1..].join(" ")))),
+        false => None,
+    };
     match sargs.0.as_str() {
-        "u" | "update" => update(),
-        "s" | "status" => status(),
-        "status-summary" => status_summary_short(),
-        "l" | "list" => list(),
-        "d" | "diff" => diff(&sargs.1),
-        "i" | "init" => init(&sargs.1),
-        "a" | "add" => add(&sargs.1),
-        "r" | "remove" => remove(&sargs.1),
+        "u" | "update" => update(&repo, &options),
+        "s" | "status" => status(&repo, &options),
+        "status-summary" => status_summary_short(&repo),
+        "l" | "list" => list(&repo, &options),
+        "p" | "pull" => pull(&repo),
+        "d" | "diff" => diff(&repo, &options),
+        "i" | "init" => init(&repo, &sargs.1, &options),
+        "a" | "add" if sargs.1.is_empty() => add_picked(&repo, &options),
+        "a" | "add" => add(&repo, &resolve_path(repo.home(), &sargs.1), &options),
+        "r" | "remove" => remove(&repo, &resolve_path(repo.home(), &sargs.1)),
+        "doctor" => doctor(&repo, &options),
+        "which" => which(&repo, &resolve_path(repo.home(), &sargs.1)),
+        "info" => info(&repo, &resolve_path(repo.home(), &sargs.1)),
+        "restore" => restore(&repo, &resolve_path(repo.home(), &sargs.1), &options),
+        "log" => log(&repo, &options),
+        "checkout" => checkout(&repo, &options),
+        "snapshot" => snapshot(&repo, &options),
+        "clone" => clone(&repo, &sargs.1, &options),
+        "undo" => undo(&repo, &options),
+        "env" => print_env(&repo),
+        "completions" => print_completions(&sargs.1),
+        "man" => man(),
+        "complete" => complete(&repo, &sargs.1),
         "h" | "help" => {
             help();
             exit(0);
@@ -64,161 +120,982 @@ fn handle_input(valid_inputs: &[&str]) {
             exit(2);
         }
     }
+    if let Some(operation) = operation {
+        or_exit(repo.finish_operation(operation));
+    }
 }
 
-fn help() {
-    cprintln!(
+/// Long options accepted by each command, in the format of `parse_options`.
+fn option_spec(cmd: &str) -> &'static str {
+    match cmd {
+        "d" | "diff" => "side-by-side, word-diff, rev:, remote, between::, tag:",
+        "l" | "list" => "expand, rebuild, tag:",
+        "a" | "add" => "tag:, follow, no-follow",
+        "s" | "status" | "u" | "update" => "tag:",
+        "restore" => "rev:",
+        "log" => "interactive, limit:",
+        "checkout" => "at:, return",
+        "snapshot" => "keep:",
+        "undo" => "force",
+        "doctor" => "fix",
+        "i" | "init" | "clone" => "farm, farm-dir:",
+        _ => "",
+    }
+}
+
+/// The tracking entries a `--tag` option narrows a command to, or `None` without one.
+fn tag_scope(repo: &DotRepo, options: &Options) -> Option<Vec<String>> {
+    options.value("tag").map(|tag| or_exit(repo.tagged(tag)))
+}
+
+/// Whether the HOME-relative `path` lies in one of the `scope` entries, or there is no scope.
+fn in_scope(repo: &DotRepo, scope: Option<&[String]>, path: &str) -> bool {
+    scope.is_none_or(|entries| {
+        entries
+            .iter()
+            .any(|e| paths::is_within(&repo.live_path(path), e))
+    })
+}
+
+/// Builds the diff renderer from the command line and the terminal dotmanager writes to.
+fn renderer(options: &Options) -> Renderer {
+    let tty = stdout().is_terminal();
+    Renderer {
+        layout: if options.flag("side-by-side") {
+            Layout::SideBySide
+        } else {
+            Layout::Unified
+        },
+        word_diff: options.flag("word-diff"),
+        color: tty && env::var_os("NO_COLOR").is_none(),
+        width: terminal::size().map(|(w, _)| w as usize).unwrap_or(120),
+    }
+}
+
+/// Unwraps a library result, printing the error and exiting otherwise.
+fn or_exit<T>(result: dotmanager::Result<T>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            print_error(&err);
+            exit(if matches!(err, Error::NoChanges(_)) {
+                0
+            } else {
+                2
+            });
+        }
+    }
+}
+
+/// Expands `$m!` on the help text, so `help()` and the man page share it.
+macro_rules! with_help {
+    ($m:ident) => {
+        $m!(
 "
 <bold>Dotmanager</> is a utility that creates and maintains a bare git repository to manage dotfiles.
 
-<green,bold>Usage</>: <cyan><bold>dm</bold> [option] (argument)</>
+<green,bold>Usage</>: <cyan><bold>dm</bold> [global options] [option] (argument)</>
+
+<green,bold>Global options</>:
+<cyan><bold>  --home</bold> <<path>></>         Uses <<path>> as the work-tree instead of $HOME. Also read from $DM_HOME.
+<cyan><bold>  --data-dir</bold> <<path>></>     Uses <<path>> instead of $XDG_DATA_HOME/dotmanager. Also read from $DM_DATA_DIR.
 
 <green,bold>Options</>:
 <cyan,bold>  -h</>, <cyan,bold>--help</>           Displays the help message.
-<cyan,bold>  -s</>, <cyan,bold>--status</>         Displays the status of the dotfile repository.
-<cyan,bold>  -l</>, <cyan,bold>--list</>           Displays the tracking list.
+<cyan,bold>  -s</>, <cyan,bold>--status</>         Displays the status of the dotfile repository, or with <cyan><bold>--tag</bold> <<tag>></> of the entries tagged <<tag>>.
+                      Files whose mode changed since the last commit are listed under Permissions, symlinks show
+                      where they point now and before.
+<cyan,bold>  -l</>, <cyan,bold>--list</>           Displays the tracking list as a tree, with the type, tracked files, size, last commit and state
+                      (clean, modified or missing) of each entry. <cyan,bold>--expand</> lists the files tracked in folders.
+                      <cyan,bold>--rebuild</> reconstructs the list from the files in the repository, collapsing fully tracked
+                      folders, and writes it after showing how it differs. Tagged entries are grouped by tag,
+                      <cyan><bold>--tag</bold> <<tag>></> shows one group only.
 <cyan,bold>  -i</>, <cyan><bold>--init</bold> <<url>></>     Initializes a bare git repository under $XDG_DATA_HOME/dotmanager and does an initial commit and push to the remote-url.
-<cyan,bold>  -u</>, <cyan,bold>--update</>         Stages all changes of folders and files in the tracking list, then prompts the user for commit & push.
-<cyan,bold>  -a</>, <cyan><bold>--add</bold> <<path>></>     Adds a file or folder to the tracking list and stages the change.
+                      <cyan,bold>--farm</> keeps the files in a normal checkout at ~/dotfiles instead, with symlinks in HOME
+                      pointing into it, <cyan><bold>--farm-dir</bold> <<path>></> puts the checkout elsewhere. Added files move into the
+                      checkout and are linked back; <cyan,bold>--doctor</> reports links that are missing or have a real file in the way.
+<cyan,bold>  -u</>, <cyan,bold>--update</>         Stages all changes of folders and files in the tracking list, then prompts the user to commit & push, review the changes first, or choose the files and hunks to commit.
+                      <cyan><bold>--tag</bold> <<tag>></> commits only the changes of the entries tagged <<tag>>.
+<cyan,bold>  -p</>, <cyan,bold>--pull</>           Pulls the latest changes from the remote into the work-tree and gives the files their recorded mode.
+<cyan,bold>  -a</>, <cyan><bold>--add</bold> (<<path>>)</>   Adds a file or folder to the tracking list and stages the change. Without a path, offers the
+                      dotfiles found in HOME and ~/.config, grouped by application, to pick from.
+                      <cyan><bold>--tag</bold> <<tags>></> tags the entry, e.g. <cyan>--tag wm,desktop</>, also if it is tracked already.
+                      Paths outside HOME, e.g. /etc/pacman.conf, become system entries, kept under .dm/system in the
+                      repository along with their mode and owner. Empty folders are tracked too, and symlinks are
+                      kept as links; <cyan,bold>--follow</> keeps the content of a link's target instead, <cyan,bold>--no-follow</> the
+                      link again.
 <cyan,bold>  -r</>, <cyan><bold>--remove</bold> <<path>></>  Removes a file or folder from the tracking list and stages the change.
-<cyan,bold>  -d</>, <cyan><bold>--diff</bold> (<<file>>)</>  Displays git diff. Comparing the latest commit with the live work-tree. Without an argument, shows a list of all diff files.
+<cyan><bold>      --log</bold> (<<path>>)</>    Lists the commits, or those touching <<path>>, following renames of a file.
+                      <cyan,bold>--interactive</> picks a commit and shows its diff, <cyan><bold>--limit</bold> <<n>></> shows the last <<n>> commits only.
+<cyan><bold>      --checkout</bold> --at <<date|commit>></> Rolls every tracked file back to a commit or to the last commit before a date
+                      (e.g. 2024-05-01 or \"3 days ago\"), after saving the live state as a snapshot.
+<cyan><bold>      --checkout</bold> --return</> Brings back the state saved by the last <cyan,bold>--checkout --at</>.
+<cyan><bold>      --snapshot</bold> <<command>></> Manages snapshots of the live state of all tracked files, kept in $XDG_DATA_HOME/dotmanager.
+                      dm takes one on its own before remove, restore, checkout, pull and clone, keeping the last 20
+                      (see [snapshots] keep and max_age_days in config.toml).
+                      <cyan,bold>create</> (<<name>>), <cyan,bold>list</>, <cyan,bold>restore</> <<id>>, <cyan,bold>delete</> <<id>>, <cyan,bold>prune</> (<cyan><bold>--keep</bold> <<n>></>)
+<cyan><bold>      --clone</bold> <<url>></>   Clones an existing dotfile repository and checks its files out into the work-tree, keeping the
+                      files it replaces in a snapshot. The files get the modes recorded in .dm/modes.toml.
+                      <cyan,bold>--farm</> and <cyan><bold>--farm-dir</bold> <<path>></> check them out as with <cyan,bold>--init</> and link them into HOME,
+                      leaving real files that are in the way alone.
+<cyan><bold>      --undo</bold></>           Reverts the last add, remove, update, restore, checkout, pull or snapshot restore: the tracking list,
+                      the index, its commits and the files it changed. Pushed commits are only undone with <cyan,bold>--force</>,
+                      which rewrites the remote branch.
+<cyan><bold>      --doctor</bold></>         Checks the data directory, the repository's config, the remote and its upstream, that the tracking
+                      list and the index agree, the links into the checkout of <cyan,bold>--farm</>, the permissions of secret files and
+                      that less is installed, suggesting a fix for each problem. <cyan,bold>--fix</> applies the fixes it can.
+<cyan><bold>      --which</bold> <<path>></>  Prints the tracking entry that covers <<path>>, or why dm does not manage it.
+<cyan><bold>      --info</bold> <<path>></>   Shows whether <<path>> is tracked, its state, size and mode, its last commit, the hosts that
+                      committed it, the hosts its entry has versions for and whether it is encrypted or templated.
+<cyan><bold>      --git</bold> <<args>></>     Runs git on the dotfile repository with <<args>> passed through as given.
+<cyan><bold>      --exec</bold> <<cmd>></>     Runs <<cmd>>, e.g. lazygit or an editor, with GIT_DIR and GIT_WORK_TREE pointing at the repository.
+<cyan><bold>      --env</bold></>            Prints GIT_DIR and GIT_WORK_TREE exports for the shell: <cyan>eval \"$(dm --env)\"</>
+<cyan><bold>      --restore</bold> <<path>></> Puts the committed version of a file or tracking entry back into the work-tree, with its recorded
+                      mode, after copying the live files to $XDG_DATA_HOME/dotmanager/backups. <cyan><bold>--rev</bold> <<commit>></> restores from <<commit>> instead.
+                      System entries are written through the command in [system] escalate in config.toml (sudo by
+                      default), with their recorded mode and owner.
+<cyan,bold>  -d</>, <cyan><bold>--diff</bold> (<<paths>>)</> Displays git diff. Comparing the latest commit with the live work-tree. Without an argument, opens a full-screen
+                      review to browse, stage, unstage, discard and commit changes (j/k move, enter page, s/u/x, c commit, p push, / filter, q quit).
+                      Unstaged changes are left out of the commits made there; the next command that stages all
+                      changes, like --status or another --diff, stages them again.
+                      Paths may be files or tracking entries, given as absolute, ~/ or relative to the current directory.
+                      <cyan><bold>--tag</bold> <<tag>></>   diffs the entries tagged <<tag>>.
+                      <cyan><bold>--rev</bold> <<commit>></>  compares the live files with <<commit>>.
+                      <cyan,bold>--remote</>        compares the live files with the upstream branch.
+                      <cyan><bold>--between</bold> <<a>> <<b>></> compares two commits.
+                      <cyan,bold>--side-by-side</>  shows old and new next to each other, <cyan,bold>--word-diff</> highlights changed words.
+<cyan><bold>      --completions</bold> <<shell>></> Prints the completion script for bash, zsh or fish, e.g. <cyan>dm --completions bash > /etc/bash_completion.d/dm</>
+<cyan><bold>      --man</bold></>            Prints this help as a man page: <cyan>dm --man > ~/.local/share/man/man1/dm.1</>
 "
-);
+        )
+    };
+}
+
+fn help() {
+    with_help!(cprintln);
+}
+
+fn man() {
+    print!("{}", completions::man(with_help!(untagged)));
 }
 
-fn update() {
-    git_add_all();
+fn update(repo: &DotRepo, options: &Options) {
+    let scope = tag_scope(repo, options);
+    or_exit(repo.stage_all());
     println!();
-    let status_info = get_status_info();
+    let status_info = get_status_info(repo, scope.as_deref());
     println!("{}", status_info.work_tree);
     println!("{}", status_info.remote_url);
     println!("{}", status_info.status);
     if !status_info.entry_type_counts.is_empty() {
-        status_info.table.printstd();
-        select_next_step(&status_info);
+        print_changes(&status_info);
+        select_next_step(repo, scope.as_deref());
     }
 }
 
-fn status() {
-    git_add_all();
+fn status(repo: &DotRepo, options: &Options) {
+    let scope = tag_scope(repo, options);
+    or_exit(repo.stage_all());
     println!();
-    let status_info = get_status_info();
+    let status_info = get_status_info(repo, scope.as_deref());
     println!("{}", status_info.work_tree);
     println!("{}", status_info.remote_url);
     println!("{}", status_info.status);
     if !status_info.entry_type_counts.is_empty() {
-        status_info.table.printstd();
+        print_changes(&status_info);
         println!("{}", status_info.summary);
     }
 }
 
-fn status_summary_short() {
-    git_add_all();
-    let status = git_command_output("status --porcelain");
-    if !status.is_empty() {
-        let status_lines: Vec<String> = status.trim().split('\n').map(|l| l.to_string()).collect();
-        let status_counts = get_status_counts(&status_lines);
-        println!("{}", get_status_summary_short(&status_counts));
+fn status_summary_short(repo: &DotRepo) {
+    or_exit(repo.stage_all());
+    let status = or_exit(repo.status());
+    if !status.is_clean() {
+        println!("{}", get_status_summary_short(&get_status_counts(&status)));
     }
 }
 
-fn list() {
-    git_add_all();
+fn list(repo: &DotRepo, options: &Options) {
+    if options.flag("rebuild") {
+        return rebuild_list(repo);
+    }
     println!();
-    print_tracking_list_table();
+    print_tracking_tree(repo, options.flag("expand"), options.value("tag"));
     println!();
 }
 
-fn diff(file: &str) {
-    git_add_all();
-    if file.is_empty() {
-        let status_info = get_status_info();
-        status_info.table.printstd();
-        diff_file_select(&status_info);
+/// Shows how the list rebuilt from the repository differs from the current one and writes it
+/// once confirmed.
+fn rebuild_list(repo: &DotRepo) {
+    // A list that no longer parses is what a rebuild recovers from.
+    let current = repo.tracking_list().unwrap_or_default();
+    let rebuilt = or_exit(repo.rebuild_list());
+    let removed: Vec<String> = current
+        .paths()
+        .into_iter()
+        .filter(|p| !rebuilt.contains(p))
+        .collect();
+    let added: Vec<String> = rebuilt
+        .paths()
+        .into_iter()
+        .filter(|p| !current.contains(p))
+        .collect();
+    if removed.is_empty() && added.is_empty() {
+        cprintln!(" <bold>Tracking:</>\tthe list already matches the repository");
+        return;
+    }
+    for entry in removed.iter() {
+        cprintln!(" <red>- /{}</>", repo.home_relative(entry));
+    }
+    for entry in added.iter() {
+        cprintln!(" <green>+ /{}</>", repo.home_relative(entry));
+    }
+    let kept = rebuilt.entries.len() - added.len();
+    cprintln!(
+        " <bold>Rebuilt:</>\t{} added, {} removed, {} kept",
+        added.len(),
+        removed.len(),
+        kept
+    );
+    let write = Confirm::with_theme(&prompt_theme())
+        .with_prompt("Write the rebuilt tracking list?")
+        .default(false)
+        .interact()
+        .unwrap();
+    if write {
+        or_exit(repo.save_list(&rebuilt));
+        cprintln!(" <bold>Saved:</>\t<cyan>{}</>", repo.list_file());
+    }
+}
+
+fn pull(repo: &DotRepo) {
+    or_exit(repo.pull());
+    cprintln!(" <bold>Pulled:</>\t<cyan>{}</>", or_exit(repo.remote_url()));
+}
+
+fn diff(repo: &DotRepo, options: &Options) {
+    let renderer = renderer(options);
+    let target = diff_target(options);
+    let scope = tag_scope(repo, options);
+    or_exit(repo.stage_all());
+    if options.positional.is_empty()
+        && scope.is_none()
+        && target == DiffTarget::Staged
+        && stdout().is_terminal()
+    {
+        if or_exit(repo.status()).is_clean() {
+            println!("There are no modified files. Git appears to be up to date");
+            return;
+        }
+        or_exit(tui::review(repo, &renderer));
+        return;
+    }
+
+    let paths: Vec<String> = options
+        .positional
+        .iter()
+        .map(|p| resolve_path(repo.home(), p))
+        .chain(scope.unwrap_or_default())
+        .collect();
+    let paths: Vec<&str> = paths.iter().map(|p| p.as_str()).collect();
+    let files = or_exit(repo.diff_files(&paths, &target));
+    page(&renderer.render(&files));
+}
+
+fn diff_target(options: &Options) -> DiffTarget {
+    let given = ["rev", "remote", "between"]
+        .iter()
+        .filter(|f| options.flag(f))
+        .count();
+    if given > 1 {
+        cprintln!("<red,bold>error</><bold>:</> '--rev', '--remote' and '--between' are mutually exclusive");
+        exit(2);
+    }
+    if let Some(rev) = options.value("rev") {
+        DiffTarget::Revision(rev.to_string())
+    } else if options.flag("remote") {
+        DiffTarget::Upstream
+    } else if let Some(revs) = options.values("between") {
+        DiffTarget::Between(revs[0].clone(), revs[1].clone())
     } else {
-        diff(file);
+        DiffTarget::Staged
     }
 }
 
-fn init(repo_url: &String) {
-    if metadata(GIT.as_str()).is_err() {
-        fs::create_dir_all(GIT.as_str()).expect("Could not create git data directory");
+/// The repository in symlink-farm mode if `--farm` or `--farm-dir` asks for it, with the
+/// checkout at `~/dotfiles` unless given.
+fn farm_mode(repo: &DotRepo, options: &Options) -> DotRepo {
+    let dir = match (options.value("farm-dir"), options.flag("farm")) {
+        (Some(dir), _) => resolve_path(repo.home(), dir),
+        (None, true) => format!("{}/dotfiles", repo.home()),
+        (None, false) => return repo.clone(),
+    };
+    or_exit(repo.clone().into_farm(&dir))
+}
+
+fn init(repo: &DotRepo, repo_url: &str, options: &Options) {
+    let repo = farm_mode(repo, options);
+    or_exit(repo.init(repo_url));
+    if repo.is_farm() {
+        cprintln!(" <bold>Checkout:</>\t<cyan>{}/</>", repo.work_tree());
     }
-    let home_git_path = format!("{}/.github", HOME.as_str());
-    if metadata(&home_git_path).is_err() {
-        fs::create_dir(&home_git_path).expect("Could not create $HOME/.github");
+}
+
+fn clone(repo: &DotRepo, repo_url: &str, options: &Options) {
+    let repo = &farm_mode(repo, options);
+    let snapshot = or_exit(repo.clone_from(repo_url));
+    cprintln!(" <bold>Cloned:</>\t<cyan>{}</>", repo_url);
+    if repo.is_farm() {
+        cprintln!(" <bold>Checkout:</>\t<cyan>{}/</>", repo.work_tree());
+    }
+    cprintln!(
+        " <bold>Snapshot:</>\t<cyan>{}</> of the files that were replaced",
+        snapshot.id
+    );
+    print_tracking_tree(repo, false, None);
+    let system: Vec<String> = or_exit(repo.tracking_list())
+        .paths()
+        .into_iter()
+        .filter(|p| repo.is_system(p))
+        .collect();
+    if !system.is_empty() {
+        cprintln!(
+            " <bold>System:</>\t{} entry(s) outside HOME left alone, put them in place with <cyan>dm --restore <<path>></>",
+            system.len()
+        );
     }
-    let readme_path = format!("{}/.github/README.md", HOME.as_str());
-    if metadata(&readme_path).is_err() {
-        fs::File::create(&readme_path).expect("Could not create $HOME/.github/README.md");
+    let conflicts: Vec<Link> = or_exit(repo.links())
+        .into_iter()
+        .filter(|l| l.state == LinkState::Conflict)
+        .collect();
+    if !conflicts.is_empty() {
+        cprintln!(
+            " <bold>Conflicts:</>\t{} file(s) in HOME are in the way of their links and were left alone:",
+            conflicts.len()
+        );
+        for link in conflicts {
+            cprintln!("\t<red>~/{}</>", repo.home_relative(&link.path));
+        }
+        cprintln!("\tmove them aside, then link them with <cyan>dm --doctor --fix</>");
     }
-    git_command_output(format!("git init --bare {}", GIT.as_str()).as_str());
-    git_command_output("config --local status.showUntrackedFiles no");
-    git_command_output("branch -M main");
-    git_command_output(format!("remote add origin {repo_url}").as_str());
-    git_command_output(format!("add {readme_path}").as_str());
-    git_command_output("commit -m \"Initial commit\"");
-    git_command_output("push -u origin main");
 }
 
-fn add(path: &String) {
-    check_path_exists(path);
-    add_to_tracking_list(path);
-    git_command_spawn(format!("add {path}").as_str());
+/// The tags given with `--tag`, which may be comma separated.
+fn tags(options: &Options) -> Vec<&str> {
+    options
+        .value("tag")
+        .map(|t| t.split(',').map(|t| t.trim()).collect())
+        .unwrap_or_default()
 }
 
-fn remove(path: &String) {
-    check_path_exists(path);
-    remove_from_tracking_list(path);
-    git_command_spawn(format!("rm -rf {path}").as_str());
+/// Adds `path` to the tracking list. Tags are added to the entry, also if it is tracked
+/// already.
+fn add(repo: &DotRepo, path: &str, options: &Options) {
+    let tags = tags(options);
+    let follow = match (options.flag("follow"), options.flag("no-follow")) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    };
+    match repo.track(path) {
+        Err(Error::AlreadyTracked(_)) if !tags.is_empty() || follow.is_some() => {}
+        result => or_exit(result),
+    }
+    if !tags.is_empty() {
+        or_exit(repo.tag(path, &tags));
+    }
+    if let Some(follow) = follow {
+        or_exit(repo.set_follow(path, follow));
+    }
 }
 
-fn git_add_all() {
-    let mut paths = file_to_vec(LIST.as_str());
-    let l = paths.len();
-    paths.retain(|p| metadata(p).is_ok());
-    if l != paths.len() {
-        fs::write(LIST.as_str(), paths.join("\n")).expect("Could not update path list.");
+/// Offers the dotfiles found in HOME and `~/.config` and adds the chosen ones.
+fn add_picked(repo: &DotRepo, options: &Options) {
+    let candidates = or_exit(repo.discover());
+    if candidates.is_empty() {
+        println!("No dotfiles found.");
+        return;
     }
-    for path in paths {
-        git_command_spawn(format!("add {path}").as_str());
+    let items: Vec<String> = candidates
+        .iter()
+        .map(|c| {
+            let path = format!(
+                "~/{}{}",
+                repo.home_relative(&c.path),
+                if c.is_dir { "/" } else { "" }
+            );
+            let details = format!(
+                "{} file(s), {}, {}",
+                c.files,
+                human_size(c.size),
+                c.modified.map_or("-".to_string(), age)
+            );
+            match c.tracked {
+                true => cformat!(
+                    "{:<9} <dim>{:<32} {}  tracked</>",
+                    c.group.title(),
+                    path,
+                    details
+                ),
+                false => cformat!("{:<9} {:<32} <dim>{}</>", c.group.title(), path, details),
+            }
+        })
+        .collect();
+    let chosen = MultiSelect::with_theme(&prompt_theme())
+        .with_prompt("Dotfiles to add (space toggles)")
+        .items(&items)
+        .interact()
+        .unwrap();
+    let chosen: Vec<&Candidate> = chosen
+        .iter()
+        .map(|&i| &candidates[i])
+        .filter(|c| !c.tracked)
+        .collect();
+    if chosen.is_empty() {
+        println!("Nothing selected.");
+        return;
+    }
+    let tags = tags(options);
+    for candidate in chosen {
+        let added = repo
+            .track(&candidate.path)
+            .and_then(|()| match tags.is_empty() {
+                true => Ok(()),
+                false => repo.tag(&candidate.path, &tags),
+            });
+        match added {
+            Ok(()) => cprintln!(
+                " <bold>Added:</>\t<cyan>~/{}</>",
+                repo.home_relative(&candidate.path)
+            ),
+            Err(e) => print_error(&e),
+        }
     }
 }
 
-fn get_status_info() -> StatusInfo {
-    let mut status_info = StatusInfo::default();
-    let url = git_command_output("remote get-url --all origin");
-    let status_output = git_command_output("status --porcelain");
+fn remove(repo: &DotRepo, path: &str) {
+    or_exit(repo.untrack(path));
+}
 
-    status_info.work_tree = cformat!(" <bold>{}</>\t<cyan>{}/</>", "Work-tree:", HOME.as_str());
-    status_info.remote_url = cformat!(" <bold>{}</>\t<cyan>{}</>", "Remote-URL:", url.trim());
-    if !status_output.is_empty() {
-        status_info.status = cformat!(" <bold>Git status:</>");
-        let status_lines: Vec<String> = status_output
-            .trim()
-            .split('\n')
-            .map(|l| l.to_string())
+fn restore(repo: &DotRepo, path: &str, options: &Options) {
+    let rev = options.value("rev").unwrap_or("HEAD");
+    let restored = or_exit(repo.restore(path, Some(rev)));
+    cprintln!(
+        " <bold>Restored:</>\t<cyan>/{}</> ({} file(s) from {})",
+        repo.home_relative(path),
+        restored.files.len(),
+        rev
+    );
+    if let Some(backup) = restored.backup {
+        cprintln!(" <bold>Backup:</>\t<cyan>{}</>", backup);
+    }
+}
+
+/// Reports problems with the repository and the tracking list, repairing what it can with
+/// `--fix`. Exits with 1 while problems remain.
+fn doctor(repo: &DotRepo, options: &Options) {
+    let problems = or_exit(repo.doctor());
+    if problems.is_empty() {
+        cprintln!(" <bold>Doctor:</>\t<green>no problems found</>");
+        return;
+    }
+    let fix = options.flag("fix");
+    let mut left = 0;
+    for problem in problems.iter() {
+        cprintln!(
+            "<yellow,bold>{}</><bold>:</> {}",
+            problem.check,
+            problem.message
+        );
+        match &problem.repair {
+            Some(repair) if fix => match repo.repair(repair) {
+                Ok(()) => cprintln!("  <green>fixed:</> {}", problem.suggestion),
+                Err(e) => {
+                    left += 1;
+                    cprintln!("  <red>could not fix:</> {}", e);
+                }
+            },
+            Some(_) => {
+                left += 1;
+                cprintln!("  <dim>fix:</> {} (dm --doctor --fix)", problem.suggestion);
+            }
+            None => {
+                left += 1;
+                cprintln!("  <dim>fix:</> {}", problem.suggestion);
+            }
+        }
+    }
+    if left > 0 {
+        exit(1);
+    }
+}
+
+/// Prints the entry covering `path`, or why none does. Exits with 1 if dm does not manage it.
+fn which(repo: &DotRepo, path: &str) {
+    match or_exit(repo.which(path)) {
+        Coverage::Tracked(entry) => cprintln!(
+            " <bold>Tracked:</>\t<cyan>{}</> by the entry <cyan>{}</>",
+            shown_path(repo, path),
+            shown_path(repo, &entry)
+        ),
+        coverage => {
+            cprintln!(
+                " <bold>Not tracked:</>\t{} {}",
+                shown_path(repo, path),
+                exclusion(repo, &coverage)
+            );
+            exit(1);
+        }
+    }
+}
+
+/// `path` as dm shows it: `/`-prefixed relative to HOME, or absolute outside of it.
+fn shown_path(repo: &DotRepo, path: &str) -> String {
+    match repo.home_relative(path) {
+        rel if rel == path => path.to_string(),
+        rel => format!("/{rel}"),
+    }
+}
+
+/// Why `coverage` leaves a path out, for `which` and `info`.
+fn exclusion(repo: &DotRepo, coverage: &Coverage) -> String {
+    match coverage {
+        Coverage::Tracked(_) => String::new(),
+        Coverage::Ignored { entry, rule } => format!(
+            "is in the entry /{} but ignored by {}",
+            repo.home_relative(entry),
+            rule
+        ),
+        Coverage::NotTracked => "is not covered by any entry".to_string(),
+        Coverage::OutsideHome => format!("is outside the work-tree {}", repo.home()),
+        Coverage::DataDir => "is part of the dotmanager data directory".to_string(),
+    }
+}
+
+fn info(repo: &DotRepo, path: &str) {
+    let info = or_exit(repo.info(path));
+    cprintln!(
+        " <bold>Path:</>\t\t<cyan>{}</>",
+        shown_path(repo, &info.path)
+    );
+    match &info.coverage {
+        Coverage::Tracked(entry) => {
+            cprintln!(" <bold>Entry:</>\t\t<cyan>{}</>", shown_path(repo, entry))
+        }
+        coverage => cprintln!(" <bold>Entry:</>\t\tnone, {}", exclusion(repo, coverage)),
+    }
+    let state = match info.state {
+        Some(EntryState::Clean) => cformat!("<green>clean</>"),
+        Some(EntryState::Modified) => cformat!("<yellow>modified</>"),
+        Some(EntryState::Missing) => cformat!("<red>missing</>"),
+        None => "untracked".to_string(),
+    };
+    cprintln!(" <bold>State:</>\t\t{}", state);
+    cprintln!(
+        " <bold>Type:</>\t\t{}",
+        if info.is_dir { "folder" } else { "file" }
+    );
+    cprintln!(" <bold>Size:</>\t\t{}", human_size(info.size));
+    let mode = info.mode.map_or("-".to_string(), |m| format!("{m:04o}"));
+    match &info.index_mode {
+        Some(index_mode) => cprintln!(" <bold>Mode:</>\t\t{} (git {})", mode, index_mode),
+        None => cprintln!(" <bold>Mode:</>\t\t{}", mode),
+    }
+    cprintln!(" <bold>Files in git:</>\t{}", info.files);
+    match &info.last_commit {
+        Some(commit) => cprintln!(
+            " <bold>Last commit:</>\t<cyan>{}</> {} {}",
+            commit.short,
+            commit.date,
+            commit.subject
+        ),
+        None => cprintln!(" <bold>Last commit:</>\tnone"),
+    }
+    match info.hosts.is_empty() {
+        true => cprintln!(" <bold>Hosts:</>\t\tnone recorded"),
+        false => cprintln!(" <bold>Hosts:</>\t\t{}", info.hosts.join(", ")),
+    }
+    match info.variants.is_empty() {
+        true => cprintln!(" <bold>Variants:</>\tone for every host"),
+        false => cprintln!(" <bold>Variants:</>\t{}", info.variants.join(", ")),
+    }
+    let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+    cprintln!(" <bold>Encrypted:</>\t{}", yes_no(info.encrypt));
+    cprintln!(" <bold>Templated:</>\t{}", yes_no(info.template));
+}
+
+fn log(repo: &DotRepo, options: &Options) {
+    let path = options
+        .positional
+        .first()
+        .map(|p| resolve_path(repo.home(), p));
+    let limit = match options.value("limit").map(|l| l.parse::<usize>()) {
+        Some(Ok(limit)) => Some(limit),
+        Some(Err(_)) => {
+            cprintln!("<red,bold>error</><bold>:</> '--limit' requires a number");
+            exit(2);
+        }
+        None => None,
+    };
+    let entries = or_exit(repo.log(path.as_deref(), limit));
+    if entries.is_empty() {
+        println!("No commits found.");
+        return;
+    }
+    if !options.flag("interactive") {
+        get_log_table(&entries).printstd();
+        return;
+    }
+
+    let renderer = renderer(options);
+    let items: Vec<String> = entries
+        .iter()
+        .map(|e| {
+            cformat!(
+                "<yellow>{}</> <dim>{}</> <cyan>{:<12}</> {}",
+                e.short,
+                e.date,
+                e.host,
+                e.subject
+            )
+        })
+        .collect();
+    let mut selected = 0;
+    while let Some(i) = Select::with_theme(&prompt_theme())
+        .with_prompt("Show commit (esc to quit)")
+        .items(&items)
+        .default(selected)
+        .interact_opt()
+        .unwrap()
+    {
+        selected = i;
+        let entry = &entries[i];
+        // For a single path, show just its changes under the name it had in that commit.
+        let paths = if path.is_some() {
+            entry.files.clone()
+        } else {
+            vec![]
+        };
+        let files = or_exit(repo.commit_diff(&entry.hash, &paths));
+        page(&renderer.render(&files));
+    }
+}
+
+fn get_log_table(entries: &[LogEntry]) -> Table {
+    let mut table = new_table();
+    table.set_titles(Row::new(
+        ["commit", "date", "host", "message", "files"]
+            .iter()
+            .map(|t| Cell::new(t).style_spec("bFgc"))
+            .collect(),
+    ));
+    for entry in entries {
+        let mut files: Vec<String> = entry
+            .files
+            .iter()
+            .take(3)
+            .map(|f| format!("/{f}"))
             .collect();
+        if entry.files.len() > 3 {
+            files.push(format!("+{} more", entry.files.len() - 3));
+        }
+        table.add_row(Row::new(vec![
+            Cell::new(&entry.short).style_spec("Fy"),
+            Cell::new(&entry.date),
+            Cell::new(&entry.host).style_spec("Fc"),
+            Cell::new(&entry.subject),
+            Cell::new(&files.join("\n")).style_spec("Fb"),
+        ]));
+    }
+    table
+}
+
+fn checkout(repo: &DotRepo, options: &Options) {
+    let checkout = match (options.value("at"), options.flag("return")) {
+        (Some(when), false) => or_exit(repo.checkout_at(when)),
+        (None, true) => or_exit(repo.checkout_return()),
+        _ => {
+            cprintln!("<red,bold>error</><bold>:</> checkout requires either '--at <<date|commit>>' or '--return'");
+            exit(2);
+        }
+    };
+    if options.flag("return") {
+        cprintln!(
+            " <bold>Returned:</>\t<cyan>{}</> {}",
+            checkout.snapshot.id,
+            checkout.snapshot.label
+        );
+    } else {
+        cprintln!(" <bold>Checked out:</>\t<cyan>{}</>", &checkout.commit[..7]);
+        cprintln!(
+            " <bold>Snapshot:</>\t<cyan>{}</> of the previous state",
+            checkout.snapshot.id
+        );
+    }
+    if checkout.changes.is_empty() {
+        cprintln!(" <bold>Changes:</>\t<green>none</>");
+    } else {
+        get_change_table(&checkout.changes).printstd();
+    }
+    if !options.flag("return") {
+        println!(" Run 'dm --checkout --return' to bring the previous state back.");
+    }
+}
+
+fn snapshot(repo: &DotRepo, options: &Options) {
+    let args: Vec<&str> = options.positional.iter().map(|a| a.as_str()).collect();
+    match args.as_slice() {
+        ["create"] | ["create", _] => {
+            let snapshot = or_exit(repo.create_snapshot(args.get(1).copied(), "created by hand"));
+            cprintln!(
+                " <bold>Snapshot:</>\t<cyan>{}</> {}",
+                snapshot.id,
+                snapshot.short()
+            );
+        }
+        ["list"] => {
+            let snapshots = or_exit(repo.snapshots());
+            if snapshots.is_empty() {
+                println!("No snapshots found.");
+                return;
+            }
+            get_snapshot_table(&snapshots).printstd();
+        }
+        ["restore", id] => {
+            let restored = or_exit(repo.restore_snapshot(id));
+            cprintln!(" <bold>Restored:</>\t<cyan>{}</>", id);
+            cprintln!(
+                " <bold>Snapshot:</>\t<cyan>{}</> of the previous state",
+                restored.snapshot.id
+            );
+            if !restored.changes.is_empty() {
+                get_change_table(&restored.changes).printstd();
+            }
+        }
+        ["delete", id] => {
+            or_exit(repo.delete_snapshot(id));
+            cprintln!(" <bold>Deleted:</>\t<cyan>{}</>", id);
+        }
+        ["prune"] => {
+            let mut policy = or_exit(repo.config()).snapshots;
+            if let Some(keep) = options.value("keep") {
+                policy.keep = keep.parse().unwrap_or_else(|_| {
+                    cprintln!("<red,bold>error</><bold>:</> '--keep' requires a number");
+                    exit(2);
+                });
+            }
+            let pruned = or_exit(repo.prune_snapshots(&policy));
+            cprintln!(" <bold>Pruned:</>\t{} snapshot(s)", pruned.len());
+        }
+        _ => {
+            help();
+            exit(2);
+        }
+    }
+}
+
+fn undo(repo: &DotRepo, options: &Options) {
+    let undone = or_exit(repo.undo(options.flag("force")));
+    cprintln!(
+        " <bold>Undone:</>\t<cyan>dm {}</>",
+        undone.operation.command
+    );
+    for note in undone.notes {
+        println!("\t\t{note}");
+    }
+}
+
+/// Runs git against the dotfile repository, or another program with `GIT_DIR` and
+/// `GIT_WORK_TREE` set, passing `args` through untouched, and exits with its status.
+fn passthrough(repo: &DotRepo, cmd: &str, args: &[String]) -> ! {
+    let mut command = if cmd == "git" {
+        let mut command = repo.git().command();
+        command.args(args);
+        command
+    } else {
+        let Some((program, rest)) = args.split_first() else {
+            cprintln!("<red,bold>error</><bold>:</> '--exec' requires a command");
+            exit(2);
+        };
+        let mut command = Command::new(program);
+        command.args(rest).envs(repo.git_env());
+        command
+    };
+    match command.status() {
+        Ok(status) => exit(status.code().unwrap_or(2)),
+        Err(e) => {
+            cprintln!(
+                "<red,bold>error</><bold>:</> could not run '{}': {}",
+                args.first().map_or("git", |a| a),
+                e
+            );
+            exit(2);
+        }
+    }
+}
+
+/// Prints `export` lines for `eval "$(dm --env)"`.
+fn print_env(repo: &DotRepo) {
+    for (var, value) in repo.git_env() {
+        println!("export {var}='{}'", value.replace('\'', "'\\''"));
+    }
+}
+
+/// The commands the shells complete, derived from `VALID_INPUTS` and `option_spec`.
+fn completion_commands() -> Vec<completions::Command> {
+    let longs: Vec<&str> = VALID_INPUTS[1]
+        .split(',')
+        .map(|c| c.trim().trim_end_matches([';', ':']))
+        .collect();
+    // A short flag belongs to the first long command starting with its letter.
+    let short = |long: &str| {
+        VALID_INPUTS[0]
+            .chars()
+            .filter(|c| c.is_alphabetic())
+            .find(|c| longs.iter().find(|l| l.starts_with(*c)) == Some(&long))
+    };
+    longs
+        .iter()
+        .filter(|long| **long != "complete")
+        .map(|&long| completions::Command {
+            short: short(long),
+            long: long.to_string(),
+            options: option_spec(long)
+                .split(',')
+                .map(|o| o.trim().trim_end_matches(':').to_string())
+                .filter(|o| !o.is_empty())
+                .collect(),
+            argument: match long {
+                "add" => Argument::HomeFiles,
+                "which" | "info" => Argument::Files,
+                "remove" | "restore" | "log" => Argument::Dynamic("entries"),
+                "diff" => Argument::Dynamic("changed"),
+                "snapshot" => Argument::Dynamic("snapshots"),
+                "completions" => Argument::Words(&["bash", "zsh", "fish"]),
+                _ => Argument::None,
+            },
+        })
+        .collect()
+}
+
+fn print_completions(shell: &str) {
+    let commands = completion_commands();
+    match shell {
+        "bash" => print!("{}", completions::bash(&commands)),
+        "zsh" => print!("{}", completions::zsh(&commands)),
+        "fish" => print!("{}", completions::fish(&commands)),
+        _ => {
+            cprintln!(
+                "<red,bold>error</><bold>:</> unsupported shell '{}', use bash, zsh or fish",
+                shell
+            );
+            exit(2);
+        }
+    }
+}
+
+/// Prints the candidates for `dm --complete <kind>`, one per line, as the completion scripts
+/// call it. Errors print nothing, so a broken repository does not spill into the prompt.
+fn complete(repo: &DotRepo, kind: &str) {
+    let candidates: Vec<String> = match kind {
+        "entries" => repo
+            .tracking_list()
+            .map(|list| {
+                list.entries
+                    .iter()
+                    .map(|e| match repo.is_system(&e.path) {
+                        true => e.path.clone(),
+                        false => format!("~/{}", repo.home_relative(&e.path)),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+        "changed" => repo
+            .status()
+            .map(|status| {
+                status
+                    .entries
+                    .into_iter()
+                    .map(|e| match repo.live_path(&e.path) {
+                        live if repo.is_system(&live) => live,
+                        _ => format!("~/{}", e.path),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+        "snapshots" => ["create", "list", "restore", "delete", "prune"]
+            .iter()
+            .map(|c| c.to_string())
+            .chain(
+                repo.snapshots()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|s| s.id),
+            )
+            .collect(),
+        _ => vec![],
+    };
+    for candidate in candidates {
+        println!("{candidate}");
+    }
+}
+
+fn get_snapshot_table(snapshots: &[Snapshot]) -> Table {
+    let mut table = new_table();
+    table.set_titles(Row::new(
+        ["snapshot", "date", "commit", "label"]
+            .iter()
+            .map(|t| Cell::new(t).style_spec("bFgc"))
+            .collect(),
+    ));
+    for snapshot in snapshots.iter().rev() {
+        let spec = if snapshot.is_automatic() { "" } else { "b" };
+        table.add_row(Row::new(vec![
+            Cell::new(&snapshot.id).style_spec(spec),
+            Cell::new(&snapshot.date),
+            Cell::new(snapshot.short()).style_spec("Fy"),
+            Cell::new(&snapshot.label),
+        ]));
+    }
+    table
+}
+
+/// The status of the repository, narrowed to the files in `scope` if given.
+fn get_status_info(repo: &DotRepo, scope: Option<&[String]>) -> StatusInfo {
+    let mut status_info = StatusInfo::default();
+    let mut status = or_exit(repo.status());
+    status.entries.retain(|e| in_scope(repo, scope, &e.path));
+    status
+        .drift
+        .retain(|d| in_scope(repo, scope, &d.current.path));
 
-        status_info.entry_type_counts = get_status_counts(&status_lines);
-        status_info.table = get_status_table(&status_lines, &mut status_info);
+    status_info.work_tree = cformat!(" <bold>{}</>\t<cyan>{}/</>", "Work-tree:", repo.work_tree());
+    status_info.remote_url = cformat!(
+        " <bold>{}</>\t<cyan>{}</>",
+        "Remote-URL:",
+        status.remote_url
+    );
+    if !status.is_clean() {
+        status_info.status = cformat!(" <bold>Git status:</>");
+        status_info.entry_type_counts = get_status_counts(&status);
+        status_info.table = get_status_table(&status);
         status_info.summary = get_status_summary(&status_info.entry_type_counts);
         status_info.summary_short = get_status_summary_short(&status_info.entry_type_counts);
+        status_info.drift = get_drift_list(&status.drift);
     } else {
         status_info.status = cformat!(" <bold>Git status:\t<green>Up to date</>");
     }
     status_info
 }
 
-fn select_next_step(status_info: &StatusInfo) {
-    let options = ["commit & push", "diff", "exit"];
-    let theme = ColorfulTheme {
-        prompt_prefix: style("".to_string()).for_stderr().yellow(),
-        prompt_suffix: style("".to_string()).for_stderr().black().bright(),
-        ..Default::default()
-    };
-
-    let selection = Select::with_theme(&theme)
+/// Offers the ways to commit the staged changes, or only those in `scope` if given.
+fn select_next_step(repo: &DotRepo, scope: Option<&[String]>) {
+    let mut options = vec!["commit & push", "review", "choose what to commit", "exit"];
+    if scope.is_some() {
+        // The review covers every staged change, not just those of the tag.
+        options.retain(|o| *o != "review");
+    }
+    let selection = Select::with_theme(&prompt_theme())
         .with_prompt("Proceed to:")
         .default(0)
         .items(&options[..])
@@ -227,16 +1104,34 @@ fn select_next_step(status_info: &StatusInfo) {
 
     let mut stdout = stdout();
     stdout.queue(cursor::MoveUp(1)).unwrap();
-    stdout.execute(terminal::Clear(terminal::ClearType::CurrentLine)).unwrap();
+    stdout
+        .execute(terminal::Clear(terminal::ClearType::CurrentLine))
+        .unwrap();
 
-    match selection {
-        0 => {
-            commit_and_push();
-        }
-        1 => {
-            diff_file_select(status_info);
-            select_next_step(status_info);
+    match options[selection] {
+        "commit & push" => match scope {
+            Some(scope) => commit_scoped_and_push(repo, scope),
+            None => commit_and_push(repo),
+        },
+        "review" => {
+            let committed = match or_exit(tui::review(repo, &renderer(&Options::default()))) {
+                tui::Outcome::Committed(hash) => {
+                    cprintln!(" <bold>Committed:</>\t<cyan>{}</>", hash);
+                    true
+                }
+                tui::Outcome::Quit => false,
+            };
+            if or_exit(repo.status()).entries.iter().any(|e| e.staged) {
+                return select_next_step(repo, scope);
+            }
+            if committed {
+                or_exit(repo.push());
+                cprintln!(" <bold>Pushed:</>\t<cyan>{}</>", or_exit(repo.remote_url()));
+            } else {
+                println!("Nothing left to commit.");
+            }
         }
+        "choose what to commit" => commit_picked_and_push(repo, scope),
         _ => {
             println!("Terminating.");
             exit(2);
@@ -244,62 +1139,121 @@ fn select_next_step(status_info: &StatusInfo) {
     }
 }
 
-fn commit_and_push() {
-    let message = read_input("Add commit message: ");
-    git_command_spawn(format!("commit -m \"{message}\"").as_str());
-    git_command_spawn("push");
+/// Commits a chosen subset of the staged files, optionally down to single hunks. Whatever is
+/// left out stays staged and is offered again by the next update.
+fn commit_picked_and_push(repo: &DotRepo, scope: Option<&[String]>) {
+    let entries = staged_entries(repo, scope);
+    let items: Vec<String> = entries
+        .iter()
+        .map(|e| cformat!("{:<9} <dim>/</>{}", e.change.title(), e.path))
+        .collect();
+    let chosen = MultiSelect::with_theme(&prompt_theme())
+        .with_prompt("Files to commit (space toggles)")
+        .items(&items)
+        .defaults(&vec![true; items.len()])
+        .interact()
+        .unwrap();
+    if chosen.is_empty() {
+        println!("Nothing selected.");
+        return select_next_step(repo, scope);
+    }
+
+    let by_hunk = Confirm::with_theme(&prompt_theme())
+        .with_prompt("Choose individual hunks?")
+        .default(false)
+        .interact()
+        .unwrap();
+    let renderer = renderer(&Options::default());
+    let mut picks = vec![];
+    for entry in chosen.iter().map(|&i| &entries[i]) {
+        let file = or_exit(repo.diff(&repo.absolute(&entry.path)));
+        if !by_hunk || !file.is_splittable() || file.hunks.len() < 2 {
+            picks.push(Pick::File(entry.path.clone()));
+            if let Some(from) = &entry.renamed_from {
+                picks.push(Pick::File(from.clone()));
+            }
+            continue;
+        }
+        print!("{}", renderer.render_file(&file));
+        let hunks: Vec<String> = file
+            .hunks
+            .iter()
+            .map(|h| format!("{} {}", h.header(), h.section))
+            .collect();
+        let hunks = MultiSelect::with_theme(&prompt_theme())
+            .with_prompt(format!("Hunks of /{} to commit", entry.path))
+            .items(&hunks)
+            .defaults(&vec![true; hunks.len()])
+            .interact()
+            .unwrap();
+        if hunks.len() == file.hunks.len() {
+            picks.push(Pick::File(entry.path.clone()));
+        } else if !hunks.is_empty() {
+            picks.push(Pick::Hunks(file, hunks));
+        }
+    }
+
+    commit_picks_and_push(repo, &picks);
 }
 
-fn check_path_exists(path: &String) {
-    if metadata(path.trim_end_matches('/')).is_err() {
-        print_path_error("error", "did not match any files or folders", path);
-        exit(2);
+/// Commits the staged files in `scope`, leaving the other staged changes for later.
+fn commit_scoped_and_push(repo: &DotRepo, scope: &[String]) {
+    let mut picks = vec![];
+    for entry in staged_entries(repo, Some(scope)) {
+        picks.push(Pick::File(entry.path));
+        picks.extend(entry.renamed_from.map(Pick::File));
     }
+    commit_picks_and_push(repo, &picks);
 }
 
-fn diff_file(file: &str) {
-    let cached_diff = git_command_output("diff --cached");
-    let cached_diff = cached_diff.split("diff --git ").collect::<Vec<&str>>();
-    let diff_paths = git_command_output("diff --cached --name-only");
-    let diff_paths = diff_paths.split('\n').collect::<Vec<&str>>();
-    let file = file.trim_start_matches((HOME.to_string() + "/").as_str());
-    let file_diff: Vec<&str> = match diff_paths
+/// The staged changes, narrowed to the files in `scope` if given.
+fn staged_entries(repo: &DotRepo, scope: Option<&[String]>) -> Vec<StatusEntry> {
+    or_exit(repo.status())
+        .entries
         .into_iter()
-        .enumerate()
-        .find(|(_i, p)| &file == p)
-    {
-        Some((i, _p)) => cached_diff[i + 1].split('\n').collect(),
-        None => {
-            print_path_error("warn", "did not find any changes", &file.to_string());
-            exit(0);
-        }
-    };
+        .filter(|e| e.staged && in_scope(repo, scope, &e.path))
+        .collect()
+}
 
-    let mut output = cformat!("<bold>diff --git </>");
-    let mut found_atat = false;
-    for line in file_diff {
-        if line.starts_with("@@ ") {
-            found_atat = true;
-            let split = line.split("@@").collect::<Vec<&str>>();
-            output += &cformat!("<cyan>@@{}@@</>{}\n", split[1], split[2]);
-            continue;
-        }
-        if !found_atat {
-            output += &cformat!("<bold>{line}</>\n");
-            continue;
-        }
+fn commit_picks_and_push(repo: &DotRepo, picks: &[Pick]) {
+    let message = read_input("Add commit message: ");
+    let hash = or_exit(repo.commit_picked(&message, picks));
+    cprintln!(" <bold>Committed:</>\t<cyan>{}</> {}", hash, message);
+    or_exit(repo.push());
+    cprintln!(" <bold>Pushed:</>\t<cyan>{}</>", or_exit(repo.remote_url()));
+    let left = or_exit(repo.status()).entries.len();
+    if left > 0 {
+        cprintln!(
+            " <bold>Left out:</>\t{} file(s), offered again on the next update",
+            left
+        );
+    }
+}
 
-        if line.starts_with('+') {
-            output += &cformat!("<green>{line}</>\n");
-        } else if line.starts_with('-') {
-            output += &cformat!("<red>{line}</>\n");
-        } else {
-            output += &cformat!("{line}\n");
-        }
+fn prompt_theme() -> ColorfulTheme {
+    ColorfulTheme {
+        prompt_prefix: style("".to_string()).for_stderr().yellow(),
+        prompt_suffix: style("".to_string()).for_stderr().black().bright(),
+        ..Default::default()
     }
+}
+
+fn commit_and_push(repo: &DotRepo) {
+    let message = read_input("Add commit message: ");
+    let hash = or_exit(repo.commit(&message));
+    cprintln!(" <bold>Committed:</>\t<cyan>{}</> {}", hash, message);
+    or_exit(repo.push());
+    cprintln!(" <bold>Pushed:</>\t<cyan>{}</>", or_exit(repo.remote_url()));
+}
 
+/// Shows `output` in less when writing to a terminal, otherwise prints it as is.
+fn page(output: &str) {
+    if !stdout().is_terminal() {
+        print!("{output}");
+        return;
+    }
     let mut child = Command::new("less")
-        .arg("-~")
+        .args(["-~", "-R"])
         .stdin(Stdio::piped())
         .spawn()
         .unwrap();
@@ -312,207 +1266,250 @@ fn diff_file(file: &str) {
     child.wait().expect("wait for less failed");
 }
 
-fn diff_file_select(status_info: &StatusInfo) {
-    let names = git_command_output("diff --cached --name-only");
-    if names.is_empty() {
-        println!("There are no modified files. Git appears to be up to date");
-        println!("Terminating");
-        exit(0);
+/// Prints the changed files and the files whose permissions changed, whichever there are.
+fn print_changes(status_info: &StatusInfo) {
+    if status_info.entry_type_counts.iter().any(|c| *c > 0) {
+        status_info.table.printstd();
     }
-
-    let mut stdout = stdout();
-    stdout.execute(cursor::Hide).unwrap();
-    enable_raw_mode().unwrap();
-
-    let mut index: i32 = 0;
-    select_status_entry(status_info, &index, true);
-    loop {
-        if let Ok(c) = Term::buffered_stdout().read_key() {
-            match c {
-                Key::Tab | Key::ArrowDown => {
-                    next_status_entry(status_info, &mut index, 1);
-                }
-                Key::BackTab | Key::ArrowUp => {
-                    next_status_entry(status_info, &mut index, -1);
-                }
-                Key::Enter => {
-                    diff_file(&status_info.status_entries[index as usize].1.trim()[10..]);
-                }
-                _ => {
-                    select_status_entry(status_info, &index, false);
-                    disable_raw_mode().unwrap();
-                    stdout.execute(cursor::Show).unwrap();
-                    break;
-                }
-            }
-        }
+    if !status_info.drift.is_empty() {
+        cprintln!(" <bold>Permissions:</>");
+        println!("{}", status_info.drift);
     }
 }
 
-fn next_status_entry(status_info: &StatusInfo, index: &mut i32, dir: i32) {
-    select_status_entry(status_info, index, false);
-    let l = status_info.status_entries.len() as i32;
-    *index = (*index + dir) % l;
-    if *index < 0 {
-        *index += l;
-    }
-    select_status_entry(status_info, index, true);
+/// One line per file with its committed and current mode, and owner if that changed.
+fn get_drift_list(drift: &[ModeDrift]) -> String {
+    let owner = |mode: &FileMode| {
+        format!(
+            "{}:{}",
+            mode.owner.as_deref().unwrap_or("-"),
+            mode.group.as_deref().unwrap_or("-")
+        )
+    };
+    let lines: Vec<String> = drift
+        .iter()
+        .map(|d| {
+            let mut line = cformat!(
+                " <dim>></> <dim>/</>{}  {} → <yellow>{}</>",
+                d.current.path,
+                d.recorded.mode,
+                d.current.mode
+            );
+            if (&d.recorded.owner, &d.recorded.group) != (&d.current.owner, &d.current.group) {
+                line += &cformat!(
+                    ", owner {} → <yellow>{}</>",
+                    owner(&d.recorded),
+                    owner(&d.current)
+                );
+            }
+            line
+        })
+        .collect();
+    lines.join("\n")
 }
 
-fn select_status_entry(status_info: &StatusInfo, index: &i32, select: bool) {
-    let size0 = status_info
-        .status_entries
+fn get_status_table(status: &Status) -> Table {
+    let changes: Vec<(Change, String)> = status
+        .entries
         .iter()
-        .map(|e| e.0.len())
-        .max()
-        .unwrap();
-    let size1 = status_info
-        .status_entries
-        .iter()
-        .map(|e| e.1.len())
-        .max()
-        .unwrap();
-    let path = &status_info.status_entries[*index as usize].1;
-    let start: u16 = (status_info.status_entries.len() as i32 - index + 1) as u16;
-    let right: u16 = (size0 + 5) as u16;
-
-    let mut stdout = stdout();
-    stdout.queue(cursor::SavePosition).unwrap();
-    stdout.queue(cursor::MoveToPreviousLine(start)).unwrap();
-    stdout.queue(cursor::MoveToColumn(right)).unwrap();
-
-    let selection = if select {
-        cformat!("<cyan>{}</>", path)
-    } else {
-        path.clone()
-    };
-    stdout.write_all(selection.as_bytes()).unwrap();
-
-    stdout
-        .queue(cursor::MoveRight((size1 - path.len() + 3) as u16))
-        .unwrap();
-    let selection = if select {
-        cformat!("<green>❮</>")
-    } else {
-        cformat!(" ")
-    };
-    stdout.write_all(selection.as_bytes()).unwrap();
-
-    stdout.queue(cursor::RestorePosition).unwrap();
-    stdout.flush().unwrap();
+        .map(|e| {
+            let path = match &e.link {
+                Some(link) => format!("{}{}", e.path, link_targets(link)),
+                None => e.path.clone(),
+            };
+            (e.change, path)
+        })
+        .collect();
+    get_change_table(&changes)
 }
 
-fn add_to_tracking_list(path: &String) {
-    let mut paths = file_to_vec(LIST.as_str());
-    for p in paths.iter() {
-        if path == p {
-            print_path_error("warn", "is already in the tracking list", path);
-            exit(2);
-        }
-        if path.contains(p) {
-            print_path_error(
-                "warn",
-                format!("entry exists at lower depth: '{p}'").as_str(),
-                path,
-            );
-            exit(2);
-        }
+/// Where a changed symlink points now, and where it pointed before.
+fn link_targets(link: &LinkChange) -> String {
+    let mut targets = String::new();
+    if let Some(to) = &link.to {
+        targets += &cformat!(" <dim>-></> {}", to);
     }
-    paths.retain(|p| !p.contains(path));
-    paths.push(path.clone());
-    fs::write(LIST.as_str(), paths.join("\n")).expect("Could not write new path to list.");
-}
-
-fn remove_from_tracking_list(path: &String) {
-    let mut paths = file_to_vec(LIST.as_str());
-    let size = paths.len();
-    paths.retain(|p| p != path);
-    if paths.len() == size {
-        print_path_error(
-            "error",
-            "did not match any files or folders in the tracking list",
-            path,
-        );
-        exit(2);
+    if let Some(from) = &link.from {
+        targets += &cformat!(" <dim>(was -> {})</>", from);
     }
-    fs::write(LIST.as_str(), paths.join("\n")).expect("Could not write new path to list.");
+    targets
 }
 
-fn get_status_table(status_lines: &[String], status_info: &mut StatusInfo) -> Table {
+fn get_change_table(changes: &[(Change, String)]) -> Table {
     let mut table = new_table();
     table.set_titles(Row::new(vec![
         Cell::new("status").style_spec("bFgc"),
         Cell::new("path").style_spec("bFgc"),
     ]));
 
-    let matches = ["A", "D", "M"];
-    let titles = ["new file", "deleted", "modified"];
     let specs = ["Fb", "Fr", "Fg"];
-    for line in status_lines.iter().cloned() {
-        for ((m, title), spec) in matches.iter().zip(titles.iter()).zip(specs.iter()) {
-            if !line[0..2].contains(m) {
-                continue;
-            }
-            let mut path = line
-                .split(' ')
-                .collect::<Vec<&str>>()
-                .last()
-                .unwrap()
-                .to_string();
-            path = cformat!("<dim>/</>{}", path);
-            status_info
-                .status_entries
-                .push((title.to_string(), path.clone()));
-            table.add_row(Row::new(vec![
-                Cell::new(title).style_spec(spec),
-                Cell::new(path.as_str()),
-            ]));
-        }
+    for (change, path) in changes.iter() {
+        let spec = specs[Change::ALL.iter().position(|c| c == change).unwrap()];
+        let title = change.title();
+        let path = cformat!("<dim>/</>{}", path);
+        table.add_row(Row::new(vec![
+            Cell::new(title).style_spec(spec),
+            Cell::new(path.as_str()),
+        ]));
     }
     table
 }
 
-fn print_tracking_list_table() {
-    let mut files: Vec<&str> = vec![];
-    let mut folders: Vec<&str> = vec![];
-    let paths = file_to_vec(LIST.as_str());
-    for path in paths.iter() {
-        let md = metadata(path).unwrap();
-        if md.is_dir() {
-            folders.push(&path[HOME.as_str().len() + 1..]);
-        } else {
-            files.push(&path[HOME.as_str().len() + 1..]);
+/// A node of the tree `dm --list` prints: a path component, the tracking entry at it if any,
+/// and the components below it.
+#[derive(Default)]
+struct TreeNode {
+    name: String,
+    entry: Option<EntryInfo>,
+    children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    /// The node at the HOME-relative `path`, created along with its parents if needed.
+    fn at(&mut self, path: &str) -> &mut TreeNode {
+        let mut node = self;
+        for part in path.split('/').filter(|p| !p.is_empty()) {
+            let i = match node.children.iter().position(|c| c.name == part) {
+                Some(i) => i,
+                None => {
+                    node.children.push(TreeNode {
+                        name: part.to_string(),
+                        ..TreeNode::default()
+                    });
+                    node.children.len() - 1
+                }
+            };
+            node = &mut node.children[i];
         }
+        node
     }
-    folders.sort();
-    files.sort();
 
-    let mut table = new_table();
-    table.set_titles(Row::new(vec![
-        Cell::new("#").style_spec("bFgr"),
-        Cell::new("folders").style_spec("bFgc"),
-        Cell::new("files").style_spec("bFgc"),
-    ]));
-
-    for i in 0..max(files.len(), folders.len()) {
-        let mut folder = String::new();
-        let mut file = String::new();
-        if i < folders.len() {
-            folder = cformat!(" <dim>/</>{}", folders[i]);
+    /// Flattens the tree into lines of box drawing prefix and node.
+    fn lines<'a>(&'a self, indent: &str, lines: &mut Vec<(String, &'a TreeNode)>) {
+        let mut children: Vec<&TreeNode> = self.children.iter().collect();
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+        for (i, child) in children.iter().enumerate() {
+            let last = i == children.len() - 1;
+            let branch = if last { "└── " } else { "├── " };
+            lines.push((format!("{indent}{branch}"), child));
+            let indent = format!("{indent}{}", if last { "    " } else { "│   " });
+            child.lines(&indent, lines);
         }
-        if i < files.len() {
-            file = cformat!(" <dim>/</>{}", files[i]);
+    }
+}
+
+/// Prints the tracking list as a tree below HOME with the type, tracked files, size, last
+/// commit and state of each entry. `expand` lists the files git tracks below folders. Once
+/// entries are tagged, there is a tree per tag and one of the untagged entries; `tag` prints
+/// only the tree of that tag.
+fn print_tracking_tree(repo: &DotRepo, expand: bool, tag: Option<&str>) {
+    let entries = or_exit(repo.entries());
+    let tagged = |tag: &str| -> Vec<EntryInfo> {
+        entries
+            .iter()
+            .filter(|e| e.tags.iter().any(|t| t == tag))
+            .cloned()
+            .collect()
+    };
+    if let Some(tag) = tag {
+        or_exit(repo.tagged(tag));
+        return print_entry_tree(repo, tagged(tag), expand, &format!("Tag {tag}:"));
+    }
+    let mut tags: Vec<&String> = entries.iter().flat_map(|e| e.tags.iter()).collect();
+    tags.sort();
+    tags.dedup();
+    if tags.is_empty() {
+        return print_entry_tree(repo, entries, expand, "Tracking:");
+    }
+    for (i, tag) in tags.iter().enumerate() {
+        if i > 0 {
+            println!();
         }
+        print_entry_tree(repo, tagged(tag), expand, &format!("Tag {tag}:"));
+    }
+    let untagged: Vec<EntryInfo> = entries
+        .iter()
+        .filter(|e| e.tags.is_empty())
+        .cloned()
+        .collect();
+    if !untagged.is_empty() {
+        println!();
+        print_entry_tree(repo, untagged, expand, "Untagged:");
+    }
+}
 
-        table.add_row(Row::new(vec![
-            Cell::new((i + 1).to_string().as_str()).style_spec("bFgr"),
-            Cell::new(folder.as_str()).style_spec("Fb"),
-            Cell::new(file.as_str()),
-        ]));
+/// Prints `entries` as a tree below HOME, followed by one below `/` of the system entries,
+/// under the heading `title`.
+fn print_entry_tree(repo: &DotRepo, entries: Vec<EntryInfo>, expand: bool, title: &str) {
+    let mut home = TreeNode::default();
+    let mut system = TreeNode::default();
+    for entry in entries {
+        let rel = repo.repo_path(&entry.path);
+        let node = match repo.is_system(&entry.path) {
+            true => system.at(&entry.path),
+            false => home.at(&rel),
+        };
+        if expand && entry.is_dir {
+            for file in entry.files.iter() {
+                node.at(file.strip_prefix(&rel).unwrap_or(file));
+            }
+        }
+        node.entry = Some(entry);
     }
+    let mut lines = vec![];
+    home.lines("", &mut lines);
+    let home_lines = lines.len();
+    system.lines("", &mut lines);
+    let width = lines
+        .iter()
+        .map(|(prefix, node)| prefix.chars().count() + node.name.chars().count() + 1)
+        .max()
+        .unwrap_or(0);
 
-    cprintln!("<bold> Tracking:</>");
-    table.printstd();
+    cprintln!("<bold> {}</>", title);
+    cprintln!(" <dim>~</>");
+    for (i, (prefix, node)) in lines.into_iter().enumerate() {
+        if i == home_lines {
+            cprintln!(" <dim>/</>");
+        }
+        let Some(entry) = &node.entry else {
+            let name = match node.children.is_empty() {
+                true => node.name.clone(),
+                false => format!("{}/", node.name),
+            };
+            cprintln!(" <dim>{}</>{}", prefix, name);
+            continue;
+        };
+        let name = format!("{}{}", node.name, if entry.is_dir { "/" } else { "" });
+        let pad = width - prefix.chars().count() - name.chars().count();
+        let state = match entry.state {
+            EntryState::Clean => cformat!("<green>{}</>", entry.state.title()),
+            EntryState::Modified => cformat!("<yellow>{}</>", entry.state.title()),
+            EntryState::Missing => cformat!("<red>{}</>", entry.state.title()),
+        };
+        let (kind, target) = match &entry.link {
+            Some(target) if entry.follow => ("link", cformat!("  <dim>-> {} (content)</>", target)),
+            Some(target) => ("link", cformat!("  <dim>-> {}</>", target)),
+            None if entry.is_dir => ("folder", String::new()),
+            None => ("file", String::new()),
+        };
+        cprintln!(
+            " <dim>{}</>{}{}  {:<6}  {:>9}  {:>9}  {:<11}  {}{}",
+            prefix,
+            name,
+            " ".repeat(pad),
+            kind,
+            format!(
+                "{} file{}",
+                entry.files.len(),
+                if entry.files.len() == 1 { "" } else { "s" }
+            ),
+            human_size(entry.size),
+            entry.last_commit.as_deref().unwrap_or("uncommitted"),
+            state,
+            target
+        );
+    }
 }
 
 fn get_status_summary_short(status_counts: &[i32]) -> String {
@@ -550,43 +1547,6 @@ fn get_status_summary(status_counts: &[i32]) -> String {
     output.trim_end().to_string()
 }
 
-fn get_status_counts(status_lines: &[String]) -> Vec<i32> {
-    let mut counts: Vec<i32> = vec![];
-    let matches = ["A", "D", "M"];
-    for m in matches {
-        counts.push(status_lines.iter().filter(|l| l[0..2].contains(m)).count() as i32);
-    }
-    counts
-}
-
-fn git_command_output(cmd: &str) -> String {
-    let args = format!(
-        "--git-dir={} --work-tree={} {}",
-        GIT.as_str(),
-        HOME.as_str(),
-        cmd
-    );
-    let split = split_cmd(args);
-    let output = Command::new("/bin/git")
-        .args(split)
-        .output()
-        .expect("failed to execute process");
-
-    String::from_utf8_lossy(&output.stdout).to_string()
-}
-
-fn git_command_spawn(cmd: &str) {
-    let args = format!(
-        "--git-dir={} --work-tree={} {}",
-        GIT.as_str(),
-        HOME.as_str(),
-        cmd
-    );
-    let split = split_cmd(args);
-    Command::new("/bin/git")
-        .args(split)
-        .spawn()
-        .expect("failed to spawn process")
-        .wait()
-        .expect("failed to execute process");
+fn get_status_counts(status: &Status) -> Vec<i32> {
+    status.counts().iter().map(|c| *c as i32).collect()
 }
@@ -0,0 +1,247 @@
+use crate::error::{Error, Result};
+use crate::git::Git;
+use crate::paths;
+use crate::repo::DotRepo;
+use crate::status::{Change, StatusEntry};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+/// Where the modes and empty folders are kept in the repository. Like the files of system
+/// entries, it lives in the index and commits only, never in HOME. It is updated whenever
+/// entries are staged, so it goes into every commit along with the files.
+pub const MODES_FILE: &str = ".dm/modes.toml";
+
+/// Permission bits of tracked files, owners of those of system entries, and the empty folders
+/// of tracking entries, none of which git keeps.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Modes {
+    #[serde(default, rename = "file")]
+    pub files: Vec<FileMode>,
+    #[serde(default, rename = "dir", skip_serializing_if = "Vec::is_empty")]
+    pub dirs: Vec<FileMode>,
+}
+
+/// The mode and owner of a file, keyed by its path in the repository.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileMode {
+    pub path: String,
+    /// Permission bits in octal, e.g. `"0644"`.
+    pub mode: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+impl Modes {
+    pub fn parse(text: &str) -> Result<Modes> {
+        toml::from_str(text).map_err(|e| Error::InvalidModes(e.message().to_string()))
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|e| Error::InvalidModes(e.to_string()))
+    }
+
+    pub fn get(&self, path: &str) -> Option<&FileMode> {
+        self.files.iter().find(|f| f.path == path)
+    }
+
+    /// Records `mode`, replacing what was known about its path, and keeps the files sorted.
+    pub fn set(&mut self, mode: FileMode) {
+        insert_sorted(&mut self.files, mode);
+    }
+
+    /// Records the empty folder `mode` describes, keeping the folders sorted.
+    pub fn set_dir(&mut self, mode: FileMode) {
+        insert_sorted(&mut self.dirs, mode);
+    }
+}
+
+fn insert_sorted(modes: &mut Vec<FileMode>, mode: FileMode) {
+    modes.retain(|f| f.path != mode.path);
+    let i = modes.partition_point(|f| f.path < mode.path);
+    modes.insert(i, mode);
+}
+
+/// A tracked file whose mode or owner in the index differs from the last commit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModeDrift {
+    pub recorded: FileMode,
+    pub current: FileMode,
+}
+
+impl DotRepo {
+    /// The modes recorded in the index of `git`, or in `rev` if given.
+    pub(crate) fn modes_in(&self, git: &Git, rev: Option<&str>) -> Result<Modes> {
+        let object = format!("{}:{MODES_FILE}", rev.unwrap_or(""));
+        match git.output_lossy(&["cat-file", "blob", &object])? {
+            text if text.is_empty() => Ok(Modes::default()),
+            text => Modes::parse(&text),
+        }
+    }
+
+    /// Stages `modes` as [`MODES_FILE`] in the index of `git`, unless they are staged already.
+    pub(crate) fn write_modes(&self, git: &Git, modes: &Modes) -> Result<()> {
+        if self.modes_in(git, None)? == *modes {
+            return Ok(());
+        }
+        let hash = git.output_with_input(
+            &["hash-object", "-w", "--stdin"],
+            modes.to_toml()?.as_bytes(),
+        )?;
+        let cacheinfo = format!("100644,{},{MODES_FILE}", hash.trim());
+        git.output(&["update-index", "--add", "--cacheinfo", &cacheinfo])?;
+        git.output(&["update-index", "--skip-worktree", "--", MODES_FILE])?;
+        Ok(())
+    }
+
+    /// Records the modes of the live files git tracks at or below the HOME path `entry` in the
+    /// index of `git`, and forgets those of files it no longer tracks there. Symlinks have no
+    /// mode of their own and are left out. While `entry` is in the tracking list, its empty
+    /// folders are recorded too, since git drops them.
+    pub(crate) fn record_modes(&self, git: &Git, entry: &str) -> Result<()> {
+        let prefix = self.repo_path(entry);
+        let mut modes = self.modes_in(git, None)?;
+        modes.files.retain(|f| !paths::is_within(&f.path, &prefix));
+        modes.dirs.retain(|d| !paths::is_within(&d.path, &prefix));
+        for file in self.indexed(git, &prefix)? {
+            let Ok(metadata) = fs::symlink_metadata(self.stored_path(&file)) else {
+                continue;
+            };
+            if !metadata.is_symlink() {
+                modes.set(FileMode {
+                    path: file,
+                    mode: format!("{:04o}", metadata.permissions().mode() & 0o7777),
+                    owner: None,
+                    group: None,
+                });
+            }
+        }
+        if self.tracking_list()?.entry_for(entry).is_some() {
+            let stored = self.stored_path(&prefix);
+            for dir in empty_dirs(&stored) {
+                let Ok(metadata) = fs::symlink_metadata(&dir) else {
+                    continue;
+                };
+                modes.set_dir(FileMode {
+                    path: format!("{prefix}{}", &dir[stored.len()..]),
+                    mode: format!("{:04o}", metadata.permissions().mode() & 0o7777),
+                    owner: None,
+                    group: None,
+                });
+            }
+        }
+        self.write_modes(git, &modes)
+    }
+
+    /// Gives the live HOME files the modes recorded in `rev`, or only those at or below the
+    /// repository path `prefix`, and creates the empty folders recorded there that are missing.
+    /// Returns the files and folders whose mode changed. System files get theirs when they are
+    /// written; see [`DotRepo::restore`].
+    pub(crate) fn apply_modes(&self, rev: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+        let mut changed = vec![];
+        let recorded = self.modes_in(self.git(), Some(rev))?;
+        let dirs = recorded.dirs.iter().map(|d| (d, true));
+        for (recorded, is_dir) in recorded.files.iter().map(|f| (f, false)).chain(dirs) {
+            if self.is_system(&self.live_path(&recorded.path))
+                || prefix.is_some_and(|p| !paths::is_within(&recorded.path, p))
+            {
+                continue;
+            }
+            let live = self.stored_path(&recorded.path);
+            if is_dir && fs::symlink_metadata(&live).is_err() {
+                fs::create_dir_all(&live)
+                    .map_err(|e| Error::io(format!("could not create '{live}'"), e))?;
+            }
+            let (Ok(metadata), Ok(mode)) = (
+                fs::symlink_metadata(&live),
+                u32::from_str_radix(&recorded.mode, 8),
+            ) else {
+                continue;
+            };
+            if metadata.is_symlink() || metadata.permissions().mode() & 0o7777 == mode {
+                continue;
+            }
+            fs::set_permissions(&live, fs::Permissions::from_mode(mode))
+                .map_err(|e| Error::io(format!("could not change the mode of '{live}'"), e))?;
+            changed.push(recorded.path.clone());
+        }
+        Ok(changed)
+    }
+
+    /// Files and empty folders whose mode or owner in the index differs from the last commit.
+    /// Those new to the repository are not drift.
+    pub(crate) fn mode_drift(&self) -> Result<Vec<ModeDrift>> {
+        let git = self.git();
+        if !git.succeeds(&["rev-parse", "-q", "--verify", "HEAD"])? {
+            return Ok(vec![]);
+        }
+        let committed = self.modes_in(git, Some("HEAD"))?;
+        let current = self.modes_in(git, None)?;
+        Ok(current
+            .files
+            .into_iter()
+            .chain(current.dirs)
+            .filter_map(|current| {
+                let recorded = committed
+                    .get(&current.path)
+                    .or_else(|| committed.dirs.iter().find(|d| d.path == current.path))?;
+                (*recorded != current).then(|| ModeDrift {
+                    recorded: recorded.clone(),
+                    current,
+                })
+            })
+            .collect())
+    }
+
+    /// Empty folders recorded in the index but not in the last commit, or the other way round.
+    /// A folder that still exists was only dropped because it is not empty anymore.
+    pub(crate) fn dir_changes(&self) -> Result<Vec<StatusEntry>> {
+        let git = self.git();
+        let committed = self.modes_in(git, Some("HEAD"))?.dirs;
+        let current = self.modes_in(git, None)?.dirs;
+        let change = |change, path: &str| StatusEntry {
+            change,
+            path: path.to_string(),
+            renamed_from: None,
+            staged: true,
+            unstaged: false,
+            link: None,
+        };
+        let mut changes: Vec<StatusEntry> = current
+            .iter()
+            .filter(|d| !committed.iter().any(|c| c.path == d.path))
+            .map(|d| change(Change::Added, &d.path))
+            .collect();
+        changes.extend(
+            committed
+                .iter()
+                .filter(|c| !current.iter().any(|d| d.path == c.path))
+                .filter(|c| fs::symlink_metadata(self.stored_path(&c.path)).is_err())
+                .map(|c| change(Change::Deleted, &c.path)),
+        );
+        Ok(changes)
+    }
+}
+
+/// The folders at or below `dir` that hold nothing at all. Symlinked folders are not entered.
+fn empty_dirs(dir: &str) -> Vec<String> {
+    let Ok(read) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut empty = vec![];
+    let mut children = 0;
+    for child in read.flatten() {
+        children += 1;
+        if child.file_type().is_ok_and(|t| t.is_dir()) {
+            empty.extend(empty_dirs(&child.path().to_string_lossy()));
+        }
+    }
+    if children == 0 {
+        empty.push(dir.to_string());
+    }
+    empty
+}
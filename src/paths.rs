@@ -0,0 +1,93 @@
+use crate::error::{Error, Result};
+use dirs::{data_dir, home_dir};
+use std::env;
+
+/// Overrides $HOME, e.g. to provision another user's home or a scratch directory.
+pub const HOME_ENV: &str = "DM_HOME";
+/// Overrides the dotmanager data directory ($XDG_DATA_HOME/dotmanager by default).
+pub const DATA_ENV: &str = "DM_DATA_DIR";
+
+/// The work-tree: `$DM_HOME`, falling back to the user's home directory.
+pub fn home() -> Result<String> {
+    match env_override(HOME_ENV) {
+        Some(p) => Ok(p),
+        None => match home_dir() {
+            Some(p) => Ok(p.to_string_lossy().to_string()),
+            None => Err(Error::MissingDirectory("$HOME")),
+        },
+    }
+}
+
+/// The dotmanager data directory: `$DM_DATA_DIR`, falling back to `$XDG_DATA_HOME/dotmanager`.
+pub fn data() -> Result<String> {
+    match env_override(DATA_ENV) {
+        Some(p) => Ok(p),
+        None => match data_dir() {
+            Some(mut p) => {
+                p.push("dotmanager");
+                Ok(p.to_string_lossy().to_string())
+            }
+            None => Err(Error::MissingDirectory("DATA directory")),
+        },
+    }
+}
+
+fn env_override(var: &str) -> Option<String> {
+    match env::var(var) {
+        Ok(p) if !p.trim().is_empty() => Some(trim_slash(p.trim()).to_string()),
+        _ => None,
+    }
+}
+
+/// Removes trailing slashes, leaving a lone `/` intact.
+pub fn trim_slash(path: &str) -> &str {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() && path.starts_with('/') {
+        "/"
+    } else {
+        trimmed
+    }
+}
+
+/// Whether `path` is `entry` itself or lies below it.
+pub fn is_within(path: &str, entry: &str) -> bool {
+    let path = trim_slash(path);
+    let entry = trim_slash(entry);
+    path == entry || entry == "/" || path.starts_with(&format!("{entry}/"))
+}
+
+/// Returns `path` relative to `base`, or `path` unchanged if it does not live under `base`.
+pub fn relative_to<'a>(path: &'a str, base: &str) -> &'a str {
+    let base = trim_slash(base).trim_end_matches('/');
+    match path.strip_prefix(base) {
+        Some("") => "",
+        Some(rest) if rest.starts_with('/') => &rest[1..],
+        _ => path,
+    }
+}
+
+/// Resolves a path given on the command line: absolute, `~`-relative or relative to `cwd`.
+/// `.` and `..` components are folded lexically, so the path need not exist.
+pub fn resolve(input: &str, home: &str, cwd: &str) -> String {
+    let joined = if input == "~" {
+        home.to_string()
+    } else if let Some(rest) = input.strip_prefix("~/") {
+        format!("{}/{rest}", trim_slash(home))
+    } else if input.starts_with('/') {
+        input.to_string()
+    } else {
+        format!("{}/{input}", trim_slash(cwd))
+    };
+
+    let mut parts: Vec<&str> = vec![];
+    for part in joined.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
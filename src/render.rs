@@ -0,0 +1,430 @@
+//! Turns [`FileDiff`]s into text for the terminal.
+
+use crate::diff::{DiffLine, FileDiff, FileStatus, Hunk, LineKind};
+
+const RESET: &str = "\u{1b}[0m";
+const BOLD: &str = "\u{1b}[1m";
+const DIM: &str = "\u{1b}[2m";
+const RED: &str = "\u{1b}[31m";
+const GREEN: &str = "\u{1b}[32m";
+const CYAN: &str = "\u{1b}[36m";
+const REVERSE: &str = "\u{1b}[7m";
+
+/// Lines longer than this many tokens are highlighted as a whole instead of word by word.
+const MAX_WORD_DIFF_TOKENS: usize = 256;
+
+/// A line split into runs of text, each flagged when it changed.
+pub type Segments = Vec<(String, bool)>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    Unified,
+    SideBySide,
+}
+
+#[derive(Clone, Debug)]
+pub struct Renderer {
+    pub layout: Layout,
+    /// Highlight the words that changed within modified lines.
+    pub word_diff: bool,
+    /// Emit ANSI colors; without them word changes are marked `[-old-]{+new+}`.
+    pub color: bool,
+    /// Total width available to the side-by-side layout.
+    pub width: usize,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer {
+            layout: Layout::Unified,
+            word_diff: false,
+            color: true,
+            width: 120,
+        }
+    }
+}
+
+impl Renderer {
+    /// A plain unified renderer, for output that does not go to a terminal.
+    pub fn plain() -> Renderer {
+        Renderer {
+            color: false,
+            ..Default::default()
+        }
+    }
+
+    pub fn render(&self, files: &[FileDiff]) -> String {
+        let mut output = String::new();
+        for file in files {
+            output += &self.render_file(file);
+        }
+        output
+    }
+
+    pub fn render_file(&self, file: &FileDiff) -> String {
+        let mut output = self.file_header(file);
+        for hunk in file.hunks.iter() {
+            output += &self.paint(CYAN, &hunk.header());
+            if !hunk.section.is_empty() {
+                output.push(' ');
+                output += &hunk.section;
+            }
+            output.push('\n');
+            match self.layout {
+                Layout::Unified => output += &self.unified_hunk(hunk),
+                Layout::SideBySide => output += &self.side_by_side_hunk(hunk),
+            }
+        }
+        output
+    }
+
+    fn file_header(&self, file: &FileDiff) -> String {
+        let old = file.old_path.as_deref().unwrap_or(file.path());
+        let new = file.new_path.as_deref().unwrap_or(file.path());
+        let mut output = self.paint(BOLD, &format!("diff --git a/{old} b/{new}"));
+        output.push('\n');
+
+        let mut meta = vec![];
+        match file.status {
+            FileStatus::Added => meta.push(format!(
+                "new file mode {}",
+                file.new_mode.as_deref().unwrap_or("100644")
+            )),
+            FileStatus::Deleted => meta.push(format!(
+                "deleted file mode {}",
+                file.old_mode.as_deref().unwrap_or("100644")
+            )),
+            FileStatus::Renamed | FileStatus::Copied => {
+                let verb = if file.status == FileStatus::Renamed {
+                    "renamed"
+                } else {
+                    "copied"
+                };
+                let similarity = file
+                    .similarity
+                    .map(|s| format!(" ({s}% similar)"))
+                    .unwrap_or_default();
+                meta.push(format!("{verb} {old} → {new}{similarity}"));
+            }
+            FileStatus::Modified => {}
+        }
+        if file.mode_changed() {
+            meta.push(format!(
+                "mode {} → {}",
+                file.old_mode.as_deref().unwrap_or_default(),
+                file.new_mode.as_deref().unwrap_or_default()
+            ));
+        }
+        if file.binary {
+            meta.push("binary file differs".to_string());
+        }
+        for m in meta {
+            output += &self.paint(BOLD, &m);
+            output.push('\n');
+        }
+        output
+    }
+
+    fn unified_hunk(&self, hunk: &Hunk) -> String {
+        let mut output = String::new();
+        for block in blocks(&hunk.lines) {
+            match block {
+                Block::Context(line) => {
+                    output += &format!(" {}\n", line.content);
+                    output += &no_newline_marker(line);
+                }
+                Block::Change(removed, added) => {
+                    let (old_segments, new_segments) = self.segments(&removed, &added);
+                    for (line, segments) in removed.iter().zip(old_segments.iter()) {
+                        output += &self.paint_line(RED, '-', segments);
+                        output += &no_newline_marker(line);
+                    }
+                    for (line, segments) in added.iter().zip(new_segments.iter()) {
+                        output += &self.paint_line(GREEN, '+', segments);
+                        output += &no_newline_marker(line);
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    fn side_by_side_hunk(&self, hunk: &Hunk) -> String {
+        let number_width = hunk
+            .lines
+            .iter()
+            .filter_map(|l| l.old_no.max(l.new_no))
+            .max()
+            .unwrap_or(0)
+            .to_string()
+            .len();
+        // Each side: number, a space, the text. The sides are separated by " │ ".
+        let column = (self.width.saturating_sub(3) / 2).max(number_width + 8);
+        let text_width = column - number_width - 1;
+
+        let mut output = String::new();
+        for block in blocks(&hunk.lines) {
+            match block {
+                Block::Context(line) => {
+                    let segments = vec![(expand_tabs(&line.content), false)];
+                    let left = self.side(line.old_no, &segments, "", number_width, text_width);
+                    let right = self.side(line.new_no, &segments, "", number_width, text_width);
+                    output += &format!("{left}{} {}\n", self.paint(DIM, " │"), right.trim_end());
+                }
+                Block::Change(removed, added) => {
+                    let (old_segments, new_segments) = self.segments(&removed, &added);
+                    for i in 0..removed.len().max(added.len()) {
+                        let left = match removed.get(i) {
+                            Some(line) => self.side(
+                                line.old_no,
+                                &old_segments[i],
+                                RED,
+                                number_width,
+                                text_width,
+                            ),
+                            None => " ".repeat(column),
+                        };
+                        let right = match added.get(i) {
+                            Some(line) => self.side(
+                                line.new_no,
+                                &new_segments[i],
+                                GREEN,
+                                number_width,
+                                text_width,
+                            ),
+                            None => String::new(),
+                        };
+                        output +=
+                            &format!("{left}{} {}\n", self.paint(DIM, " │"), right.trim_end());
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    /// One column of the side-by-side layout, padded to its full width.
+    fn side(
+        &self,
+        number: Option<usize>,
+        segments: &[(String, bool)],
+        color: &str,
+        number_width: usize,
+        text_width: usize,
+    ) -> String {
+        let number = match number {
+            Some(n) => format!("{n:>number_width$}"),
+            None => " ".repeat(number_width),
+        };
+        let (segments, used) = truncate(segments, text_width);
+        let mut text = String::new();
+        for (s, changed) in segments.iter() {
+            if *changed {
+                text += &self.highlight(color, s);
+            } else {
+                text += &self.paint(color, s);
+            }
+        }
+        format!(
+            "{} {}{}",
+            self.paint(DIM, &number),
+            text,
+            " ".repeat(text_width - used)
+        )
+    }
+
+    /// Splits paired removed/added lines into segments, flagging the words that changed.
+    fn segments(
+        &self,
+        removed: &[&DiffLine],
+        added: &[&DiffLine],
+    ) -> (Vec<Segments>, Vec<Segments>) {
+        let whole = |lines: &[&DiffLine]| -> Vec<Segments> {
+            lines
+                .iter()
+                .map(|l| vec![(expand_tabs(&l.content), false)])
+                .collect()
+        };
+        let mut old = whole(removed);
+        let mut new = whole(added);
+        if self.word_diff {
+            for i in 0..removed.len().min(added.len()) {
+                let (o, n) = word_diff(
+                    &expand_tabs(&removed[i].content),
+                    &expand_tabs(&added[i].content),
+                );
+                old[i] = o;
+                new[i] = n;
+            }
+        }
+        (old, new)
+    }
+
+    fn paint_line(&self, color: &str, sign: char, segments: &[(String, bool)]) -> String {
+        let mut text = String::new();
+        for (s, changed) in segments {
+            if !*changed {
+                text += s;
+            } else if self.color {
+                text += &format!("{REVERSE}{s}{RESET}{color}");
+            } else if sign == '-' {
+                text += &format!("[-{s}-]");
+            } else {
+                text += &format!("{{+{s}+}}");
+            }
+        }
+        format!("{}\n", self.paint(color, &format!("{sign}{text}")))
+    }
+
+    fn highlight(&self, color: &str, s: &str) -> String {
+        if self.color {
+            format!("{color}{REVERSE}{s}{RESET}")
+        } else if color == RED {
+            format!("[-{s}-]")
+        } else {
+            format!("{{+{s}+}}")
+        }
+    }
+
+    fn paint(&self, color: &str, s: &str) -> String {
+        if self.color && !color.is_empty() && !s.is_empty() {
+            format!("{color}{s}{RESET}")
+        } else {
+            s.to_string()
+        }
+    }
+}
+
+enum Block<'a> {
+    Context(&'a DiffLine),
+    /// A run of removed lines followed by the added lines replacing them.
+    Change(Vec<&'a DiffLine>, Vec<&'a DiffLine>),
+}
+
+fn blocks(lines: &[DiffLine]) -> Vec<Block<'_>> {
+    let mut blocks = vec![];
+    let mut i = 0;
+    while i < lines.len() {
+        if lines[i].kind == LineKind::Context {
+            blocks.push(Block::Context(&lines[i]));
+            i += 1;
+            continue;
+        }
+        let mut removed = vec![];
+        let mut added = vec![];
+        while i < lines.len() && lines[i].kind == LineKind::Removed {
+            removed.push(&lines[i]);
+            i += 1;
+        }
+        while i < lines.len() && lines[i].kind == LineKind::Added {
+            added.push(&lines[i]);
+            i += 1;
+        }
+        blocks.push(Block::Change(removed, added));
+    }
+    blocks
+}
+
+fn no_newline_marker(line: &DiffLine) -> String {
+    if line.no_newline {
+        "\\ No newline at end of file\n".to_string()
+    } else {
+        String::new()
+    }
+}
+
+fn expand_tabs(s: &str) -> String {
+    s.replace('\t', "    ")
+}
+
+/// Cuts segments down to `width` characters, ending in `…` when something was dropped.
+fn truncate(segments: &[(String, bool)], width: usize) -> (Segments, usize) {
+    let total: usize = segments.iter().map(|(s, _)| s.chars().count()).sum();
+    if total <= width {
+        return (segments.to_vec(), total);
+    }
+    let mut left = width.saturating_sub(1);
+    let mut out = vec![];
+    for (s, changed) in segments {
+        if left == 0 {
+            break;
+        }
+        let part: String = s.chars().take(left).collect();
+        left -= part.chars().count();
+        out.push((part, *changed));
+    }
+    out.push(("…".to_string(), false));
+    (out, width)
+}
+
+fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = vec![];
+    let mut current = String::new();
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            0
+        } else if c.is_whitespace() {
+            1
+        } else {
+            2
+        }
+    };
+    for c in s.chars() {
+        if let Some(last) = current.chars().last() {
+            if class(last) != class(c) || class(c) == 2 {
+                tokens.push(std::mem::take(&mut current));
+            }
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Compares two lines token by token and returns both split into (text, changed) segments.
+pub fn word_diff(old: &str, new: &str) -> (Segments, Segments) {
+    let a = tokenize(old);
+    let b = tokenize(new);
+    if a.len() > MAX_WORD_DIFF_TOKENS || b.len() > MAX_WORD_DIFF_TOKENS {
+        return (vec![(old.to_string(), true)], vec![(new.to_string(), true)]);
+    }
+
+    // Longest common subsequence table, filled from the end.
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut old_segments: Segments = vec![];
+    let mut new_segments: Segments = vec![];
+    let push = |segments: &mut Vec<(String, bool)>, token: &str, changed: bool| match segments
+        .last_mut()
+    {
+        Some((s, c)) if *c == changed => s.push_str(token),
+        _ => segments.push((token.to_string(), changed)),
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            push(&mut old_segments, &a[i], false);
+            push(&mut new_segments, &b[j], false);
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            push(&mut new_segments, &b[j], true);
+            j += 1;
+        } else {
+            push(&mut old_segments, &a[i], true);
+            i += 1;
+        }
+    }
+    (old_segments, new_segments)
+}
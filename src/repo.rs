@@ -0,0 +1,1800 @@
+use crate::config::{Config, SnapshotPolicy};
+use crate::diff::{self, FileDiff};
+use crate::discover::{self, Candidate};
+use crate::entry::{Coverage, EntryInfo, EntryState, PathInfo};
+use crate::error::{Error, Result};
+use crate::farm::{self, Link};
+use crate::git::{Git, GIT_BIN};
+use crate::journal::{Journal, Operation};
+use crate::log::{self, LogEntry, HOST_TRAILER};
+use crate::modes::MODES_FILE;
+use crate::paths::{self, relative_to, trim_slash};
+use crate::snapshot::{self, Snapshot, AUTO_PREFIX, RETURN_REF, SNAPSHOT_REFS};
+use crate::status::{Change, Status};
+use crate::system::SYSTEM_PREFIX;
+use crate::tracking::{Entry, TrackingList};
+use std::{
+    cell::Cell,
+    fs::{self, metadata},
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Folders that hold the files of many applications and are never tracked as a whole.
+const SHARED_DIRS: [&str; 4] = [".config", ".local", ".local/share", ".local/state"];
+
+/// What a diff compares against.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiffTarget {
+    /// The last commit against the index, which mirrors the live files after staging.
+    Staged,
+    /// The index against the live files: changes that are not staged.
+    Unstaged,
+    /// A commit against the live files.
+    Revision(String),
+    /// The upstream branch, freshly fetched, against the live files.
+    Upstream,
+    /// Two commits against each other.
+    Between(String, String),
+}
+/// The outcome of [`DotRepo::restore`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Restored {
+    /// HOME-relative paths written back from the revision.
+    pub files: Vec<String>,
+    /// Directory holding copies of the live files that were overwritten, if any were.
+    pub backup: Option<String>,
+}
+
+/// The outcome of [`DotRepo::checkout_at`] and [`DotRepo::checkout_return`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checkout {
+    /// The commit or snapshot now in HOME.
+    pub commit: String,
+    /// The snapshot of the state that was replaced, or the one that was brought back.
+    pub snapshot: Snapshot,
+    /// HOME-relative paths that changed, with how they changed.
+    pub changes: Vec<(Change, String)>,
+}
+
+/// Part of the staged changes to include in a partial commit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Pick {
+    /// Every staged change of a HOME-relative path.
+    File(String),
+    /// Only the hunks at the given indices of a staged file diff.
+    Hunks(FileDiff, Vec<usize>),
+}
+
+/// A dotfile repository: a bare git repository in the data directory whose work-tree is HOME,
+/// together with the tracking list that decides what gets staged. In symlink-farm mode the
+/// work-tree is a separate checkout instead, and HOME holds symlinks into it.
+#[derive(Clone, Debug)]
+pub struct DotRepo {
+    home: String,
+    data: String,
+    list: String,
+    git: Git,
+    /// What the running operation did besides changing files, for the journal.
+    effects: Cell<Effects>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Effects {
+    committed: bool,
+    pushed: bool,
+}
+
+/// The outcome of [`DotRepo::undo`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Undone {
+    pub operation: Operation,
+    /// What undo did beyond restoring the list, index and files, or left alone.
+    pub notes: Vec<String>,
+}
+
+impl DotRepo {
+    /// Opens the repository at the default locations, honouring `DM_HOME`/`DM_DATA_DIR`.
+    pub fn open() -> Result<DotRepo> {
+        Ok(DotRepo::with_paths(&paths::home()?, &paths::data()?))
+    }
+
+    pub fn with_paths(home: &str, data: &str) -> DotRepo {
+        let home = trim_slash(home).to_string();
+        let data = trim_slash(data).to_string();
+        let work_tree = farm::farm_dir(&data).unwrap_or_else(|| home.clone());
+        DotRepo {
+            git: Git::new(&format!("{data}/git"), &work_tree),
+            list: format!("{data}/list"),
+            home,
+            data,
+            effects: Cell::new(Effects::default()),
+        }
+    }
+
+    pub fn home(&self) -> &str {
+        &self.home
+    }
+
+    pub fn data_dir(&self) -> &str {
+        &self.data
+    }
+
+    pub fn git_dir(&self) -> &str {
+        self.git.git_dir()
+    }
+
+    /// HOME, or in symlink-farm mode the checkout HOME links into.
+    pub fn work_tree(&self) -> &str {
+        self.git.work_tree()
+    }
+
+    pub fn list_file(&self) -> &str {
+        &self.list
+    }
+
+    /// `GIT_DIR` and `GIT_WORK_TREE` for running other git tools on the repository.
+    pub fn git_env(&self) -> [(&'static str, String); 2] {
+        [
+            ("GIT_DIR", self.git_dir().to_string()),
+            ("GIT_WORK_TREE", self.work_tree().to_string()),
+        ]
+    }
+
+    pub fn config_file(&self) -> String {
+        format!("{}/config.toml", self.data)
+    }
+
+    pub fn config(&self) -> Result<Config> {
+        Config::load(&self.config_file())
+    }
+
+    pub fn git(&self) -> &Git {
+        &self.git
+    }
+
+    /// Returns `path` relative to HOME, or unchanged if it lies elsewhere.
+    pub fn home_relative<'a>(&self, path: &'a str) -> &'a str {
+        relative_to(path, &self.home)
+    }
+
+    /// Turns a HOME-relative repository path into an absolute one.
+    pub fn absolute(&self, rel: &str) -> String {
+        format!("{}/{}", self.home, rel)
+    }
+
+    /// Turns a repository path into an absolute one in the work-tree.
+    pub(crate) fn tree_path(&self, rel: &str) -> String {
+        format!("{}/{}", self.work_tree(), rel)
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        metadata(format!("{}/HEAD", self.git_dir())).is_ok()
+    }
+
+    fn ensure_initialized(&self) -> Result<()> {
+        if self.is_initialized() {
+            Ok(())
+        } else {
+            Err(Error::NotInitialized(self.data.clone()))
+        }
+    }
+
+    /// Creates the bare repository and tracking list, commits `$HOME/.github/README.md` and
+    /// pushes it to `repo_url`. In symlink-farm mode the README goes into the checkout, which
+    /// becomes the work-tree of the repository.
+    pub fn init(&self, repo_url: &str) -> Result<()> {
+        create_dir(self.git_dir())?;
+        if metadata(&self.list).is_err() {
+            fs::File::create(&self.list)
+                .map_err(|e| Error::io("Could not create the tracking list", e))?;
+        }
+        let readme_dir = format!("{}/.github", self.work_tree());
+        create_dir(&readme_dir)?;
+        let readme_path = format!("{readme_dir}/README.md");
+        if metadata(&readme_path).is_err() {
+            fs::File::create(&readme_path)
+                .map_err(|e| Error::io(format!("Could not create {readme_path}"), e))?;
+        }
+
+        let output = Command::new(GIT_BIN)
+            .args(["init", "--quiet", "--bare", self.git_dir()])
+            .output()
+            .map_err(|e| Error::io("failed to execute git", e))?;
+        if !output.status.success() {
+            return Err(Error::Git {
+                command: "init --bare".to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            });
+        }
+        if self.is_farm() {
+            self.set_up_checkout()?;
+        }
+        self.git
+            .output(&["config", "--local", "status.showUntrackedFiles", "no"])?;
+        self.enable_sparse()?;
+        self.git.output(&["branch", "-M", "main"])?;
+        self.git.output(&["remote", "add", "origin", repo_url])?;
+        self.git.output(&["add", &readme_path])?;
+        self.git.output(&[
+            "commit",
+            "-m",
+            "Initial commit",
+            "--trailer",
+            &host_trailer(),
+        ])?;
+        self.git.output(&["push", "-u", "origin", "main"])?;
+        Ok(())
+    }
+
+    pub fn tracking_list(&self) -> Result<TrackingList> {
+        TrackingList::load(&self.list)
+    }
+
+    /// Adds a file or folder to the tracking list and stages it. Paths outside HOME become
+    /// system entries, kept below [`SYSTEM_PREFIX`](crate::SYSTEM_PREFIX) in the repository.
+    /// In symlink-farm mode, paths in HOME are moved into the checkout and linked back.
+    pub fn track(&self, path: &str) -> Result<()> {
+        self.ensure_initialized()?;
+        check_path_exists(path)?;
+        let tree = self.work_tree();
+        if self.is_farm() && (paths::is_within(path, tree) || paths::is_within(tree, path)) {
+            return Err(Error::InFarm(trim_slash(path).to_string()));
+        }
+        let mut list = self.tracking_list()?;
+        list.insert(path)?;
+        if self.is_farm() && !self.is_system(path) {
+            self.adopt(path)?;
+        }
+        list.save(&self.list)?;
+        self.stage_entry(path)
+    }
+
+    /// Stages the live state of a tracking entry.
+    pub(crate) fn stage_entry(&self, path: &str) -> Result<()> {
+        self.stage_entry_in(&self.git, path)
+    }
+
+    /// Stages the live state of a tracking entry into the index of `git`.
+    pub(crate) fn stage_entry_in(&self, git: &Git, path: &str) -> Result<()> {
+        let excluded = self.excluded(path)?;
+        if self.is_system(path) {
+            self.stage_system(git, path)?;
+            return self.drop_excluded(git, &excluded);
+        }
+        if self.is_followed(path)? {
+            return self.stage_followed(git, path);
+        }
+        let mut args: Vec<String> = ["add", "-A", "--"].iter().map(|a| a.to_string()).collect();
+        args.push(self.pathspec(path));
+        args.extend(excluded.iter().map(|g| format!(":(exclude,glob){g}")));
+        let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+        git.output(&args)?;
+        self.drop_excluded(git, &excluded)?;
+        self.record_modes(git, path)
+    }
+
+    /// The `exclude` patterns of the tracking entry at `path` as glob pathspecs.
+    fn excluded(&self, path: &str) -> Result<Vec<String>> {
+        Ok(match self.tracking_list()?.get(path) {
+            Some(entry) => entry.excluded(&self.repo_path(path)),
+            None => vec![],
+        })
+    }
+
+    /// Removes the files matching the `excluded` globs from the index of `git`, with their
+    /// modes, for those staged before the entry excluded them.
+    fn drop_excluded(&self, git: &Git, excluded: &[String]) -> Result<()> {
+        if excluded.is_empty() {
+            return Ok(());
+        }
+        let globs: Vec<String> = excluded.iter().map(|g| format!(":(glob){g}")).collect();
+        let mut args = vec!["ls-files", "--full-name", "-z", "--"];
+        args.extend(globs.iter().map(|g| g.as_str()));
+        let files = split_z(&git.output(&args)?);
+        if files.is_empty() {
+            return Ok(());
+        }
+        let mut args = vec!["update-index", "--force-remove", "--"];
+        args.extend(files.iter().map(|f| f.as_str()));
+        git.output(&args)?;
+        let mut modes = self.modes_in(git, None)?;
+        modes.files.retain(|f| !files.contains(&f.path));
+        self.write_modes(git, &modes)
+    }
+
+    /// Adds `tags` to the tracking entry at `path`.
+    pub fn tag(&self, path: &str, tags: &[&str]) -> Result<()> {
+        self.ensure_initialized()?;
+        let mut list = self.tracking_list()?;
+        list.tag(path, tags)?;
+        list.save(&self.list)
+    }
+
+    /// Sets whether the repository keeps the content of the target of the symlink entry at
+    /// `path` rather than the link itself, and stages the entry again. Only symlinks to files
+    /// can be followed.
+    pub fn set_follow(&self, path: &str, follow: bool) -> Result<()> {
+        self.ensure_initialized()?;
+        let path = trim_slash(path);
+        let is_link = fs::symlink_metadata(path).is_ok_and(|m| m.is_symlink());
+        if !is_link || (follow && !fs::metadata(path).is_ok_and(|m| m.is_file())) {
+            return Err(Error::NotAFileLink(path.to_string()));
+        }
+        let mut list = self.tracking_list()?;
+        if list.get(path).is_some_and(|e| e.follow == follow) {
+            return Ok(());
+        }
+        list.set_follow(path, follow)?;
+        list.save(self.list_file())?;
+        self.enable_sparse()?;
+        let rel = self.repo_path(path);
+        if !follow {
+            self.git
+                .output(&["update-index", "--force-remove", "--", &rel])?;
+        }
+        // A followed link stays in HOME; any other is a link into the checkout in farm mode.
+        if self.is_farm() && !self.is_system(path) {
+            match follow {
+                true => self.release(path)?,
+                false => self.adopt(path)?,
+            }
+        }
+        self.stage_entry(path)
+    }
+
+    /// The paths of the tracking entries tagged `tag`, or [`Error::UnknownTag`] if there are
+    /// none.
+    pub fn tagged(&self, tag: &str) -> Result<Vec<String>> {
+        let list = self.tracking_list()?;
+        let paths: Vec<String> = list.tagged(tag).iter().map(|e| e.path.clone()).collect();
+        match paths.is_empty() {
+            true => Err(Error::UnknownTag(tag.to_string())),
+            false => Ok(paths),
+        }
+    }
+
+    /// Type, files, size, last commit, state and tags of every tracking entry. Entries that
+    /// vanished from HOME are reported as missing rather than dropped.
+    pub fn entries(&self) -> Result<Vec<EntryInfo>> {
+        self.ensure_initialized()?;
+        let has_commits = self
+            .git
+            .output(&["rev-parse", "-q", "--verify", "HEAD"])
+            .is_ok();
+        let mut entries = vec![];
+        for Entry {
+            path, tags, follow, ..
+        } in self.tracking_list()?.entries
+        {
+            let pathspec = self.pathspec(&path);
+            let files =
+                split_z(
+                    &self
+                        .git
+                        .output(&["ls-files", "--full-name", "-z", "--", &pathspec])?,
+                );
+            let rel = self.repo_path(&path);
+            let source = self.source_path(&path)?;
+            let metadata = fs::symlink_metadata(&source).ok();
+            let state = self.state_of(&path)?;
+            let last_commit = match has_commits {
+                true => Some(
+                    self.git
+                        .output(&["log", "-1", "--format=%as", "--", &pathspec])?,
+                )
+                .map(|d| d.trim().to_string())
+                .filter(|d| !d.is_empty()),
+                false => None,
+            };
+            let size = files
+                .iter()
+                .filter_map(|f| self.source_path(&self.live_path(f)).ok())
+                .filter_map(|f| fs::symlink_metadata(f).ok())
+                .map(|m| m.len())
+                .sum();
+            let link = fs::read_link(&source)
+                .ok()
+                .map(|t| t.to_string_lossy().to_string());
+            entries.push(EntryInfo {
+                is_dir: match &metadata {
+                    Some(m) => m.is_dir(),
+                    None => files.iter().any(|f| *f != rel),
+                },
+                path,
+                state,
+                files,
+                size,
+                last_commit,
+                tags,
+                link,
+                follow,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Whether `path` differs from the last commit, or is gone.
+    fn state_of(&self, path: &str) -> Result<EntryState> {
+        if self.is_system(path) || self.is_followed(path)? {
+            return self.scratch_state(path);
+        }
+        if fs::symlink_metadata(self.source_path(path)?).is_err() {
+            return Ok(EntryState::Missing);
+        }
+        let mut args: Vec<String> = ["status", "--porcelain", "-z", "-uall", "--"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        args.push(self.pathspec(path));
+        let excluded = self.excluded(path)?;
+        args.extend(excluded.iter().map(|g| format!(":(exclude,glob){g}")));
+        let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+        let porcelain = self.git.output(&args)?;
+        Ok(match porcelain.is_empty() {
+            true => EntryState::Clean,
+            false => EntryState::Modified,
+        })
+    }
+
+    /// The tracking entry that covers `path`, or why none does. `path` need not exist.
+    pub fn which(&self, path: &str) -> Result<Coverage> {
+        self.ensure_initialized()?;
+        let path = trim_slash(path);
+        if paths::is_within(path, &self.data) {
+            return Ok(Coverage::DataDir);
+        }
+        let entry = self
+            .tracking_list()?
+            .entry_for(path)
+            .map(|e| e.path.clone());
+        if self.is_system(path) {
+            return Ok(entry.map_or(Coverage::OutsideHome, Coverage::Tracked));
+        }
+        let Some(entry) = entry else {
+            return Ok(Coverage::NotTracked);
+        };
+        let pathspec = self.pathspec(path);
+        let check =
+            self.git
+                .output_lossy(&["check-ignore", "-v", "--no-index", "--", &pathspec])?;
+        match check.split_once('\t') {
+            Some((rule, _)) if self.git.output(&["ls-files", "--", &pathspec])?.is_empty() => {
+                let rule = match rule.split_once(':') {
+                    Some((source, rest)) => {
+                        format!("{}:{rest}", relative_to(source, self.work_tree()))
+                    }
+                    None => rule.to_string(),
+                };
+                Ok(Coverage::Ignored { entry, rule })
+            }
+            _ => Ok(Coverage::Tracked(entry)),
+        }
+    }
+
+    /// Coverage, state, size, mode and history of `path`.
+    pub fn info(&self, path: &str) -> Result<PathInfo> {
+        let path = trim_slash(path).to_string();
+        let coverage = self.which(&path)?;
+        let metadata = fs::symlink_metadata(self.source_path(&path)?).ok();
+        let indexed = split_z(&self.git.output(&[
+            "ls-files",
+            "--full-name",
+            "-s",
+            "-z",
+            "--",
+            &self.pathspec(&path),
+        ])?);
+        if metadata.is_none() && indexed.is_empty() {
+            return Err(Error::PathNotFound(path));
+        }
+        let rel = self.repo_path(&path);
+        let files: Vec<&str> = indexed
+            .iter()
+            .filter_map(|line| line.split_once('\t').map(|(_, file)| file))
+            .collect();
+        let index_mode = indexed
+            .iter()
+            .find(|line| line.ends_with(&format!("\t{rel}")))
+            .and_then(|line| line.split(' ').next())
+            .map(|mode| mode.to_string());
+        let is_dir = match &metadata {
+            Some(m) => m.is_dir(),
+            None => files.iter().any(|f| *f != rel),
+        };
+        let size = match &metadata {
+            Some(m) if !m.is_dir() => m.len(),
+            _ => files
+                .iter()
+                .filter_map(|f| self.source_path(&self.live_path(f)).ok())
+                .filter_map(|f| fs::symlink_metadata(f).ok())
+                .map(|m| m.len())
+                .sum(),
+        };
+        let log = match coverage.entry().is_some() || !files.is_empty() {
+            true => self.log(Some(&path), None)?,
+            false => vec![],
+        };
+        let mut hosts: Vec<String> = vec![];
+        for entry in log.iter().filter(|e| !e.host.is_empty()) {
+            if !hosts.contains(&entry.host) {
+                hosts.push(entry.host.clone());
+            }
+        }
+        let list = self.tracking_list()?;
+        let entry = coverage.entry().and_then(|e| list.get(e));
+        Ok(PathInfo {
+            encrypt: entry.is_some_and(|e| e.encrypt),
+            template: entry.is_some_and(|e| e.template),
+            variants: entry.map(|e| e.hosts.clone()).unwrap_or_default(),
+            state: match coverage.entry().is_some() || !files.is_empty() {
+                true => Some(self.state_of(&path)?),
+                false => None,
+            },
+            mode: metadata.as_ref().map(|m| m.permissions().mode() & 0o7777),
+            files: files.len(),
+            last_commit: log.into_iter().next(),
+            path,
+            coverage,
+            is_dir,
+            size,
+            index_mode,
+            hosts,
+        })
+    }
+
+    /// Likely dotfiles in HOME and `~/.config`, for picking what to track. The data
+    /// directory and the checkout of symlink-farm mode are left out when they live in HOME.
+    pub fn discover(&self) -> Result<Vec<Candidate>> {
+        self.ensure_initialized()?;
+        let list = self.tracking_list()?;
+        let mut candidates = discover::discover(&self.home, &list);
+        candidates.retain(|c| {
+            !paths::is_within(&self.data, &c.path) && !paths::is_within(self.work_tree(), &c.path)
+        });
+        Ok(candidates)
+    }
+
+    /// Removes a file or folder from the tracking list and stages its removal. In
+    /// symlink-farm mode the files move from the checkout back into HOME.
+    pub fn untrack(&self, path: &str) -> Result<()> {
+        self.ensure_initialized()?;
+        check_path_exists(path)?;
+        let mut list = self.tracking_list()?;
+        let followed = self.is_followed(path)?;
+        list.remove(path)?;
+        let rel = self.home_relative(trim_slash(path));
+        self.auto_snapshot(&format!("before remove {rel}"), None)?;
+        list.save(&self.list)?;
+        if self.is_system(path) {
+            self.unstage_system(path)?;
+        } else if followed {
+            // Git stays out of the sparse path, so the link goes the way `git rm` removes one.
+            self.git
+                .output(&["update-index", "--force-remove", "--", rel])?;
+            fs::remove_file(trim_slash(path))
+                .map_err(|e| Error::io(format!("could not remove '{rel}'"), e))?;
+            self.enable_sparse()?;
+        } else if self.is_farm() {
+            // The files are the user's own, they go back into HOME in place of the link.
+            self.git
+                .output(&["rm", "-rq", "--cached", "--", &self.pathspec(path)])?;
+            self.record_modes(&self.git, path)?;
+            self.release(path)?;
+        } else {
+            self.git.output(&["rm", "-rfq", &self.pathspec(path)])?;
+            self.record_modes(&self.git, path)?;
+        }
+        Ok(())
+    }
+
+    /// Reconstructs a minimal tracking list from the files in the index, or in HEAD if the
+    /// index is empty. Each file is covered by its highest folder below HOME that holds no
+    /// untracked, unignored files, or by itself if there is none. Folders shared by many
+    /// applications, like `~/.config`, never become entries. Files of system entries are
+    /// covered by the current entry above them, or by themselves, and so are recorded empty
+    /// folders. Entries that stay keep their metadata. Nothing is written.
+    pub fn rebuild_list(&self) -> Result<TrackingList> {
+        self.ensure_initialized()?;
+        let own = |f: &String| f != ".github/README.md" && f != MODES_FILE;
+        let mut files = split_z(&self.git.output(&["ls-files", "--full-name", "-z"])?);
+        files.retain(own);
+        if files.is_empty() {
+            files = split_z(&self.git.output_lossy(&[
+                "ls-tree",
+                "-r",
+                "-z",
+                "--name-only",
+                "--full-tree",
+                "HEAD",
+            ])?);
+            files.retain(own);
+        }
+        // Files of system entries become entries of their own.
+        let (system, files): (Vec<String>, Vec<String>) = files
+            .into_iter()
+            .partition(|f| f.starts_with(&format!("{SYSTEM_PREFIX}/")));
+
+        let mut tops: Vec<String> = vec![];
+        for file in files.iter() {
+            if let Some((top, _)) = file.split_once('/') {
+                if !tops.iter().any(|t| t == top) {
+                    tops.push(top.to_string());
+                }
+            }
+        }
+        let untracked = match tops.is_empty() {
+            true => vec![],
+            false => {
+                let mut args = vec![
+                    "ls-files",
+                    "--full-name",
+                    "-o",
+                    "--exclude-standard",
+                    "-z",
+                    "--",
+                ];
+                let tops: Vec<String> = tops.iter().map(|t| self.tree_path(t)).collect();
+                args.extend(tops.iter().map(|t| t.as_str()));
+                let mut untracked = split_z(&self.git.output(&args)?);
+                // The checkout of symlink-farm mode holds only the repository's files, the
+                // ones an entry would hide behind its link are in HOME.
+                if self.is_farm() {
+                    for top in tops.iter() {
+                        let live = self.absolute(relative_to(top, self.work_tree()));
+                        if fs::symlink_metadata(&live).is_ok_and(|m| m.is_dir()) {
+                            untracked.extend(
+                                walk_files(&live)?
+                                    .iter()
+                                    .map(|f| self.home_relative(f).to_string()),
+                            );
+                        }
+                    }
+                }
+                // Files only in HEAD count as tracked when the index is empty.
+                untracked.retain(|u| !files.contains(u));
+                untracked
+            }
+        };
+
+        // Entries that stay keep their metadata; a list that does not parse is what a
+        // rebuild recovers from.
+        let current = self.tracking_list().unwrap_or_default();
+        let mut list = TrackingList::default();
+        for file in files.iter().chain(system.iter()) {
+            if system.contains(file) {
+                let live = self.live_path(file);
+                let entry = match current.entry_for(&live) {
+                    Some(kept) => kept.clone(),
+                    None => Entry::new(&live),
+                };
+                if !list.contains(&entry.path) {
+                    list.entries.push(entry);
+                }
+                continue;
+            }
+            let parts: Vec<&str> = file.split('/').collect();
+            let entry = (1..parts.len())
+                .map(|depth| parts[..depth].join("/"))
+                .filter(|dir| !SHARED_DIRS.contains(&dir.as_str()))
+                .find(|dir| {
+                    let prefix = format!("{dir}/");
+                    !untracked.iter().any(|u| u.starts_with(&prefix))
+                })
+                .unwrap_or_else(|| file.clone());
+            let entry = self.absolute(&entry);
+            if !list.contains(&entry) {
+                list.entries.push(match current.get(&entry) {
+                    Some(kept) => kept.clone(),
+                    None => Entry::new(&entry),
+                });
+            }
+        }
+        // Git drops empty folders, so they only show up in the recorded modes.
+        let mut dirs = self.modes_in(&self.git, None)?.dirs;
+        if dirs.is_empty() {
+            dirs = self.modes_in(&self.git, Some("HEAD"))?.dirs;
+        }
+        for dir in dirs {
+            let live = self.live_path(&dir.path);
+            if list.entry_for(&live).is_none() {
+                list.entries.push(match current.get(&live) {
+                    Some(kept) => kept.clone(),
+                    None => Entry::new(&live),
+                });
+            }
+        }
+        Ok(list)
+    }
+
+    /// Replaces the tracking list, e.g. with one from [`DotRepo::rebuild_list`].
+    pub fn save_list(&self, list: &TrackingList) -> Result<()> {
+        list.save(&self.list)
+    }
+
+    /// Stages every entry of the tracking list that applies to this host. Entries that no
+    /// longer exist are dropped from the list and returned.
+    pub fn stage_all(&self) -> Result<Vec<String>> {
+        self.ensure_initialized()?;
+        let mut list = self.tracking_list()?;
+        let host = log::hostname();
+        let mut vanished = vec![];
+        let mut kept = vec![];
+        for entry in list.entries {
+            // Entries of other hosts are neither staged nor expected to exist here.
+            if !entry.applies_to(&host) {
+                kept.push(entry);
+                continue;
+            }
+            match fs::symlink_metadata(self.source_path(&entry.path)?) {
+                Ok(_) => kept.push(entry),
+                Err(_) => vanished.push(entry.path),
+            }
+        }
+        list.entries = kept;
+        if !vanished.is_empty() {
+            list.save(&self.list)?;
+        }
+        for entry in list.entries.iter().filter(|e| e.applies_to(&host)) {
+            self.stage_entry(&entry.path)?;
+        }
+        Ok(vanished)
+    }
+
+    pub fn remote_url(&self) -> Result<String> {
+        Ok(self
+            .git
+            .output_lossy(&["remote", "get-url", "--all", "origin"])?
+            .trim()
+            .to_string())
+    }
+
+    /// Differences between the last commit and the index.
+    pub fn status(&self) -> Result<Status> {
+        self.ensure_initialized()?;
+        let porcelain = self.git.output(&["status", "--porcelain", "-z"])?;
+        let mut entries = Status::parse_entries(&porcelain);
+        for (path, change) in self.link_changes()? {
+            if let Some(entry) = entries.iter_mut().find(|e| e.path == path) {
+                entry.link = Some(change);
+            }
+        }
+        entries.extend(self.dir_changes()?);
+        Ok(Status {
+            remote_url: self.remote_url()?,
+            entries,
+            drift: self.mode_drift()?,
+        })
+    }
+
+    /// HOME-relative paths with staged changes, leaving out the recorded modes.
+    pub fn changed_files(&self) -> Result<Vec<String>> {
+        self.ensure_initialized()?;
+        let names = self
+            .git
+            .output(&["diff", "--cached", "--name-only", "-z"])?;
+        Ok(names
+            .split('\0')
+            .filter(|n| !n.is_empty() && *n != MODES_FILE)
+            .map(|n| n.to_string())
+            .collect())
+    }
+
+    /// The staged diff of a single file. A rename is reported as one change when `path` is
+    /// either side of it.
+    pub fn diff(&self, path: &str) -> Result<FileDiff> {
+        self.ensure_initialized()?;
+        let rel = self.repo_path(path);
+        let mut pathspecs = vec![self.tree_path(&rel)];
+        if let Some(other) = self.rename_partner(&rel)? {
+            pathspecs.push(self.tree_path(&other));
+        }
+
+        let mut args = vec![
+            "-c",
+            "core.quotePath=false",
+            "diff",
+            "--cached",
+            "-M",
+            "--no-color",
+            "--no-ext-diff",
+            "--src-prefix=a/",
+            "--dst-prefix=b/",
+            "--",
+        ];
+        args.extend(pathspecs.iter().map(|p| p.as_str()));
+        let text = self.git.output(&args)?;
+        diff::parse(&text)
+            .into_iter()
+            .find(|f| f.new_path.as_deref() == Some(&rel) || f.old_path.as_deref() == Some(&rel))
+            .ok_or(Error::NoChanges(rel))
+    }
+
+    /// Diffs `paths` (files or whole tracking entries, absolute or HOME-relative) for the given
+    /// target. An empty `paths` covers the whole repository.
+    pub fn diff_files(&self, paths: &[&str], target: &DiffTarget) -> Result<Vec<FileDiff>> {
+        self.ensure_initialized()?;
+        let mut args: Vec<String> = ["-c", "core.quotePath=false", "diff"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        match target {
+            DiffTarget::Staged => args.push("--cached".to_string()),
+            DiffTarget::Unstaged => {}
+            DiffTarget::Revision(rev) => args.push(self.verify_revision(rev)?),
+            DiffTarget::Upstream => {
+                self.git.output(&["fetch", "--quiet"])?;
+                args.push(self.verify_revision("@{upstream}")?);
+            }
+            DiffTarget::Between(a, b) => {
+                args.push(self.verify_revision(a)?);
+                args.push(self.verify_revision(b)?);
+            }
+        }
+        args.extend(
+            [
+                "-M",
+                "--no-color",
+                "--no-ext-diff",
+                "--src-prefix=a/",
+                "--dst-prefix=b/",
+                "--",
+            ]
+            .map(|a| a.to_string()),
+        );
+        for path in paths {
+            args.push(self.pathspec(path));
+        }
+
+        let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+        let files: Vec<FileDiff> = diff::parse(&self.git.output(&args)?)
+            .into_iter()
+            .filter(|f| !f.is_empty())
+            .collect();
+        if files.is_empty() {
+            let rel: Vec<&str> = paths.iter().map(|p| self.home_relative(p)).collect();
+            return Err(Error::NoChanges(rel.join(", ")));
+        }
+        Ok(files)
+    }
+
+    /// Stages the live state of a single path, in HOME or of a system entry.
+    pub fn stage(&self, path: &str) -> Result<()> {
+        self.ensure_initialized()?;
+        if self.is_system(path) {
+            return self.stage_system(&self.git, path);
+        }
+        self.git
+            .output(&["add", "-A", "--", &self.pathspec(path)])?;
+        Ok(())
+    }
+
+    /// Resets the index entry of a path to the last commit, keeping the live file. The next
+    /// [`DotRepo::stage_all`] stages the path again.
+    pub fn unstage(&self, path: &str) -> Result<()> {
+        self.ensure_initialized()?;
+        self.git
+            .output(&["reset", "--quiet", "--", &self.pathspec(path)])?;
+        Ok(())
+    }
+
+    /// Puts the version of a file or whole folder from `rev` (the last commit by default) back
+    /// into HOME, or for system paths into place through the escalation command, with their
+    /// recorded modes and owners. Empty folders recorded in `rev` are created. Live files that
+    /// differ are copied to `$DATA/backups/<time>` first, and a path that had dropped out of
+    /// the tracking list is tracked again.
+    pub fn restore(&self, path: &str, rev: Option<&str>) -> Result<Restored> {
+        self.ensure_initialized()?;
+        let rev = rev.unwrap_or("HEAD");
+        let commit = self.verify_revision(rev)?;
+        let rel = self.repo_path(path);
+        let abs = self.tree_path(&rel);
+        let files = split_z(&self.git.output(&[
+            "ls-tree",
+            "-r",
+            "-z",
+            "--name-only",
+            "--full-tree",
+            &commit,
+            "--",
+            &rel,
+        ])?);
+        let empty_dir = files.is_empty()
+            && self
+                .modes_in(&self.git, Some(&commit))?
+                .dirs
+                .iter()
+                .any(|d| paths::is_within(&d.path, &rel));
+        if files.is_empty() && !empty_dir {
+            return Err(Error::NotInRevision {
+                path: rel,
+                rev: rev.to_string(),
+            });
+        }
+
+        let followed = self.is_followed(path)?;
+        let differing = match self.is_system(path) || followed {
+            // Git never looks at the live files of system entries and followed symlinks.
+            true => self.differing_files(&commit, &files)?,
+            false => split_z(&self.git.output(&[
+                "diff",
+                "--name-only",
+                "-z",
+                "--no-renames",
+                &commit,
+                "--",
+                &abs,
+            ])?),
+        };
+        let mut overwritten: Vec<&String> = vec![];
+        for file in differing.iter().filter(|f| files.contains(f)) {
+            if metadata(self.source_path(&self.live_path(file))?).is_ok() {
+                overwritten.push(file);
+            }
+        }
+        self.auto_snapshot(&format!("before restore {rel}"), Some(&commit))?;
+        let backup = if overwritten.is_empty() {
+            None
+        } else {
+            Some(self.back_up(&overwritten)?)
+        };
+
+        let live = self.live_path(&rel);
+        if self.is_system(path) {
+            self.deploy(&live, &commit)?;
+        } else if followed {
+            self.write_followed(&commit, Some(&rel))?;
+        } else {
+            if !files.is_empty() {
+                self.git.output(&["checkout", &commit, "--", &abs])?;
+            }
+            self.apply_modes(&commit, Some(&rel))?;
+        }
+        let mut list = self.tracking_list()?;
+        if list.entry_for(&live).is_none() {
+            list.insert(&live)?;
+            list.save(&self.list)?;
+        }
+        if self.is_farm() {
+            self.place_links()?;
+        }
+        Ok(Restored { files, backup })
+    }
+
+    /// Puts back what git leaves to dm after HOME was checked out at `rev`: the recorded modes
+    /// and empty folders, the content behind followed symlinks and, in symlink-farm mode, the
+    /// links into the checkout. Returns the links that real files are in the way of.
+    fn settle(&self, rev: &str) -> Result<Vec<Link>> {
+        self.apply_modes(rev, None)?;
+        self.write_followed(rev, None)?;
+        match self.is_farm() {
+            true => self.place_links(),
+            false => Ok(vec![]),
+        }
+    }
+
+    /// Copies the live versions of the repository `files` into a new backup directory.
+    fn back_up(&self, files: &[&String]) -> Result<String> {
+        let secs = now();
+        let mut dir = format!("{}/backups/{secs}", self.data);
+        let mut n = 1;
+        while metadata(&dir).is_ok() {
+            dir = format!("{}/backups/{secs}-{n}", self.data);
+            n += 1;
+        }
+        for file in files {
+            copy_file(
+                &self.source_path(&self.live_path(file))?,
+                &format!("{dir}/{file}"),
+            )?;
+        }
+        Ok(dir)
+    }
+
+    /// Resolves `rev` to a full commit hash.
+    pub fn verify_revision(&self, rev: &str) -> Result<String> {
+        match self.git.output(&[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{rev}^{{commit}}"),
+        ]) {
+            Ok(hash) if !hash.trim().is_empty() => Ok(hash.trim().to_string()),
+            _ => Err(Error::UnknownRevision(rev.to_string())),
+        }
+    }
+
+    /// The other path of a staged rename involving `rel`.
+    fn rename_partner(&self, rel: &str) -> Result<Option<String>> {
+        let output = self
+            .git
+            .output(&["diff", "--cached", "-M", "--name-status", "-z"])?;
+        let mut fields = output.split('\0').filter(|f| !f.is_empty());
+        while let Some(status) = fields.next() {
+            if status.starts_with('R') || status.starts_with('C') {
+                let from = fields.next().unwrap_or_default();
+                let to = fields.next().unwrap_or_default();
+                if from == rel {
+                    return Ok(Some(to.to_string()));
+                }
+                if to == rel {
+                    return Ok(Some(from.to_string()));
+                }
+            } else {
+                fields.next();
+            }
+        }
+        Ok(None)
+    }
+
+    /// Commits the index and returns the abbreviated hash of the new commit.
+    pub fn commit(&self, message: &str) -> Result<String> {
+        self.ensure_initialized()?;
+        self.git.output(&[
+            "commit",
+            "--quiet",
+            "-m",
+            message,
+            "--trailer",
+            &host_trailer(),
+        ])?;
+        self.note_effect(|e| e.committed = true);
+        Ok(self
+            .git
+            .output(&["rev-parse", "--short", "HEAD"])?
+            .trim()
+            .to_string())
+    }
+
+    /// Commits only the picked changes. They are assembled in a scratch index on top of the
+    /// last commit, so everything else stays staged and shows up again in the next status.
+    pub fn commit_picked(&self, message: &str, picks: &[Pick]) -> Result<String> {
+        self.ensure_initialized()?;
+        if picks.is_empty() {
+            return Err(Error::NoChanges(self.home.clone()));
+        }
+        let index = format!("{}/dm-commit-index", self.git.git_dir());
+        let scratch = self.git.with_index_file(&index);
+        let result = self.build_index(&scratch, picks).and_then(|()| {
+            if scratch.succeeds(&["diff", "--cached", "--quiet"])? {
+                return Err(Error::NoChanges(self.home.clone()));
+            }
+            scratch.output(&[
+                "commit",
+                "--quiet",
+                "-m",
+                message,
+                "--trailer",
+                &host_trailer(),
+            ])
+        });
+        let _ = fs::remove_file(&index);
+        result?;
+        self.note_effect(|e| e.committed = true);
+        Ok(self
+            .git
+            .output(&["rev-parse", "--short", "HEAD"])?
+            .trim()
+            .to_string())
+    }
+
+    fn build_index(&self, scratch: &Git, picks: &[Pick]) -> Result<()> {
+        scratch.output(&["read-tree", "HEAD"])?;
+        for pick in picks {
+            match pick {
+                Pick::File(path) => {
+                    let entry = self.git.output(&["ls-files", "-s", "-z", "--", path])?;
+                    if entry.is_empty() {
+                        scratch.output(&["update-index", "--force-remove", "--", path])?;
+                    } else {
+                        scratch.output_with_input(
+                            &["update-index", "-z", "--index-info"],
+                            entry.as_bytes(),
+                        )?;
+                    }
+                }
+                Pick::Hunks(file, hunks) => {
+                    let patch = file.patch(hunks);
+                    scratch.output_with_input(&["apply", "--cached", "-"], patch.as_bytes())?;
+                }
+            }
+        }
+        // The modes staged along with the files go into every commit.
+        let modes = self
+            .git
+            .output(&["ls-files", "-s", "-z", "--", MODES_FILE])?;
+        if !modes.is_empty() {
+            scratch.output_with_input(&["update-index", "-z", "--index-info"], modes.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Commits touching `path` (a file or tracking entry), or the whole repository, newest
+    /// first. The history follows renames, of the file or of any file below the entry.
+    pub fn log(&self, path: Option<&str>, limit: Option<usize>) -> Result<Vec<LogEntry>> {
+        self.ensure_initialized()?;
+        let mut args: Vec<String> = [
+            "-c",
+            "core.quotePath=false",
+            "log",
+            log::FORMAT,
+            "--date=format:%Y-%m-%d %H:%M",
+            "-z",
+            "-M",
+        ]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+        let Some(path) = path else {
+            args.push("--name-only".to_string());
+            if let Some(limit) = limit {
+                args.push(format!("--max-count={limit}"));
+            }
+            let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+            let mut entries = LogEntry::parse(&self.git.output(&args)?);
+            for entry in entries.iter_mut() {
+                entry.files.retain(|f| f != MODES_FILE);
+            }
+            return Ok(entries);
+        };
+
+        // `--follow` only works for a single file, so the whole history is walked instead,
+        // and every path a file below `path` was renamed from is taken along to older commits.
+        args.push("--name-status".to_string());
+        let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+        let mut followed = vec![self.repo_path(path)];
+        let covers =
+            |followed: &[String], file: &str| followed.iter().any(|p| paths::is_within(file, p));
+        let mut entries = vec![];
+        for (mut entry, changes) in LogEntry::parse_changes(&self.git.output(&args)?) {
+            for (old, new) in changes {
+                if !covers(&followed, &new) {
+                    continue;
+                }
+                if old != new && !covers(&followed, &old) {
+                    match followed.iter().position(|p| *p == new) {
+                        Some(i) => followed[i] = old,
+                        None => followed.push(old),
+                    }
+                }
+                entry.files.push(new);
+            }
+            if !entry.files.is_empty() {
+                entries.push(entry);
+            }
+            if limit.is_some_and(|l| entries.len() >= l) {
+                break;
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The changes a commit introduced, optionally limited to HOME-relative `paths`.
+    pub fn commit_diff(&self, rev: &str, paths: &[String]) -> Result<Vec<FileDiff>> {
+        self.ensure_initialized()?;
+        let commit = self.verify_revision(rev)?;
+        let mut args = vec![
+            "-c",
+            "core.quotePath=false",
+            "show",
+            "--format=",
+            "-M",
+            "--no-color",
+            "--no-ext-diff",
+            "--src-prefix=a/",
+            "--dst-prefix=b/",
+            &commit,
+        ];
+        let paths: Vec<String> = paths.iter().map(|p| self.tree_path(p)).collect();
+        if !paths.is_empty() {
+            args.push("--");
+            args.extend(paths.iter().map(|p| p.as_str()));
+        }
+        Ok(diff::parse(&self.git.output(&args)?))
+    }
+
+    fn note_effect(&self, note: impl FnOnce(&mut Effects)) {
+        let mut effects = self.effects.get();
+        note(&mut effects);
+        self.effects.set(effects);
+    }
+
+    pub fn journal_file(&self) -> String {
+        format!("{}/journal.toml", self.data)
+    }
+
+    /// The journaled operations, oldest first.
+    pub fn journal(&self) -> Result<Vec<Operation>> {
+        Ok(Journal::load(&self.journal_file())?.operation)
+    }
+
+    /// Records the state before running `command`. Pass the result to
+    /// [`DotRepo::finish_operation`] once the command is done.
+    pub fn begin_operation(&self, command: &str) -> Result<Operation> {
+        self.ensure_initialized()?;
+        self.effects.set(Effects::default());
+        Ok(Operation {
+            command: command.to_string(),
+            time: now(),
+            list_before: self.tracking_list()?.entries,
+            index_before: self.index_tree()?,
+            head_before: self.head()?,
+            snapshot: self.snapshots()?.last().map(|s| s.id.clone()),
+            ..Operation::default()
+        })
+    }
+
+    /// Completes `operation` with what the command changed and appends it to the journal,
+    /// unless it changed nothing.
+    pub fn finish_operation(&self, mut operation: Operation) -> Result<()> {
+        let last_before = operation.snapshot.take();
+        let snapshots = self.snapshots()?;
+        // Snapshots sort by age, so the new ones follow the last one from before, unless
+        // pruning removed it.
+        let first_new = last_before
+            .and_then(|id| snapshots.iter().position(|s| s.id == id).map(|i| i + 1))
+            .unwrap_or_else(|| {
+                snapshots
+                    .iter()
+                    .position(|s| s.created >= operation.time)
+                    .unwrap_or(snapshots.len())
+            });
+        operation.snapshot = snapshots[first_new..]
+            .iter()
+            .find(|s| s.is_automatic())
+            .map(|s| s.id.clone());
+        operation.list_after = self.tracking_list()?.entries;
+        operation.index_after = self.index_tree()?;
+        operation.head_after = self.head()?;
+        let effects = self.effects.get();
+        operation.committed = effects.committed;
+        operation.pushed = effects.pushed;
+        if operation.is_noop() && operation.snapshot.is_none() {
+            return Ok(());
+        }
+        Journal::append(&self.journal_file(), operation)
+    }
+
+    /// Reverts the most recent operation that has not been undone yet: HEAD, the tracking list,
+    /// the index and, through its snapshot, the files in HOME. A commit that already reached
+    /// the remote is only undone with `force`, which rewrites the remote branch.
+    pub fn undo(&self, force: bool) -> Result<Undone> {
+        self.ensure_initialized()?;
+        let mut journal = Journal::load(&self.journal_file())?;
+        let Some(position) = journal.operation.iter().rposition(|o| !o.undone) else {
+            return Err(Error::NothingToUndo);
+        };
+        let operation = journal.operation[position].clone();
+        let mut notes = vec![];
+        // In symlink-farm mode, entries the operation added or removed change between being
+        // links into the checkout and files in HOME.
+        let current = self.tracking_list()?.paths();
+        let dropped: Vec<&String> = current
+            .iter()
+            .filter(|p| !operation.list_before.iter().any(|e| e.path == **p))
+            .collect();
+        let returned: Vec<&String> = operation
+            .list_before
+            .iter()
+            .filter(|e| !e.follow && !current.contains(&e.path))
+            .map(|e| &e.path)
+            .collect();
+
+        // Commits made outside dm since then are not in the journal, so going back past them
+        // would lose track of them.
+        if self.head()? != operation.head_after {
+            return Err(Error::CannotUndo(format!(
+                "HEAD moved since '{}'",
+                operation.command
+            )));
+        }
+        let mut force_push = false;
+        if operation.moved_head() {
+            if operation.head_before.is_empty() {
+                return Err(Error::CannotUndo(format!(
+                    "'{}' created the first commit",
+                    operation.command
+                )));
+            }
+            let upstream =
+                self.git
+                    .output_lossy(&["rev-parse", "--verify", "--quiet", "@{upstream}"])?;
+            let on_remote = operation.committed
+                && !upstream.trim().is_empty()
+                && self.git.succeeds(&[
+                    "merge-base",
+                    "--is-ancestor",
+                    &operation.head_after,
+                    upstream.trim(),
+                ])?;
+            if on_remote && !force {
+                return Err(Error::CannotUndo(format!(
+                    "the commits of '{}' are already pushed, use '--force' to rewrite the remote",
+                    operation.command
+                )));
+            }
+            force_push = on_remote;
+        }
+
+        if force_push {
+            let branch = self.git.output(&["symbolic-ref", "--short", "HEAD"])?;
+            let branch = branch.trim();
+            self.git.output(&[
+                "push",
+                "--quiet",
+                &format!("--force-with-lease={branch}:{}", operation.head_after),
+                "origin",
+                &format!("{}:{branch}", operation.head_before),
+            ])?;
+            notes.push(format!("rewrote origin/{branch} with --force"));
+        }
+        if operation.moved_head() {
+            self.git.output(&[
+                "update-ref",
+                "HEAD",
+                &operation.head_before,
+                &operation.head_after,
+            ])?;
+            notes.push(format!(
+                "moved HEAD back to {}",
+                &operation.head_before[..7]
+            ));
+        }
+        if let Some(id) = &operation.snapshot {
+            match self.find_snapshot(id) {
+                Ok(snapshot) => {
+                    self.auto_snapshot("before undo", Some(&snapshot.commit))?;
+                    self.restore_snapshot_state(&snapshot)?;
+                    notes.push(format!("restored the files from snapshot {id}"));
+                }
+                Err(_) => notes.push(format!(
+                    "snapshot {id} was pruned, files in HOME were left as they are"
+                )),
+            }
+        } else if operation.pushed && !operation.committed {
+            notes.push("the push itself was not undone".to_string());
+        }
+        TrackingList {
+            entries: operation.list_before.clone(),
+        }
+        .save(&self.list)?;
+        self.git.output(&["read-tree", &operation.index_before])?;
+        self.hide_own_files()?;
+        if self.is_farm() {
+            // Entries the operation added go back to being files in HOME.
+            for path in dropped.iter().filter(|p| !self.is_system(p)) {
+                self.release(path)?;
+            }
+            // Entries the operation removed were moved back into HOME, where they are now the
+            // newer copy.
+            for path in returned.iter().filter(|p| !self.is_system(p)) {
+                if fs::symlink_metadata(path).is_ok_and(|m| !m.is_symlink()) {
+                    let copy = self.tree_path(&self.repo_path(path));
+                    remove_path(&copy)?;
+                    self.adopt(path)?;
+                }
+            }
+            self.place_links()?;
+        }
+
+        journal.operation[position].undone = true;
+        journal.save(&self.journal_file())?;
+        Ok(Undone { operation, notes })
+    }
+
+    fn head(&self) -> Result<String> {
+        Ok(self
+            .git
+            .output_lossy(&["rev-parse", "--verify", "--quiet", "HEAD"])?
+            .trim()
+            .to_string())
+    }
+
+    fn index_tree(&self) -> Result<String> {
+        Ok(self.git.output(&["write-tree"])?.trim().to_string())
+    }
+
+    /// Records the live state of every tracked file as a snapshot named `name`, or as an
+    /// automatic one, without touching the index or HOME.
+    pub fn create_snapshot(&self, name: Option<&str>, label: &str) -> Result<Snapshot> {
+        self.ensure_initialized()?;
+        match name {
+            Some(name) => {
+                let valid = !name.starts_with(AUTO_PREFIX)
+                    && !self.snapshot_dir_exists(name)
+                    && Command::new(GIT_BIN)
+                        .args(["check-ref-format", &format!("{SNAPSHOT_REFS}/{name}")])
+                        .status()
+                        .map(|s| s.success())
+                        .unwrap_or(false);
+                if !valid || name.contains('/') {
+                    return Err(Error::InvalidSnapshotName(name.to_string()));
+                }
+                self.take_snapshot(name, label, None)
+            }
+            None => self.auto_snapshot(label, None),
+        }
+    }
+
+    /// Takes an automatic snapshot before an operation that changes HOME and prunes the old
+    /// ones. `incoming` is the commit the operation is about to write into HOME; untracked live
+    /// files it would overwrite are copied into the snapshot.
+    fn auto_snapshot(&self, label: &str, incoming: Option<&str>) -> Result<Snapshot> {
+        let secs = now();
+        let mut id = format!("{AUTO_PREFIX}{secs}");
+        let mut n = 1;
+        while self.snapshot_dir_exists(&id) {
+            id = format!("{AUTO_PREFIX}{secs}-{n}");
+            n += 1;
+        }
+        let snapshot = self.take_snapshot(&id, label, incoming)?;
+        self.prune_snapshots(&self.config()?.snapshots)?;
+        Ok(snapshot)
+    }
+
+    fn take_snapshot(&self, id: &str, label: &str, incoming: Option<&str>) -> Result<Snapshot> {
+        // Start from the real index, which mirrors the tracked files, and add their live state.
+        let index = format!("{}/dm-snapshot-index", self.git.git_dir());
+        let live_index = format!("{}/index", self.git.git_dir());
+        let scratch = self.git.with_index_file(&index);
+        let list = self.tracking_list()?;
+        let result = (|| {
+            let _ = fs::remove_file(&index);
+            if metadata(&live_index).is_ok() {
+                fs::copy(&live_index, &index)
+                    .map_err(|e| Error::io("could not copy the index", e))?;
+            }
+            let host = log::hostname();
+            for entry in list.entries.iter().filter(|e| e.applies_to(&host)) {
+                if fs::symlink_metadata(self.source_path(&entry.path)?).is_ok() {
+                    self.stage_entry_in(&scratch, &entry.path)?;
+                }
+            }
+            let tree = scratch.output(&["write-tree"])?;
+            scratch.output(&["commit-tree", tree.trim(), "-p", "HEAD", "-m", label])
+        })();
+        let _ = fs::remove_file(&index);
+        let commit = result?.trim().to_string();
+
+        let dir = self.snapshot_dir(id);
+        create_dir(&dir)?;
+        list.save(&format!("{dir}/list"))?;
+        if let Some(incoming) = incoming {
+            let tracked = split_z(&self.git.output(&["ls-files", "-z"])?);
+            let files = split_z(&self.git.output(&[
+                "ls-tree",
+                "-r",
+                "-z",
+                "--name-only",
+                "--full-tree",
+                incoming,
+            ])?);
+            for file in files.iter().filter(|f| !tracked.contains(f)) {
+                let live = self.tree_path(file);
+                if fs::symlink_metadata(&live).is_ok() {
+                    copy_file(&live, &format!("{dir}/files/{file}"))?;
+                }
+            }
+        }
+        self.git
+            .output(&["update-ref", &format!("{SNAPSHOT_REFS}/{id}"), &commit])?;
+        self.find_snapshot(id)
+    }
+
+    /// All snapshots, oldest first.
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>> {
+        self.ensure_initialized()?;
+        let mut snapshots = Snapshot::parse(&self.git.output(&[
+            "for-each-ref",
+            "--sort=committerdate",
+            snapshot::FORMAT,
+            SNAPSHOT_REFS,
+        ])?);
+        snapshots.sort_by_key(|s| s.created);
+        Ok(snapshots)
+    }
+
+    fn find_snapshot(&self, id: &str) -> Result<Snapshot> {
+        self.snapshots()?
+            .into_iter()
+            .find(|s| s.id == id)
+            .ok_or_else(|| Error::UnknownSnapshot(id.to_string()))
+    }
+
+    /// Puts a snapshot back into HOME after taking an automatic snapshot of the current state.
+    pub fn restore_snapshot(&self, id: &str) -> Result<Checkout> {
+        self.ensure_initialized()?;
+        let target = self.find_snapshot(id)?;
+        self.stage_all()?;
+        let safety = self.auto_snapshot(
+            &format!("before snapshot restore {id}"),
+            Some(&target.commit),
+        )?;
+        let changes = self.tree_changes(&safety.commit, &target.commit)?;
+        self.restore_snapshot_state(&target)?;
+        Ok(Checkout {
+            commit: target.commit,
+            snapshot: safety,
+            changes,
+        })
+    }
+
+    pub fn delete_snapshot(&self, id: &str) -> Result<()> {
+        self.ensure_initialized()?;
+        self.find_snapshot(id)?;
+        self.git
+            .output(&["update-ref", "-d", &format!("{SNAPSHOT_REFS}/{id}")])?;
+        let dir = self.snapshot_dir(id);
+        if metadata(&dir).is_ok() {
+            fs::remove_dir_all(&dir)
+                .map_err(|e| Error::io(format!("could not remove '{dir}'"), e))?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the automatic snapshots that fall outside `policy` and returns them. The one a
+    /// pending `checkout --return` needs is kept.
+    pub fn prune_snapshots(&self, policy: &SnapshotPolicy) -> Result<Vec<Snapshot>> {
+        let pending = self
+            .git
+            .output_lossy(&["rev-parse", "--verify", "--quiet", RETURN_REF])?;
+        let mut automatic: Vec<Snapshot> = self
+            .snapshots()?
+            .into_iter()
+            .filter(|s| s.is_automatic() && s.commit != pending.trim())
+            .collect();
+        automatic.reverse();
+        let cutoff = now().saturating_sub(policy.max_age_days * 24 * 60 * 60);
+        let mut pruned = vec![];
+        for (i, snapshot) in automatic.into_iter().enumerate() {
+            let expired = policy.max_age_days > 0 && snapshot.created < cutoff;
+            if i >= policy.keep || expired {
+                self.delete_snapshot(&snapshot.id)?;
+                pruned.push(snapshot);
+            }
+        }
+        Ok(pruned)
+    }
+
+    fn snapshot_dir(&self, id: &str) -> String {
+        format!("{}/snapshots/{id}", self.data)
+    }
+
+    fn snapshot_dir_exists(&self, id: &str) -> bool {
+        metadata(self.snapshot_dir(id)).is_ok()
+    }
+
+    /// Rolls every tracked file back to `when`, a commit or a date git understands such as
+    /// `2024-05-01` or `3 days ago`. The live state is saved as a snapshot first, which
+    /// [`DotRepo::checkout_return`] brings back.
+    pub fn checkout_at(&self, when: &str) -> Result<Checkout> {
+        self.ensure_initialized()?;
+        let target = match self.verify_revision(when) {
+            Ok(commit) => commit,
+            Err(_) => {
+                let before = format!("--before={when}");
+                let commit = self.git.output(&["rev-list", "-1", &before, "HEAD"])?;
+                if commit.trim().is_empty() {
+                    return Err(Error::UnknownRevision(when.to_string()));
+                }
+                commit.trim().to_string()
+            }
+        };
+        self.stage_all()?;
+        let snapshot =
+            self.auto_snapshot(&format!("before checkout --at {when}"), Some(&target))?;
+        let changes = self.tree_changes(&snapshot.commit, &target)?;
+        self.git.output(&["read-tree", "-u", "--reset", &target])?;
+        self.settle(&target)?;
+        self.git
+            .output(&["update-ref", RETURN_REF, &snapshot.commit])?;
+        Ok(Checkout {
+            commit: target,
+            snapshot,
+            changes,
+        })
+    }
+
+    /// Brings back the state saved by the last [`DotRepo::checkout_at`].
+    pub fn checkout_return(&self) -> Result<Checkout> {
+        self.ensure_initialized()?;
+        let commit = self
+            .git
+            .output_lossy(&["rev-parse", "--verify", "--quiet", RETURN_REF])?;
+        let snapshot = self
+            .snapshots()?
+            .into_iter()
+            .find(|s| s.commit == commit.trim())
+            .ok_or(Error::NoCheckout)?;
+        self.stage_all()?;
+        let safety = self.auto_snapshot("before checkout --return", Some(&snapshot.commit))?;
+        let changes = self.tree_changes(&safety.commit, &snapshot.commit)?;
+        self.restore_snapshot_state(&snapshot)?;
+        self.git.output(&["update-ref", "-d", RETURN_REF])?;
+        Ok(Checkout {
+            commit: snapshot.commit.clone(),
+            snapshot,
+            changes,
+        })
+    }
+
+    /// Puts the files and tracking list of `snapshot` back into HOME.
+    fn restore_snapshot_state(&self, snapshot: &Snapshot) -> Result<()> {
+        self.git
+            .output(&["read-tree", "-u", "--reset", &snapshot.commit])?;
+        let dir = self.snapshot_dir(&snapshot.id);
+        let list = TrackingList::load(&format!("{dir}/list"))?;
+        list.save(&self.list)?;
+        let files = format!("{dir}/files");
+        for file in walk_files(&files)? {
+            copy_file(&file, &self.tree_path(relative_to(&file, &files)))?;
+        }
+        self.settle(&snapshot.commit)?;
+        Ok(())
+    }
+
+    /// Paths that differ between two trees or commits.
+    fn tree_changes(&self, from: &str, to: &str) -> Result<Vec<(Change, String)>> {
+        let output = self
+            .git
+            .output(&["diff", "--name-status", "-z", "--no-renames", from, to])?;
+        let fields = split_z(&output);
+        Ok(fields
+            .chunks(2)
+            .filter(|c| c.len() == 2 && c[1] != MODES_FILE)
+            .filter_map(|c| {
+                let code = c[0].chars().next()?;
+                Some((Change::from_code(code)?, c[1].clone()))
+            })
+            .collect())
+    }
+
+    pub fn push(&self) -> Result<()> {
+        self.ensure_initialized()?;
+        self.git.output(&["push", "--quiet"])?;
+        self.note_effect(|e| e.pushed = true);
+        Ok(())
+    }
+
+    /// Fetches and merges the upstream branch, updating the files in HOME.
+    pub fn pull(&self) -> Result<()> {
+        self.ensure_initialized()?;
+        self.git.output(&["fetch", "--quiet"])?;
+        let upstream = self.verify_revision("@{upstream}")?;
+        if upstream != self.verify_revision("HEAD")? {
+            self.stage_all()?;
+            self.auto_snapshot("before pull", Some(&upstream))?;
+        }
+        self.git.output(&["pull", "--quiet", "--ff-only"])?;
+        self.settle("HEAD")?;
+        Ok(())
+    }
+
+    /// Sets up another machine: clones `repo_url` into the data directory, checks its files out
+    /// into HOME, or into the checkout of symlink-farm mode and links them into HOME, and
+    /// builds the tracking list from them as [`DotRepo::rebuild_list`] does. Live files that get
+    /// replaced are kept in the returned snapshot; real files in the way of links are left
+    /// alone, see [`DotRepo::links`].
+    pub fn clone_from(&self, repo_url: &str) -> Result<Snapshot> {
+        if self.is_initialized() {
+            return Err(Error::AlreadyInitialized(self.data.clone()));
+        }
+        create_dir(&self.data)?;
+        let output = Command::new(GIT_BIN)
+            .args(["clone", "--quiet", "--bare", repo_url, self.git_dir()])
+            .output()
+            .map_err(|e| Error::io("failed to execute git", e))?;
+        if !output.status.success() {
+            return Err(Error::Git {
+                command: "clone --bare".to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            });
+        }
+        if self.is_farm() {
+            self.set_up_checkout()?;
+        }
+        self.git
+            .output(&["config", "--local", "status.showUntrackedFiles", "no"])?;
+        self.git.output(&[
+            "config",
+            "--local",
+            "remote.origin.fetch",
+            "+refs/heads/*:refs/remotes/origin/*",
+        ])?;
+        self.git.output(&["fetch", "--quiet", "origin"])?;
+        let branch = self.git.output(&["symbolic-ref", "--short", "HEAD"])?;
+        let branch = branch.trim();
+        self.git.output(&[
+            "branch",
+            "--quiet",
+            &format!("--set-upstream-to=origin/{branch}"),
+            branch,
+        ])?;
+
+        self.enable_sparse()?;
+        let snapshot = self.auto_snapshot("before clone", Some("HEAD"))?;
+        self.git.output(&["read-tree", "-u", "--reset", "HEAD"])?;
+        self.rebuild_list()?.save(&self.list)?;
+        self.settle("HEAD")?;
+        Ok(snapshot)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Removes a file, symlink or whole folder, if there is one at `path`.
+fn remove_path(path: &str) -> Result<()> {
+    let removed = match fs::symlink_metadata(path) {
+        Ok(m) if m.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(_) => return Ok(()),
+    };
+    removed.map_err(|e| Error::io(format!("could not remove '{path}'"), e))
+}
+
+/// Copies a file or symlink, creating the parent directories of `to`.
+fn copy_file(from: &str, to: &str) -> Result<()> {
+    if let Some(parent) = Path::new(to).parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| Error::io(format!("could not create '{}'", parent.display()), e))?;
+    }
+    let _ = fs::remove_file(to);
+    let copied = match fs::read_link(from) {
+        Ok(target) => std::os::unix::fs::symlink(target, to),
+        Err(_) => fs::copy(from, to).map(|_| ()),
+    };
+    copied.map_err(|e| Error::io(format!("could not copy '{from}'"), e))
+}
+
+/// Files and symlinks below `dir`, which need not exist.
+pub(crate) fn walk_files(dir: &str) -> Result<Vec<String>> {
+    let mut files = vec![];
+    let Ok(read) = fs::read_dir(dir) else {
+        return Ok(files);
+    };
+    for entry in read {
+        let entry = entry.map_err(|e| Error::io(format!("could not read '{dir}'"), e))?;
+        let path = entry.path().to_string_lossy().to_string();
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => files.extend(walk_files(&path)?),
+            _ => files.push(path),
+        }
+    }
+    Ok(files)
+}
+
+fn host_trailer() -> String {
+    format!("{HOST_TRAILER}: {}", log::hostname())
+}
+
+/// Splits NUL separated git output.
+pub(crate) fn split_z(output: &str) -> Vec<String> {
+    output
+        .split('\0')
+        .filter(|f| !f.is_empty())
+        .map(|f| f.to_string())
+        .collect()
+}
+
+fn create_dir(path: &str) -> Result<()> {
+    if metadata(path).is_err() {
+        fs::create_dir_all(path).map_err(|e| Error::io(format!("Could not create {path}"), e))?;
+    }
+    Ok(())
+}
+
+/// Whether `path` exists, counting symlinks whose target does not.
+fn check_path_exists(path: &str) -> Result<()> {
+    if fs::symlink_metadata(trim_slash(path)).is_ok() {
+        Ok(())
+    } else {
+        Err(Error::PathNotFound(path.to_string()))
+    }
+}
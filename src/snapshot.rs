@@ -0,0 +1,56 @@
+/// Refs below which snapshots are kept in the bare repository.
+pub const SNAPSHOT_REFS: &str = "refs/dm/snapshots";
+/// Points at the snapshot taken by the last `checkout --at`, until `checkout --return`.
+pub const RETURN_REF: &str = "refs/dm/return";
+/// Id prefix of the snapshots dm takes on its own.
+pub const AUTO_PREFIX: &str = "auto-";
+
+/// Format passed to `git for-each-ref`, parsed by `Snapshot::parse`.
+pub(crate) const FORMAT: &str = "--format=%(refname:lstrip=3)%1f%(objectname)%1f\
+    %(committerdate:unix)%1f%(committerdate:format:%Y-%m-%d %H:%M)%1f%(subject)";
+
+/// The live state of every tracked file at one point in time: a commit under
+/// `refs/dm/snapshots/<id>` plus, in `$DATA/snapshots/<id>`, a copy of the tracking list and of
+/// the untracked files the following operation was about to overwrite.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub id: String,
+    pub commit: String,
+    /// Creation time in seconds since the epoch.
+    pub created: u64,
+    /// Creation time as `YYYY-MM-DD HH:MM`.
+    pub date: String,
+    /// Why the snapshot was taken.
+    pub label: String,
+}
+
+impl Snapshot {
+    /// Parses `git for-each-ref` output produced with `FORMAT`.
+    pub(crate) fn parse(output: &str) -> Vec<Snapshot> {
+        output
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.splitn(5, '\u{1f}').collect();
+                if fields.len() < 5 {
+                    return None;
+                }
+                Some(Snapshot {
+                    id: fields[0].to_string(),
+                    commit: fields[1].to_string(),
+                    created: fields[2].parse().unwrap_or_default(),
+                    date: fields[3].to_string(),
+                    label: fields[4].to_string(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn short(&self) -> &str {
+        &self.commit[..self.commit.len().min(7)]
+    }
+
+    /// Taken by dm before an operation rather than created by name, and subject to pruning.
+    pub fn is_automatic(&self) -> bool {
+        self.id.starts_with(AUTO_PREFIX)
+    }
+}
@@ -0,0 +1,113 @@
+use crate::modes::{ModeDrift, MODES_FILE};
+
+/// How a path differs between the last commit and the index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Deleted,
+    Modified,
+}
+
+impl Change {
+    pub const ALL: [Change; 3] = [Change::Added, Change::Deleted, Change::Modified];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Change::Added => "new file",
+            Change::Deleted => "deleted",
+            Change::Modified => "modified",
+        }
+    }
+
+    /// Maps a porcelain status letter to a change, if it is one dm reports.
+    pub(crate) fn from_code(code: char) -> Option<Change> {
+        match code {
+            'A' | 'C' => Some(Change::Added),
+            'D' => Some(Change::Deleted),
+            'M' | 'R' | 'T' => Some(Change::Modified),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatusEntry {
+    pub change: Change,
+    /// Path relative to HOME.
+    pub path: String,
+    /// Previous path of a rename.
+    pub renamed_from: Option<String>,
+    /// The index differs from the last commit.
+    pub staged: bool,
+    /// The live file differs from the index.
+    pub unstaged: bool,
+    /// The link targets on either side, when the path is a symlink on one of them.
+    pub link: Option<LinkChange>,
+}
+
+/// Where a symlink pointed in the last commit and where it points in the index. `None` on a
+/// side where the path is not a symlink.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkChange {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Status {
+    pub remote_url: String,
+    pub entries: Vec<StatusEntry>,
+    /// Files whose permissions changed, which git does not see.
+    pub drift: Vec<ModeDrift>,
+}
+
+impl Status {
+    pub fn is_clean(&self) -> bool {
+        self.entries.is_empty() && self.drift.is_empty()
+    }
+
+    /// Number of entries per change, in the order of `Change::ALL`.
+    pub fn counts(&self) -> [usize; 3] {
+        let mut counts = [0; 3];
+        for (i, change) in Change::ALL.iter().enumerate() {
+            counts[i] = self.entries.iter().filter(|e| &e.change == change).count();
+        }
+        counts
+    }
+
+    /// Parses `git status --porcelain -z` output.
+    pub(crate) fn parse_entries(porcelain: &str) -> Vec<StatusEntry> {
+        let mut entries = vec![];
+        let mut records = porcelain.split('\0').filter(|r| !r.is_empty());
+        while let Some(record) = records.next() {
+            if record.len() < 4 {
+                continue;
+            }
+            let mut codes = record[0..2].chars();
+            let (x, y) = (codes.next().unwrap(), codes.next().unwrap());
+            let path = record[3..].to_string();
+            let renamed_from = if x == 'R' || x == 'C' {
+                records.next().map(|p| p.to_string())
+            } else {
+                None
+            };
+            // dm's record of the modes shows up as drift instead.
+            if path == MODES_FILE {
+                continue;
+            }
+            let staged = Change::from_code(x);
+            let unstaged = Change::from_code(y);
+            if let Some(change) = staged.or(unstaged) {
+                entries.push(StatusEntry {
+                    change,
+                    path,
+                    renamed_from,
+                    staged: staged.is_some(),
+                    unstaged: unstaged.is_some(),
+                    link: None,
+                });
+            }
+        }
+        entries
+    }
+}
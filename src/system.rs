@@ -0,0 +1,328 @@
+use crate::entry::EntryState;
+use crate::error::{Error, Result};
+use crate::git::Git;
+use crate::modes::FileMode;
+use crate::paths::{self, trim_slash};
+use crate::repo::{split_z, walk_files, DotRepo};
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::process::Command;
+
+/// Where the files of system entries, those outside HOME, are kept in the repository: their
+/// absolute path below this prefix, e.g. `.dm/system/etc/pacman.conf`.
+pub const SYSTEM_PREFIX: &str = ".dm/system";
+
+/// Sparse checkout patterns that keep dm's own folder in the repository out of HOME, so git
+/// leaves it alone on checkout, pull and clone.
+const SPARSE_PATTERNS: &str = "/*\n!/.dm/\n";
+
+/// Mode of an index entry git removes when it is given to `update-index --index-info`.
+const REMOVED: &str = "0 0000000000000000000000000000000000000000";
+
+impl DotRepo {
+    /// Whether `path` belongs to a system entry: it is absolute and outside HOME.
+    pub fn is_system(&self, path: &str) -> bool {
+        path.starts_with('/') && !paths::is_within(path, self.home())
+    }
+
+    /// The path of a live file or folder in the repository: HOME-relative, or below
+    /// [`SYSTEM_PREFIX`] for system paths. Relative paths are returned as they are.
+    pub fn repo_path(&self, path: &str) -> String {
+        let path = trim_slash(path);
+        match self.is_system(path) {
+            true => format!("{SYSTEM_PREFIX}{path}"),
+            false => self.home_relative(path).to_string(),
+        }
+    }
+
+    /// The live path of a file in the repository, the reverse of [`DotRepo::repo_path`].
+    pub fn live_path(&self, file: &str) -> String {
+        match file.strip_prefix(SYSTEM_PREFIX) {
+            Some(system) if system.starts_with('/') => system.to_string(),
+            _ => self.absolute(file),
+        }
+    }
+
+    /// Where the content of a file in the repository is on disk: its live path, or in
+    /// symlink-farm mode its copy in the checkout the live path links to.
+    pub fn stored_path(&self, file: &str) -> String {
+        match file.strip_prefix(SYSTEM_PREFIX) {
+            Some(system) if system.starts_with('/') => system.to_string(),
+            _ => self.tree_path(file),
+        }
+    }
+
+    /// `path` as a pathspec git accepts, which for system paths points into the repository.
+    pub fn pathspec(&self, path: &str) -> String {
+        self.tree_path(&self.repo_path(path))
+    }
+
+    /// Keeps [`SYSTEM_PREFIX`], [`MODES_FILE`](crate::MODES_FILE) and the paths of followed
+    /// symlinks out of HOME through sparse checkout.
+    pub(crate) fn enable_sparse(&self) -> Result<()> {
+        let git = self.git();
+        git.output(&["config", "--local", "core.sparseCheckout", "true"])?;
+        // Followed symlinks stay in HOME, where git would otherwise track them again.
+        git.output(&[
+            "config",
+            "--local",
+            "sparse.expectFilesOutsideOfPatterns",
+            "true",
+        ])?;
+        let dir = format!("{}/info", self.git_dir());
+        let file = format!("{dir}/sparse-checkout");
+        let patterns = format!("{SPARSE_PATTERNS}{}", self.followed_patterns()?);
+        if fs::read_to_string(&file).is_ok_and(|s| s == patterns) {
+            return Ok(());
+        }
+        fs::create_dir_all(&dir).map_err(|e| Error::io(format!("could not create '{dir}'"), e))?;
+        fs::write(&file, patterns).map_err(|e| Error::io(format!("could not write '{file}'"), e))
+    }
+
+    /// Stages the live files of the system entry at `entry` into the index of `git`, along
+    /// with their modes and owners. Files that are gone are dropped from the index.
+    pub(crate) fn stage_system(&self, git: &Git, entry: &str) -> Result<()> {
+        self.enable_sparse()?;
+        let entry = trim_slash(entry);
+        let live = match fs::symlink_metadata(entry) {
+            Ok(m) if m.is_dir() => walk_files(entry)?,
+            Ok(_) => vec![entry.to_string()],
+            Err(_) => vec![],
+        };
+        let mut modes = self.modes_in(git, None)?;
+        let mut info = String::new();
+        let mut staged = vec![];
+        for file in live.iter() {
+            let metadata = fs::symlink_metadata(file)
+                .map_err(|e| Error::io(format!("could not read '{file}'"), e))?;
+            let (mode, hash) = match fs::read_link(file) {
+                Ok(target) => {
+                    let target = target.to_string_lossy();
+                    let hash = git
+                        .output_with_input(&["hash-object", "-w", "--stdin"], target.as_bytes())?;
+                    ("120000", hash)
+                }
+                Err(_) => {
+                    let exec = metadata.permissions().mode() & 0o111 != 0;
+                    let hash = git.output(&["hash-object", "-w", "--no-filters", "--", file])?;
+                    (if exec { "100755" } else { "100644" }, hash)
+                }
+            };
+            let rel = self.repo_path(file);
+            info.push_str(&format!("{mode} {}\t{rel}\n", hash.trim()));
+            modes.set(FileMode {
+                path: rel.clone(),
+                mode: format!("{:04o}", metadata.permissions().mode() & 0o7777),
+                owner: Some(user_name(metadata.uid())),
+                group: Some(group_name(metadata.gid())),
+            });
+            staged.push(rel);
+        }
+        let prefix = self.repo_path(entry);
+        for gone in self.indexed(git, &prefix)? {
+            if !staged.contains(&gone) {
+                info.push_str(&format!("{REMOVED}\t{gone}\n"));
+                modes.files.retain(|f| f.path != gone);
+            }
+        }
+        git.output_with_input(&["update-index", "--index-info"], info.as_bytes())?;
+        if !staged.is_empty() {
+            let mut args = vec!["update-index", "--skip-worktree", "--"];
+            args.extend(staged.iter().map(|s| s.as_str()));
+            git.output(&args)?;
+        }
+        self.write_modes(git, &modes)
+    }
+
+    /// Drops the files of the system entry at `entry` and their modes from the index.
+    pub(crate) fn unstage_system(&self, entry: &str) -> Result<()> {
+        let git = self.git();
+        let mut modes = self.modes_in(git, None)?;
+        let mut info = String::new();
+        for file in self.indexed(git, &self.repo_path(entry))? {
+            info.push_str(&format!("{REMOVED}\t{file}\n"));
+            modes.files.retain(|f| f.path != file);
+        }
+        git.output_with_input(&["update-index", "--index-info"], info.as_bytes())?;
+        self.write_modes(git, &modes)
+    }
+
+    /// Whether the live files of the entry at `entry` match the last commit, for the entries
+    /// whose live files git does not look at: system entries and followed symlinks.
+    pub(crate) fn scratch_state(&self, entry: &str) -> Result<EntryState> {
+        if fs::symlink_metadata(entry).is_err() {
+            return Ok(EntryState::Missing);
+        }
+        let git = self.git();
+        let index = format!("{}/dm-system-index", self.git_dir());
+        let scratch = git.with_index_file(&index);
+        let result = (|| {
+            let _ = fs::remove_file(&index);
+            if git.succeeds(&["rev-parse", "-q", "--verify", "HEAD"])? {
+                scratch.output(&["read-tree", "HEAD"])?;
+            }
+            self.stage_entry_in(&scratch, entry)?;
+            let changed = scratch.output(&["diff-index", "--cached", "--name-only", "HEAD"]);
+            Ok(changed.map_or(true, |c| !c.trim().is_empty()))
+        })();
+        let _ = fs::remove_file(&index);
+        Ok(match result? {
+            true => EntryState::Modified,
+            false => EntryState::Clean,
+        })
+    }
+
+    /// The repository `files` whose live versions differ from `rev`, or are gone.
+    pub(crate) fn differing_files(&self, rev: &str, files: &[String]) -> Result<Vec<String>> {
+        let git = self.git();
+        let mut differing = vec![];
+        for file in files {
+            let live = self.live_path(file);
+            let committed =
+                git.output_lossy(&["rev-parse", "-q", "--verify", &format!("{rev}:{file}")])?;
+            let current = match fs::read_link(&live) {
+                Ok(target) => git.output_with_input(
+                    &["hash-object", "--stdin"],
+                    target.to_string_lossy().as_bytes(),
+                )?,
+                Err(_) => git.output_lossy(&["hash-object", "--no-filters", "--", &live])?,
+            };
+            if current.trim() != committed.trim() {
+                differing.push(file.clone());
+            }
+        }
+        Ok(differing)
+    }
+
+    /// Writes the files of the system path `path` from `rev` through the escalation command,
+    /// with the mode and owner recorded for them, and updates the index to match, like
+    /// `git checkout <rev> -- <path>` does for HOME. Returns the live paths written.
+    pub(crate) fn deploy(&self, path: &str, rev: &str) -> Result<Vec<String>> {
+        let git = self.git();
+        let prefix = self.repo_path(path);
+        let tree = git.output(&[
+            "ls-tree",
+            "-r",
+            "-z",
+            "--full-tree",
+            rev,
+            "--",
+            &self.tree_path(&prefix),
+        ])?;
+        let modes = self.modes_in(git, Some(rev))?;
+        let mut index_modes = self.modes_in(git, None)?;
+        let escalate = self.config()?.system.escalate;
+        let scratch = format!("{}/dm-deploy", self.data_dir());
+        let mut written = vec![];
+        let mut info = String::new();
+        let mut files = vec![];
+        for line in split_z(&tree) {
+            let Some((meta, file)) = line.split_once('\t') else {
+                continue;
+            };
+            let fields: Vec<&str> = meta.split(' ').collect();
+            let git_mode = fields[0];
+            info.push_str(&format!("{git_mode} {}\t{file}\n", fields[2]));
+            files.push(file.to_string());
+            match modes.get(file) {
+                Some(mode) => index_modes.set(mode.clone()),
+                None => index_modes.files.retain(|f| f.path != file),
+            }
+            let live = self.live_path(file);
+            let content = git.bytes(&["cat-file", "blob", &format!("{rev}:{file}")])?;
+            if git_mode == "120000" {
+                let target = String::from_utf8_lossy(&content).to_string();
+                escalated(&escalate, &["ln", "-sfn", &target, &live])?;
+                written.push(live);
+                continue;
+            }
+            fs::write(&scratch, &content)
+                .map_err(|e| Error::io(format!("could not write '{scratch}'"), e))?;
+            let recorded = modes.get(file);
+            let mode = match recorded {
+                Some(r) => r.mode.clone(),
+                None if git_mode == "100755" => "0755".to_string(),
+                None => "0644".to_string(),
+            };
+            let mut args = vec!["install", "-D", "-m", &mode];
+            if let Some(owner) = recorded.and_then(|r| r.owner.as_deref()) {
+                args.extend(["-o", owner]);
+            }
+            if let Some(group) = recorded.and_then(|r| r.group.as_deref()) {
+                args.extend(["-g", group]);
+            }
+            args.extend([scratch.as_str(), live.as_str()]);
+            let result = escalated(&escalate, &args);
+            let _ = fs::remove_file(&scratch);
+            result?;
+            written.push(live);
+        }
+        git.output_with_input(&["update-index", "--add", "--index-info"], info.as_bytes())?;
+        if !files.is_empty() {
+            let mut args = vec!["update-index", "--skip-worktree", "--"];
+            args.extend(files.iter().map(|f| f.as_str()));
+            git.output(&args)?;
+        }
+        self.write_modes(git, &index_modes)?;
+        Ok(written)
+    }
+
+    /// Marks the files in `.dm/` skip-worktree again after the index was read without
+    /// updating HOME, which drops the bit, so git does not take them for deleted.
+    pub(crate) fn hide_own_files(&self) -> Result<()> {
+        let git = self.git();
+        let files = self.indexed(git, ".dm")?;
+        if !files.is_empty() {
+            let mut args = vec!["update-index", "--skip-worktree", "--"];
+            args.extend(files.iter().map(|f| f.as_str()));
+            git.output(&args)?;
+        }
+        Ok(())
+    }
+
+    /// Files in the index of `git` at or below the repository path `prefix`.
+    pub(crate) fn indexed(&self, git: &Git, prefix: &str) -> Result<Vec<String>> {
+        Ok(split_z(&git.output(&[
+            "ls-files",
+            "--full-name",
+            "-z",
+            "--",
+            &self.tree_path(prefix),
+        ])?))
+    }
+}
+
+/// Runs `args` through the escalation command, or directly if there is none.
+fn escalated(escalate: &str, args: &[&str]) -> Result<()> {
+    let mut command: Vec<&str> = escalate.split_whitespace().collect();
+    command.extend(args);
+    let output = Command::new(command[0])
+        .args(&command[1..])
+        .output()
+        .map_err(|e| Error::io(format!("failed to execute '{}'", command[0]), e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(Error::Escalation {
+        command: command.join(" "),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    })
+}
+
+fn user_name(uid: u32) -> String {
+    name_in("/etc/passwd", uid)
+}
+
+fn group_name(gid: u32) -> String {
+    name_in("/etc/group", gid)
+}
+
+/// The name with `id` in a passwd or group file, or the id itself if it has none.
+fn name_in(file: &str, id: u32) -> String {
+    fs::read_to_string(file)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<&str>>())
+        .find(|fields| fields.len() > 2 && fields[2] == id.to_string())
+        .map_or(id.to_string(), |fields| fields[0].to_string())
+}
@@ -0,0 +1,283 @@
+use crate::error::{Error, Result};
+use crate::paths::{is_within, trim_slash};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// The version of the tracking list format `save` writes.
+pub const LIST_VERSION: u32 = 1;
+
+/// A file or folder in the tracking list, with what dm should know about it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    /// Absolute path.
+    pub path: String,
+    /// Names of the groups the entry belongs to, e.g. `wm` or `shell`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Gitignore patterns for files below the entry that are left out: a pattern without a
+    /// slash matches at any depth, one with a slash or a leading `/` from the entry down, and
+    /// a trailing `/` matches folders only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// The files are stored encrypted.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub encrypt: bool,
+    /// The files are templates rendered per host.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub template: bool,
+    /// Hostnames the entry applies to, every host when empty. Other hosts neither stage nor
+    /// drop it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    /// Permission bits the files must have, in octal, e.g. `"0600"`, which `doctor` checks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// For a symlink to a file: the repository keeps the content of its target rather than
+    /// the link itself.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub follow: bool,
+}
+
+impl Entry {
+    pub fn new(path: &str) -> Entry {
+        Entry {
+            path: trim_slash(path).to_string(),
+            ..Entry::default()
+        }
+    }
+
+    /// Whether the entry applies to the host named `host`.
+    pub fn applies_to(&self, host: &str) -> bool {
+        self.hosts.is_empty() || self.hosts.iter().any(|h| h == host)
+    }
+
+    /// The required permission bits, if `mode` is set.
+    pub fn required_mode(&self) -> Option<u32> {
+        self.mode
+            .as_deref()
+            .and_then(|m| u32::from_str_radix(m, 8).ok())
+    }
+
+    /// The `exclude` patterns as glob pathspecs, without their magic, below `prefix`: the
+    /// entry's path relative to the top of the work-tree.
+    pub(crate) fn excluded(&self, prefix: &str) -> Vec<String> {
+        let mut globs = vec![];
+        for pattern in self.exclude.iter() {
+            let body = pattern.trim_matches('/');
+            if body.is_empty() {
+                continue;
+            }
+            let base = match pattern.starts_with('/') || body.contains('/') {
+                true => format!("{prefix}/{body}"),
+                false => format!("{prefix}/**/{body}"),
+            };
+            if !pattern.ends_with('/') {
+                globs.push(base.clone());
+            }
+            globs.push(format!("{base}/**"));
+        }
+        globs
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// The tracking list: the files and folders dm keeps in the repository, stored as TOML in
+/// `$DATA/list`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrackingList {
+    pub entries: Vec<Entry>,
+}
+
+/// The on-disk form of [`TrackingList`].
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ListFile {
+    version: u32,
+    #[serde(default, rename = "entry")]
+    entries: Vec<Entry>,
+}
+
+impl TrackingList {
+    /// Reads the list, migrating a list in the original format of one absolute path per line
+    /// to the current one; the original is kept next to it as `<file>.v0`. A file that is
+    /// neither fails with [`Error::InvalidList`].
+    pub fn load(file: &str) -> Result<TrackingList> {
+        let read = match fs::read_to_string(file) {
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(Error::io(format!("could not read '{file}'"), e)),
+        };
+        let invalid = |message: String| Error::InvalidList {
+            file: file.to_string(),
+            message,
+        };
+        if read.trim().is_empty() {
+            return Ok(TrackingList::default());
+        }
+        if let Some(list) = TrackingList::parse_v0(&read) {
+            fs::write(format!("{file}.v0"), &read)
+                .map_err(|e| Error::io(format!("could not back up '{file}'"), e))?;
+            list.save(file)?;
+            return Ok(list);
+        }
+        let parsed: ListFile = toml::from_str(&read).map_err(|e| invalid(e.message().into()))?;
+        if parsed.version != LIST_VERSION {
+            return Err(invalid(format!(
+                "unsupported version {}, expected {LIST_VERSION}",
+                parsed.version
+            )));
+        }
+        if let Some(entry) = parsed.entries.iter().find(|e| !e.path.starts_with('/')) {
+            return Err(invalid(format!("'{}' is not an absolute path", entry.path)));
+        }
+        let bad_mode =
+            |e: &&Entry| e.mode.is_some() && e.required_mode().is_none_or(|m| m > 0o7777);
+        if let Some(entry) = parsed.entries.iter().find(bad_mode) {
+            return Err(invalid(format!(
+                "mode '{}' of '{}' is not octal permission bits",
+                entry.mode.as_deref().unwrap_or_default(),
+                entry.path
+            )));
+        }
+        Ok(TrackingList {
+            entries: parsed.entries,
+        })
+    }
+
+    /// Parses the original format, in which every line is an absolute path.
+    fn parse_v0(read: &str) -> Option<TrackingList> {
+        let lines: Vec<&str> = read
+            .lines()
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .collect();
+        if !lines.iter().all(|l| l.starts_with('/')) {
+            return None;
+        }
+        Some(TrackingList {
+            entries: lines.into_iter().map(Entry::new).collect(),
+        })
+    }
+
+    pub fn save(&self, file: &str) -> Result<()> {
+        let list = ListFile {
+            version: LIST_VERSION,
+            entries: self.entries.clone(),
+        };
+        let serialized = toml::to_string(&list).map_err(|e| Error::InvalidList {
+            file: file.to_string(),
+            message: e.to_string(),
+        })?;
+        fs::write(file, serialized).map_err(|e| Error::io(format!("could not write '{file}'"), e))
+    }
+
+    /// The paths of the entries, in list order.
+    pub fn paths(&self) -> Vec<String> {
+        self.entries.iter().map(|e| e.path.clone()).collect()
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries.iter().any(|e| e.path == trim_slash(path))
+    }
+
+    pub fn get(&self, path: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.path == trim_slash(path))
+    }
+
+    /// The entry that covers `path`, either the path itself or a folder above it.
+    pub fn entry_for(&self, path: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| is_within(path, &e.path))
+    }
+
+    /// Adds `path`, replacing entries below it, and rejects paths already covered.
+    pub fn insert(&mut self, path: &str) -> Result<()> {
+        let path = trim_slash(path);
+        if let Some(entry) = self.entry_for(path) {
+            if entry.path == path {
+                return Err(Error::AlreadyTracked(path.to_string()));
+            }
+            return Err(Error::NestedEntry {
+                path: path.to_string(),
+                entry: entry.path.clone(),
+            });
+        }
+        self.entries.retain(|e| !is_within(&e.path, path));
+        self.entries.push(Entry::new(path));
+        Ok(())
+    }
+
+    /// The entries tagged `tag`, in list order.
+    pub fn tagged(&self, tag: &str) -> Vec<&Entry> {
+        self.entries
+            .iter()
+            .filter(|e| e.tags.iter().any(|t| t == tag))
+            .collect()
+    }
+
+    /// Every tag in use, sorted.
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self.entries.iter().flat_map(|e| e.tags.clone()).collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Adds `tags` to the entry at `path`, keeping those it already has.
+    pub fn tag(&mut self, path: &str, tags: &[&str]) -> Result<()> {
+        if let Some(tag) = tags.iter().find(|t| !is_valid_tag(t)) {
+            return Err(Error::InvalidTag(tag.to_string()));
+        }
+        let path = trim_slash(path);
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|e| e.path == path)
+            .ok_or_else(|| Error::NotTracked(path.to_string()))?;
+        for tag in tags {
+            if !entry.tags.iter().any(|t| t == tag) {
+                entry.tags.push(tag.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// The entries of symlinks whose target's content is kept, in list order.
+    pub fn followed(&self) -> Vec<&Entry> {
+        self.entries.iter().filter(|e| e.follow).collect()
+    }
+
+    /// Sets whether the repository keeps the target's content of the symlink entry at `path`.
+    pub fn set_follow(&mut self, path: &str, follow: bool) -> Result<()> {
+        let path = trim_slash(path);
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|e| e.path == path)
+            .ok_or_else(|| Error::NotTracked(path.to_string()))?;
+        entry.follow = follow;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, path: &str) -> Result<()> {
+        let path = trim_slash(path);
+        let size = self.entries.len();
+        self.entries.retain(|e| e.path != path);
+        if self.entries.len() == size {
+            return Err(Error::NotTracked(path.to_string()));
+        }
+        Ok(())
+    }
+}
+
+fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
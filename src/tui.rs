@@ -0,0 +1,562 @@
+//! Full-screen review of the dotfile changes: a file list, a live diff preview, staging and
+//! committing, drawn with crossterm on the alternate screen.
+//!
+//! Unstaging only lasts for the review: the next `DotRepo::stage_all`, which most commands
+//! run first, stages every change of the tracking list again.
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    queue,
+    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
+    terminal::{self, disable_raw_mode, enable_raw_mode, ClearType},
+    ExecutableCommand,
+};
+use dotmanager::{
+    diff::{FileDiff, LineKind},
+    DiffTarget, DotRepo, Error, Renderer, Restored, StatusEntry,
+};
+use std::{
+    io::{self, stdout, Write},
+    panic,
+    sync::Once,
+};
+
+use crate::page;
+
+/// How the review ended.
+pub enum Outcome {
+    Quit,
+    /// At least one commit was made; holds the last abbreviated hash.
+    Committed(String),
+}
+
+#[derive(PartialEq)]
+enum Mode {
+    Browse,
+    Filter,
+    Commit,
+    ConfirmDiscard,
+}
+
+struct Review<'a> {
+    repo: &'a DotRepo,
+    renderer: &'a Renderer,
+    entries: Vec<StatusEntry>,
+    filter: String,
+    selected: usize,
+    scroll: usize,
+    preview: Vec<(Color, String)>,
+    preview_for: Option<String>,
+    mode: Mode,
+    message: String,
+    notice: String,
+    committed: Option<String>,
+}
+
+/// Restores the terminal when dropped, including while unwinding from a panic.
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Screen> {
+        static HOOK: Once = Once::new();
+        HOOK.call_once(|| {
+            let default = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                restore_terminal();
+                default(info);
+            }));
+        });
+        enable_raw_mode()?;
+        let mut stdout = stdout();
+        stdout.execute(terminal::EnterAlternateScreen)?;
+        stdout.execute(cursor::Hide)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+fn restore_terminal() {
+    let mut stdout = stdout();
+    let _ = disable_raw_mode();
+    let _ = stdout.execute(cursor::Show);
+    let _ = stdout.execute(terminal::LeaveAlternateScreen);
+}
+
+/// Runs the review until the user quits.
+pub fn review(repo: &DotRepo, renderer: &Renderer) -> dotmanager::Result<Outcome> {
+    let mut review = Review {
+        repo,
+        renderer,
+        entries: vec![],
+        filter: String::new(),
+        selected: 0,
+        scroll: 0,
+        preview: vec![],
+        preview_for: None,
+        mode: Mode::Browse,
+        message: String::new(),
+        notice: String::new(),
+        committed: None,
+    };
+    review.refresh()?;
+
+    let mut screen = Some(Screen::enter().map_err(|e| Error::io("could not set up terminal", e))?);
+    loop {
+        review.update_preview();
+        review
+            .draw()
+            .map_err(|e| Error::io("could not draw to terminal", e))?;
+        let event = event::read().map_err(|e| Error::io("could not read from terminal", e))?;
+        let Event::Key(key) = event else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            break;
+        }
+        let done = match review.mode {
+            Mode::Browse => review.browse_key(key, &mut screen)?,
+            Mode::Filter => {
+                review.filter_key(key);
+                false
+            }
+            Mode::Commit => {
+                review.commit_key(key)?;
+                false
+            }
+            Mode::ConfirmDiscard => {
+                review.confirm_key(key)?;
+                false
+            }
+        };
+        if done {
+            break;
+        }
+    }
+    drop(screen);
+
+    Ok(match review.committed {
+        Some(hash) => Outcome::Committed(hash),
+        None => Outcome::Quit,
+    })
+}
+
+impl Review<'_> {
+    fn refresh(&mut self) -> dotmanager::Result<()> {
+        self.entries = self.repo.status()?.entries;
+        self.preview_for = None;
+        let visible = self.visible().len();
+        if self.selected >= visible {
+            self.selected = visible.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    fn visible(&self) -> Vec<&StatusEntry> {
+        let filter = self.filter.to_lowercase();
+        self.entries
+            .iter()
+            .filter(|e| filter.is_empty() || e.path.to_lowercase().contains(&filter))
+            .collect()
+    }
+
+    fn current(&self) -> Option<StatusEntry> {
+        self.visible().get(self.selected).map(|e| (*e).clone())
+    }
+
+    fn select(&mut self, index: usize) {
+        if index != self.selected {
+            self.selected = index;
+            self.scroll = 0;
+        }
+    }
+
+    fn update_preview(&mut self) {
+        let Some(entry) = self.current() else {
+            self.preview = vec![];
+            self.preview_for = None;
+            return;
+        };
+        let key = format!("{}{}{}", entry.path, entry.staged, entry.unstaged);
+        if self.preview_for.as_deref() == Some(key.as_str()) {
+            return;
+        }
+        self.preview_for = Some(key);
+        self.preview = vec![];
+        let sections = [
+            (entry.staged, DiffTarget::Staged, "staged"),
+            (entry.unstaged, DiffTarget::Unstaged, "not staged"),
+        ];
+        for (present, target, title) in sections {
+            if !present {
+                continue;
+            }
+            if entry.staged && entry.unstaged {
+                self.preview.push((Color::Yellow, format!("── {title} ──")));
+            }
+            let path = self.repo.live_path(&entry.path);
+            match self.repo.diff_files(&[&path], &target) {
+                Ok(files) => {
+                    for file in files.iter() {
+                        self.preview.extend(preview_lines(file));
+                    }
+                }
+                Err(e) => self.preview.push((Color::Yellow, e.to_string())),
+            }
+        }
+    }
+
+    fn browse_key(
+        &mut self,
+        key: KeyEvent,
+        screen: &mut Option<Screen>,
+    ) -> dotmanager::Result<bool> {
+        let count = self.visible().len();
+        let (_, height) = terminal::size().unwrap_or((80, 24));
+        let half = (height as usize / 2).max(1);
+        self.notice.clear();
+        match key.code {
+            KeyCode::Char('q') => return Ok(true),
+            KeyCode::Esc if self.filter.is_empty() => return Ok(true),
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.select(0);
+            }
+            KeyCode::Char('j') | KeyCode::Down | KeyCode::Tab if count > 0 => {
+                self.select((self.selected + 1) % count)
+            }
+            KeyCode::Char('k') | KeyCode::Up | KeyCode::BackTab if count > 0 => {
+                self.select((self.selected + count - 1) % count)
+            }
+            KeyCode::Char('g') | KeyCode::Home => self.select(0),
+            KeyCode::Char('G') | KeyCode::End => self.select(count.saturating_sub(1)),
+            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.scroll_preview(half as isize)
+            }
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.scroll_preview(-(half as isize))
+            }
+            KeyCode::Char('J') | KeyCode::PageDown => self.scroll_preview(1),
+            KeyCode::Char('K') | KeyCode::PageUp => self.scroll_preview(-1),
+            KeyCode::Char('/') => self.mode = Mode::Filter,
+            KeyCode::Char('r') => self.refresh()?,
+            KeyCode::Enter => {
+                if let Some(entry) = self.current() {
+                    *screen = None;
+                    let path = self.repo.live_path(&entry.path);
+                    let target = if entry.staged {
+                        DiffTarget::Staged
+                    } else {
+                        DiffTarget::Unstaged
+                    };
+                    if let Ok(files) = self.repo.diff_files(&[&path], &target) {
+                        page(&self.renderer.render(&files));
+                    }
+                    *screen = Some(
+                        Screen::enter().map_err(|e| Error::io("could not set up terminal", e))?,
+                    );
+                }
+            }
+            KeyCode::Char('s') => {
+                if let Some(entry) = self.current() {
+                    self.report(self.repo.stage(&self.repo.live_path(&entry.path)), "staged");
+                    self.refresh()?;
+                }
+            }
+            KeyCode::Char('u') => {
+                if let Some(entry) = self.current() {
+                    self.report(
+                        self.repo.unstage(&self.repo.live_path(&entry.path)),
+                        "unstaged until changes are staged again",
+                    );
+                    self.refresh()?;
+                }
+            }
+            KeyCode::Char('x') if self.current().is_some() => self.mode = Mode::ConfirmDiscard,
+            KeyCode::Char('c') => {
+                if self.entries.iter().any(|e| e.staged) {
+                    self.mode = Mode::Commit;
+                } else {
+                    self.notice = "nothing staged to commit".to_string();
+                }
+            }
+            KeyCode::Char('p') => match self.repo.push() {
+                Ok(()) => self.notice = "pushed".to_string(),
+                Err(e) => self.notice = e.to_string(),
+            },
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    fn filter_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Enter => self.mode = Mode::Browse,
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.mode = Mode::Browse;
+            }
+            KeyCode::Backspace => {
+                self.filter.pop();
+            }
+            KeyCode::Char(c) => self.filter.push(c),
+            _ => {}
+        }
+        self.select(0);
+    }
+
+    fn commit_key(&mut self, key: KeyEvent) -> dotmanager::Result<()> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => self.mode = Mode::Browse,
+            KeyCode::Char('s') if ctrl => {
+                let message = self.message.trim().to_string();
+                if message.is_empty() {
+                    self.notice = "empty commit message".to_string();
+                    return Ok(());
+                }
+                match self.repo.commit(&message) {
+                    Ok(hash) => {
+                        self.notice = format!("committed {hash}, press p to push");
+                        self.committed = Some(hash);
+                        self.message.clear();
+                        self.mode = Mode::Browse;
+                        self.refresh()?;
+                    }
+                    Err(e) => self.notice = e.to_string(),
+                }
+            }
+            KeyCode::Char('u') if ctrl => {
+                let keep = self.message.rfind('\n').map(|i| i + 1).unwrap_or(0);
+                self.message.truncate(keep);
+            }
+            KeyCode::Enter => self.message.push('\n'),
+            KeyCode::Backspace => {
+                self.message.pop();
+            }
+            KeyCode::Char(c) if !ctrl => self.message.push(c),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn confirm_key(&mut self, key: KeyEvent) -> dotmanager::Result<()> {
+        self.mode = Mode::Browse;
+        if let (KeyCode::Char('y'), Some(entry)) = (key.code, self.current()) {
+            self.notice = match self.repo.restore(&self.repo.live_path(&entry.path), None) {
+                Ok(Restored {
+                    backup: Some(dir), ..
+                }) => format!("discarded, backup in {dir}"),
+                Ok(_) => "discarded".to_string(),
+                Err(e) => e.to_string(),
+            };
+            self.refresh()?;
+        }
+        Ok(())
+    }
+
+    fn report(&mut self, result: dotmanager::Result<()>, done: &str) {
+        self.notice = match result {
+            Ok(()) => done.to_string(),
+            Err(e) => e.to_string(),
+        };
+    }
+
+    fn scroll_preview(&mut self, by: isize) {
+        let max = self.preview.len().saturating_sub(1) as isize;
+        self.scroll = (self.scroll as isize + by).clamp(0, max.max(0)) as usize;
+    }
+
+    fn draw(&self) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        let mut out = stdout();
+        queue!(out, cursor::MoveTo(0, 0), terminal::Clear(ClearType::All))?;
+
+        let title = format!(" dm review ─ {}/", self.repo.home());
+        queue!(
+            out,
+            SetAttribute(Attribute::Bold),
+            Print(fit(&title, width)),
+            SetAttribute(Attribute::Reset)
+        )?;
+
+        let editor_height = if self.mode == Mode::Commit { 8 } else { 0 };
+        let body = height.saturating_sub(2 + editor_height);
+        let visible = self.visible();
+        let longest = visible.iter().map(|e| e.path.len() + 5).max().unwrap_or(10);
+        let list_width = longest.clamp(20, (width / 3).max(20)).min(width);
+        let preview_width = width.saturating_sub(list_width + 3);
+
+        let offset = self.selected.saturating_sub(body.saturating_sub(1));
+        for row in 0..body {
+            queue!(out, cursor::MoveTo(0, (row + 1) as u16))?;
+            match visible.get(row + offset) {
+                Some(entry) => self.draw_entry(&mut out, entry, row + offset, list_width)?,
+                None if row == 0 && visible.is_empty() => {
+                    let empty = if self.entries.is_empty() {
+                        " Up to date"
+                    } else {
+                        " no match"
+                    };
+                    queue!(out, Print(fit(empty, list_width)))?;
+                }
+                None => queue!(out, Print(" ".repeat(list_width)))?,
+            }
+            queue!(
+                out,
+                SetForegroundColor(Color::DarkGrey),
+                Print(" │ "),
+                ResetColor
+            )?;
+            if let Some((color, line)) = self.preview.get(row + self.scroll) {
+                queue!(
+                    out,
+                    SetForegroundColor(*color),
+                    Print(fit(line, preview_width)),
+                    ResetColor
+                )?;
+            }
+        }
+
+        if self.mode == Mode::Commit {
+            self.draw_editor(&mut out, body + 1, width, editor_height)?;
+        }
+
+        queue!(out, cursor::MoveTo(0, (height - 1) as u16))?;
+        let status = match self.mode {
+            Mode::Filter => format!("/{}", self.filter),
+            Mode::ConfirmDiscard => match self.current() {
+                Some(e) => format!("discard the changes to {} and restore the committed version? (y/n)", e.path),
+                None => String::new(),
+            },
+            Mode::Commit => "ctrl-s commit · esc cancel · ctrl-u clear line".to_string(),
+            Mode::Browse if !self.notice.is_empty() => self.notice.clone(),
+            Mode::Browse => {
+                "j/k move · enter page · s stage · u unstage · x discard · c commit · p push · / filter · q quit"
+                    .to_string()
+            }
+        };
+        queue!(
+            out,
+            SetAttribute(Attribute::Reverse),
+            Print(fit(&status, width)),
+            SetAttribute(Attribute::Reset)
+        )?;
+        out.flush()
+    }
+
+    fn draw_entry(
+        &self,
+        out: &mut impl Write,
+        entry: &StatusEntry,
+        index: usize,
+        width: usize,
+    ) -> io::Result<()> {
+        let letter = match entry.change {
+            dotmanager::Change::Added => 'A',
+            dotmanager::Change::Deleted => 'D',
+            dotmanager::Change::Modified => 'M',
+        };
+        let staged = if entry.staged { letter } else { ' ' };
+        let unstaged = if entry.unstaged { letter } else { ' ' };
+        let selected = index == self.selected;
+        let marker = if selected { "❯" } else { " " };
+        queue!(
+            out,
+            SetForegroundColor(Color::Green),
+            Print(marker),
+            Print(staged),
+            SetForegroundColor(Color::Red),
+            Print(unstaged),
+            ResetColor,
+            Print(' ')
+        )?;
+        if selected {
+            queue!(out, SetForegroundColor(Color::Cyan))?;
+        }
+        queue!(
+            out,
+            Print(fit(&entry.path, width.saturating_sub(4))),
+            ResetColor
+        )
+    }
+
+    fn draw_editor(
+        &self,
+        out: &mut impl Write,
+        top: usize,
+        width: usize,
+        height: usize,
+    ) -> io::Result<()> {
+        queue!(
+            out,
+            cursor::MoveTo(0, top as u16),
+            SetForegroundColor(Color::Yellow),
+            Print(fit("── commit message ", width).replace(' ', "─")),
+            ResetColor
+        )?;
+        let lines: Vec<&str> = self.message.split('\n').collect();
+        let shown = height - 1;
+        let first = lines.len().saturating_sub(shown);
+        for row in 0..shown {
+            queue!(out, cursor::MoveTo(0, (top + 1 + row) as u16))?;
+            let mut line = lines.get(first + row).copied().unwrap_or("").to_string();
+            if first + row == lines.len() - 1 {
+                line.push('▏');
+            }
+            queue!(out, Print(fit(&format!(" {line}"), width)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Colored lines of a diff for the preview pane.
+fn preview_lines(file: &FileDiff) -> Vec<(Color, String)> {
+    let header = FileDiff {
+        hunks: vec![],
+        ..file.clone()
+    };
+    let mut lines: Vec<(Color, String)> = Renderer::plain()
+        .render_file(&header)
+        .lines()
+        .map(|l| (Color::White, l.to_string()))
+        .collect();
+    for hunk in file.hunks.iter() {
+        lines.push((Color::Cyan, format!("{} {}", hunk.header(), hunk.section)));
+        for line in hunk.lines.iter() {
+            let (color, sign) = match line.kind {
+                LineKind::Added => (Color::Green, '+'),
+                LineKind::Removed => (Color::Red, '-'),
+                LineKind::Context => (Color::Reset, ' '),
+            };
+            lines.push((
+                color,
+                format!("{sign}{}", line.content.replace('\t', "    ")),
+            ));
+        }
+    }
+    lines
+}
+
+/// Pads or cuts `s` to exactly `width` characters.
+fn fit(s: &str, width: usize) -> String {
+    let count = s.chars().count();
+    if count <= width {
+        format!("{s}{}", " ".repeat(width - count))
+    } else if width > 0 {
+        let mut cut: String = s.chars().take(width - 1).collect();
+        cut.push('…');
+        cut
+    } else {
+        String::new()
+    }
+}
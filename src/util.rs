@@ -1,15 +1,15 @@
 use prettytable::Table;
 
-
 pub struct StatusInfo {
     pub work_tree: String,
     pub remote_url: String,
     pub status: String,
-    pub status_entries: Vec<(String, String)>,
     pub entry_type_counts: Vec<i32>,
     pub table: Table,
     pub summary: String,
     pub summary_short: String,
+    /// Files whose permissions changed, one per line.
+    pub drift: String,
 }
 
 impl Default for StatusInfo {
@@ -18,58 +18,24 @@ impl Default for StatusInfo {
             work_tree: String::new(),
             remote_url: String::new(),
             status: String::new(),
-            status_entries: vec![],
             entry_type_counts: vec![],
             table: Table::new(),
             summary: String::new(),
             summary_short: String::new(),
+            drift: String::new(),
         }
     }
 }
 
-pub mod user_paths {
-
-    use dirs::{data_dir, home_dir};
-    use once_cell::sync::Lazy;
-    use std::process::exit;
-
-    pub static HOME: Lazy<String> = Lazy::new(|| match home_dir() {
-        Some(p) => p.into_os_string().into_string().unwrap(),
-        None => {
-            println!("Could not find $HOME");
-            exit(2);
-        }
-    });
-
-    static DATA: Lazy<String> = Lazy::new(|| match data_dir() {
-        Some(mut p) => {
-            p.push("dotmanager");
-            p.into_os_string().into_string().unwrap()
-        }
-        None => {
-            println!("Could not find DATA directory");
-            exit(2);
-        }
-    });
-
-    pub static GIT: Lazy<String> = Lazy::new(|| {
-        let mut git = DATA.to_string();
-        git.push_str("/git");
-        git
-    });
-
-    pub static LIST: Lazy<String> = Lazy::new(|| {
-        let mut list = DATA.to_string();
-        list.push_str("/list");
-        list
-    });
-}
-
 pub mod functions {
 
+    use dotmanager::paths::{self, DATA_ENV, HOME_ENV};
+    use dotmanager::Error;
     use prettytable::{format, Table};
     use std::io::Write;
-    use std::{fs, io};
+    use std::path::Path;
+    use std::time::SystemTime;
+    use std::{env, io};
 
     pub fn new_table() -> Table {
         let mut table = Table::new();
@@ -101,7 +67,7 @@ pub mod functions {
 
         let mut input = String::new();
         match io::stdin().read_line(&mut input) {
-            Ok(_n) => input.trim().to_lowercase(),
+            Ok(_n) => input.trim().to_string(),
             Err(error) => {
                 eprintln!("error: {error}");
                 String::new()
@@ -158,40 +124,209 @@ pub mod functions {
         (cmd, arg)
     }
 
-    pub fn print_path_error(msgtype: &str, msg: &str, path: &String) {
-        let red = "\u{1b}[31m";
-        let yellow = "\u{1b}[33m";
-        let bold = "\u{1b}[1m";
-        let end = "\u{1b}[0m";
-        let color = if msgtype == "error" { red } else { yellow };
-        let msg_type = format!("{}{}{}{}", color, bold, msgtype, end);
-        println!("{}{}:{} '{}': {}", msg_type, bold, end, path, msg);
+    /// Long options and positional arguments that follow the command.
+    #[derive(Default)]
+    pub struct Options {
+        pub positional: Vec<String>,
+        flags: Vec<(String, Vec<String>)>,
+    }
+
+    impl Options {
+        pub fn flag(&self, name: &str) -> bool {
+            self.flags.iter().any(|(n, _)| n == name)
+        }
+
+        /// The values of the last occurrence of `name`.
+        pub fn values(&self, name: &str) -> Option<&[String]> {
+            self.flags
+                .iter()
+                .rev()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_slice())
+        }
+
+        pub fn value(&self, name: &str) -> Option<&str> {
+            self.values(name)
+                .and_then(|v| v.first())
+                .map(|v| v.as_str())
+        }
     }
 
-    pub fn split_cmd(cmd: String) -> Vec<String> {
-        let mut split = vec![String::new()];
-        let mut index = 0;
-        let mut in_quotes = false;
-        for c in cmd.chars() {
-            if c == '"' {
-                in_quotes = !in_quotes;
-            } else if c == ' ' && !in_quotes {
-                index += 1;
-                split.push(String::new());
+    /// Parses `args` against `spec`, a comma separated list of long option names. A trailing
+    /// ':' marks an option that takes a value and '::' one that takes two, e.g.
+    /// `"side-by-side, rev:, between::"`.
+    pub fn parse_options(args: &[String], spec: &str) -> Result<Options, String> {
+        let known: Vec<(&str, usize)> = spec
+            .split(',')
+            .map(|o| o.trim())
+            .filter(|o| !o.is_empty())
+            .map(|o| {
+                let name = o.trim_end_matches(':');
+                (name, o.len() - name.len())
+            })
+            .collect();
+
+        let mut options = Options::default();
+        let mut iter = args.iter();
+        let mut only_positional = false;
+        while let Some(arg) = iter.next() {
+            let arg = arg.trim();
+            if only_positional || !arg.starts_with("--") {
+                options.positional.push(arg.to_string());
+                continue;
+            }
+            if arg == "--" {
+                only_positional = true;
                 continue;
             }
-            split[index] = format!("{}{}", split[index], c);
+            let (name, inline) = match arg[2..].split_once('=') {
+                Some((n, v)) => (n, Some(v.to_string())),
+                None => (&arg[2..], None),
+            };
+            let Some((name, arity)) = known.iter().find(|(n, _)| *n == name) else {
+                return Err(format!("unknown option '--{name}'"));
+            };
+            let mut values: Vec<String> = inline.into_iter().collect();
+            if *arity == 0 && !values.is_empty() {
+                return Err(format!("'--{name}' does not take a value"));
+            }
+            while values.len() < *arity {
+                match iter.next() {
+                    Some(v) => values.push(v.trim().to_string()),
+                    None => return Err(format!("'--{name}' requires {arity} value(s)")),
+                }
+            }
+            options.flags.push((name.to_string(), values));
         }
-        split
+        Ok(options)
     }
 
-    pub fn file_to_vec(file: &str) -> Vec<String> {
-        let read = fs::read_to_string(file).expect("err");
-        let paths: Vec<String> = read.trim().split('\n').map(|p| p.to_string()).collect();
-        paths
+    /// Formats a byte count with a binary unit, e.g. `1.5 KiB`.
+    pub fn human_size(bytes: u64) -> String {
+        let units = ["B", "KiB", "MiB", "GiB"];
+        let mut size = bytes as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < units.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        match unit {
+            0 => format!("{bytes} B"),
+            _ => format!("{size:.1} {}", units[unit]),
+        }
     }
 
-    // pub fn clear_screen() {
-    //     print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
-    // }
+    /// How long ago `time` was, in the largest whole unit, e.g. `3 days ago`.
+    pub fn age(time: SystemTime) -> String {
+        let secs = SystemTime::now()
+            .duration_since(time)
+            .map_or(0, |d| d.as_secs());
+        let units = [
+            (365 * 86400, "year"),
+            (30 * 86400, "month"),
+            (86400, "day"),
+            (3600, "hour"),
+            (60, "minute"),
+        ];
+        match units.iter().find(|(length, _)| secs >= *length) {
+            Some((length, unit)) => {
+                let n = secs / length;
+                format!("{n} {unit}{} ago", if n == 1 { "" } else { "s" })
+            }
+            None => "just now".to_string(),
+        }
+    }
+
+    /// Resolves a command line path against HOME and the current directory.
+    pub fn resolve_path(home: &str, input: &str) -> String {
+        let cwd = env::current_dir()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|_| home.to_string());
+        paths::resolve(input, home, &cwd)
+    }
+
+    /// Strips the global `--home` and `--data-dir` options from `args` and exports them as
+    /// `DM_HOME`/`DM_DATA_DIR`, so they take precedence over the environment. Must run before
+    /// the repository is opened.
+    pub fn extract_global_options(args: &mut Vec<String>) -> Result<(), String> {
+        let options = [("--home", HOME_ENV), ("--data-dir", DATA_ENV)];
+        let mut i = 1;
+        while i < args.len() {
+            // Everything after a passthrough command belongs to it.
+            if args[i] == "--git" || args[i] == "--exec" {
+                break;
+            }
+            let mut matched = false;
+            for (flag, var) in options {
+                let value = if args[i] == flag {
+                    if i + 1 >= args.len() {
+                        return Err(format!("'{flag}' requires a path"));
+                    }
+                    let value = args.remove(i + 1);
+                    args.remove(i);
+                    value
+                } else if let Some(value) = args[i].strip_prefix(&format!("{flag}=")) {
+                    let value = value.to_string();
+                    args.remove(i);
+                    value
+                } else {
+                    continue;
+                };
+                if value.trim().is_empty() {
+                    return Err(format!("'{flag}' requires a path"));
+                }
+                env::set_var(var, absolute_path(&value));
+                matched = true;
+                break;
+            }
+            if !matched {
+                i += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn absolute_path(path: &str) -> String {
+        let path = Path::new(path);
+        if path.is_absolute() {
+            return path.to_string_lossy().to_string();
+        }
+        match env::current_dir() {
+            Ok(cwd) => cwd.join(path).to_string_lossy().to_string(),
+            Err(_) => path.to_string_lossy().to_string(),
+        }
+    }
+
+    /// Prints a library error in the same shape as `print_path_error`.
+    pub fn print_error(err: &Error) {
+        let msgtype = if err.is_warning() { "warn" } else { "error" };
+        match err.path() {
+            Some(path) => print_path_error(msgtype, &err.message(), path),
+            None => {
+                let red = "\u{1b}[31m";
+                let bold = "\u{1b}[1m";
+                let end = "\u{1b}[0m";
+                println!(
+                    "{}{}{}{}{}:{} {}",
+                    red,
+                    bold,
+                    msgtype,
+                    end,
+                    bold,
+                    end,
+                    err.message()
+                );
+            }
+        }
+    }
+
+    pub fn print_path_error(msgtype: &str, msg: &str, path: &str) {
+        let red = "\u{1b}[31m";
+        let yellow = "\u{1b}[33m";
+        let bold = "\u{1b}[1m";
+        let end = "\u{1b}[0m";
+        let color = if msgtype == "error" { red } else { yellow };
+        let msg_type = format!("{}{}{}{}", color, bold, msgtype, end);
+        println!("{}{}:{} '{}': {}", msg_type, bold, end, path, msg);
+    }
 }
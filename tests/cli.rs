@@ -0,0 +1,584 @@
+mod common;
+
+use common::{strip_ansi, text, Sandbox};
+
+#[test]
+fn init_creates_repo_and_pushes_initial_commit() {
+    let sb = Sandbox::initialized();
+
+    assert!(sb.git_dir().join("HEAD").exists());
+    assert!(sb.list_file().exists());
+    assert!(sb.home.join(".github/README.md").exists());
+    assert_eq!(
+        sb.git(&["config", "--local", "status.showUntrackedFiles"])
+            .trim(),
+        "no"
+    );
+    assert_eq!(
+        sb.git(&["remote", "get-url", "origin"]).trim(),
+        sb.remote_url()
+    );
+    assert_eq!(
+        sb.remote_git(&["log", "--format=%s", "main"]).trim(),
+        "Initial commit"
+    );
+}
+
+#[test]
+fn add_tracks_and_stages_path() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "export EDITOR=nvim\n");
+    sb.write(".config/nvim/init.lua", "vim.o.number = true\n");
+    let nvim = sb.path(".config/nvim");
+
+    assert!(sb.dm(&["--add", &zshrc]).status.success());
+    assert!(sb.dm(&["--add", &nvim]).status.success());
+
+    assert_eq!(sb.tracking_list(), vec![zshrc, nvim]);
+    let staged = sb.git(&["diff", "--cached", "--name-only"]);
+    assert!(staged.contains(".zshrc"));
+    assert!(staged.contains(".config/nvim/init.lua"));
+}
+
+#[test]
+fn add_rejects_duplicates_and_nested_entries() {
+    let sb = Sandbox::initialized();
+    sb.write(".config/nvim/init.lua", "");
+    let nvim = sb.path(".config/nvim");
+    let init = sb.path(".config/nvim/init.lua");
+    assert!(sb.dm(&["--add", &nvim]).status.success());
+
+    let output = sb.dm(&["--add", &nvim]);
+    assert!(!output.status.success());
+    assert!(text(&output).contains("is already in the tracking list"));
+
+    let output = sb.dm(&["--add", &init]);
+    assert!(!output.status.success());
+    assert!(text(&output).contains("entry exists at lower depth"));
+    assert_eq!(sb.tracking_list(), vec![nvim]);
+}
+
+#[test]
+fn add_missing_path_fails() {
+    let sb = Sandbox::initialized();
+    let output = sb.dm(&["--add", &sb.path(".nope")]);
+    assert!(!output.status.success());
+    assert!(text(&output).contains("did not match any files or folders"));
+    assert!(sb.tracking_list().is_empty());
+}
+
+#[test]
+fn remove_untracks_path() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "a\n");
+    let vimrc = sb.write(".vimrc", "b\n");
+    sb.dm(&["--add", &zshrc]);
+    sb.dm(&["--add", &vimrc]);
+
+    assert!(sb.dm(&["--remove", &zshrc]).status.success());
+    assert_eq!(sb.tracking_list(), vec![vimrc]);
+    let staged = sb.git(&["diff", "--cached", "--name-only"]);
+    assert!(!staged.contains(".zshrc"));
+    assert!(staged.contains(".vimrc"));
+}
+
+#[test]
+fn status_reports_changes() {
+    let sb = Sandbox::initialized();
+    let output = text(&sb.dm(&["--status"]));
+    assert!(output.contains("Up to date"));
+    assert!(output.contains(&sb.remote_url()));
+
+    let zshrc = sb.write(".zshrc", "a\n");
+    sb.dm(&["--add", &zshrc]);
+    let output = text(&sb.dm(&["--status"]));
+    assert!(output.contains("new file"));
+    assert!(output.contains("/.zshrc"));
+    assert!(output.contains("new files: 1"));
+
+    let output = text(&sb.dm(&["--status-summary"]));
+    assert_eq!(output.trim(), "+1");
+}
+
+#[test]
+fn status_picks_up_edits_in_tracked_folders() {
+    let sb = Sandbox::initialized();
+    sb.write(".config/nvim/init.lua", "a\n");
+    sb.dm(&["--add", &sb.path(".config/nvim")]);
+    sb.git(&["commit", "-qm", "nvim"]);
+
+    sb.write(".config/nvim/init.lua", "b\n");
+    sb.write(".config/nvim/lua/plugins.lua", "c\n");
+    let output = text(&sb.dm(&["--status"]));
+    assert!(output.contains("modified"));
+    assert!(output.contains("/.config/nvim/init.lua"));
+    assert!(output.contains("/.config/nvim/lua/plugins.lua"));
+    assert_eq!(text(&sb.dm(&["--status-summary"])).trim(), "+1 ~1");
+}
+
+#[test]
+fn list_shows_tracked_entries_relative_to_home() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "a\n");
+    sb.write(".config/nvim/init.lua", "");
+    sb.dm(&["--add", &zshrc]);
+    sb.dm(&["--add", &sb.path(".config/nvim")]);
+
+    let output = strip_ansi(&text(&sb.dm(&["--list"])));
+    assert!(output.contains("└── .zshrc"));
+    assert!(output.contains("├── .config/\n"));
+    assert!(output.contains("│   └── nvim/"));
+    assert!(!output.contains(sb.home.to_str().unwrap()));
+}
+
+#[test]
+fn list_shows_state_counts_and_expanded_files() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "a\n");
+    let vimrc = sb.write(".vimrc", "set number\n");
+    sb.write(".config/nvim/init.lua", "vim.o.number = true\n");
+    sb.write(".config/nvim/lua/plugins.lua", "return {}\n");
+    for path in [&zshrc, &vimrc, &sb.path(".config/nvim")] {
+        sb.dm(&["--add", path]);
+    }
+    sb.git(&["commit", "-qm", "track"]);
+    sb.write(".zshrc", "b\n");
+    std::fs::remove_file(&vimrc).unwrap();
+
+    let output = strip_ansi(&text(&sb.dm(&["--list"])));
+    let line = |name: &str| {
+        output
+            .lines()
+            .find(|l| l.contains(name))
+            .unwrap_or_else(|| panic!("no line for {name} in {output}"))
+            .to_string()
+    };
+    assert!(line("nvim/").contains("folder"));
+    assert!(line("nvim/").contains("2 files"));
+    assert!(line("nvim/").contains("30 B"));
+    assert!(line("nvim/").ends_with("clean"));
+    assert!(line(".zshrc").contains("file"));
+    assert!(line(".zshrc").ends_with("modified"));
+    assert!(line(".vimrc").ends_with("missing"));
+    assert!(!output.contains("plugins.lua"));
+    // Listing leaves vanished entries in the list.
+    assert_eq!(sb.tracking_list().len(), 3);
+
+    let output = strip_ansi(&text(&sb.dm(&["--list", "--expand"])));
+    assert!(output.contains("├── init.lua"));
+    assert!(output.contains("└── lua/"));
+    assert!(output.contains("└── plugins.lua"));
+}
+
+#[test]
+fn tags_group_the_list_and_scope_status_and_diff() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "a\n");
+    sb.write(".config/sway/config", "bar\n");
+    sb.write(".config/waybar/config", "modules\n");
+    let vimrc = sb.write(".vimrc", "set number\n");
+    sb.dm(&["--add", &zshrc, "--tag", "shell"]);
+    sb.dm(&["--add", &sb.path(".config/sway"), "--tag", "wm"]);
+    sb.dm(&["--add", &sb.path(".config/waybar"), "--tag", "wm,bar"]);
+    sb.dm(&["--add", &vimrc]);
+    // Tagging an entry that is tracked already merges the tags.
+    let output = sb.dm(&["--add", &zshrc, "--tag", "shell,login"]);
+    assert!(output.status.success(), "{}", text(&output));
+    let list = std::fs::read_to_string(sb.list_file()).unwrap();
+    assert!(list.contains("tags = [\"shell\", \"login\"]"), "{list}");
+
+    let output = strip_ansi(&text(&sb.dm(&["--list"])));
+    let groups: Vec<&str> = output.split("\n\n").collect();
+    let group = |title: &str| {
+        groups
+            .iter()
+            .find(|g| g.contains(title))
+            .unwrap_or_else(|| panic!("no group {title} in {output}"))
+            .to_string()
+    };
+    assert!(group("Tag wm:").contains("sway/"));
+    assert!(group("Tag wm:").contains("waybar/"));
+    assert!(!group("Tag wm:").contains(".zshrc"));
+    assert!(group("Tag bar:").contains("waybar/"));
+    assert!(group("Untagged:").contains(".vimrc"));
+    let output = strip_ansi(&text(&sb.dm(&["--list", "--tag", "shell"])));
+    assert!(output.contains(".zshrc"));
+    assert!(!output.contains("sway"));
+
+    let output = strip_ansi(&text(&sb.dm(&["--status", "--tag", "wm"])));
+    assert!(output.contains("/.config/sway/config"));
+    assert!(output.contains("/.config/waybar/config"));
+    assert!(!output.contains("/.zshrc"));
+    assert!(!output.contains("/.vimrc"));
+
+    let output = text(&sb.dm(&["--diff", "--tag", "shell"]));
+    assert!(output.contains(".zshrc"));
+    assert!(!output.contains("sway"));
+
+    let output = sb.dm(&["--status", "--tag", "nvim"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(text(&output).contains("no tracking entry is tagged 'nvim'"));
+    let output = sb.dm(&["--add", &vimrc, "--tag", "two words"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(text(&output).contains("invalid tag 'two words'"));
+}
+
+#[test]
+fn vanished_entries_are_dropped_from_the_list() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "a\n");
+    let vimrc = sb.write(".vimrc", "b\n");
+    sb.dm(&["--add", &zshrc]);
+    sb.dm(&["--add", &vimrc]);
+    std::fs::remove_file(&vimrc).unwrap();
+
+    sb.dm(&["--status"]);
+    assert_eq!(sb.tracking_list(), vec![zshrc]);
+}
+
+#[test]
+fn home_and_data_dir_flags_override_environment() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "a\n");
+    let output = std::process::Command::new(common::DM)
+        .env_remove("DM_HOME")
+        .env_remove("DM_DATA_DIR")
+        .env("HOME", "/nonexistent")
+        .arg("--home")
+        .arg(&sb.home)
+        .arg(format!("--data-dir={}", sb.data.display()))
+        .args(["--add", &zshrc])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(sb.tracking_list(), vec![zshrc]);
+}
+
+#[test]
+fn invalid_arguments_print_help() {
+    let sb = Sandbox::new();
+    let output = sb.dm(&["--bogus"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(text(&output).contains("Usage"));
+}
+
+#[test]
+fn restore_puts_committed_version_back_and_backs_up_live_file() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "one\n");
+    sb.dm(&["--add", &zshrc]);
+    sb.git(&["commit", "-qm", "one"]);
+    sb.write(".zshrc", "two\n");
+    sb.git(&["commit", "-qam", "two"]);
+    sb.write(".zshrc", "broken\n");
+
+    let output = sb.dm(&["--restore", "~/.zshrc"]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(sb.read(".zshrc"), "two\n");
+    let out = text(&output);
+    let backup = out
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("Backup:"))
+        .expect("backup line")
+        .trim();
+    assert_eq!(
+        std::fs::read_to_string(format!("{backup}/.zshrc")).unwrap(),
+        "broken\n"
+    );
+
+    let output = sb.dm(&["--restore", &zshrc, "--rev", "HEAD~1"]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(sb.read(".zshrc"), "one\n");
+    // Nothing was overwritten that differs from the commit, so no backup this time.
+    assert!(!text(&sb.dm(&["--restore", &zshrc, "--rev", "HEAD~1"])).contains("Backup:"));
+
+    let output = sb.dm(&["--restore", &sb.path(".nope")]);
+    assert!(!output.status.success());
+    assert!(text(&output).contains("does not exist in 'HEAD'"));
+}
+
+#[test]
+fn modes_are_recorded_and_given_back_on_restore_and_clone() {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = |path: &str| std::fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+    let chmod = |path: &str, mode: u32| {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap()
+    };
+    let origin = Sandbox::initialized();
+    let ssh = origin.write(".ssh/config", "Host *\n");
+    chmod(&ssh, 0o600);
+    origin.dm(&["--add", &origin.path(".ssh")]);
+    origin.git(&["commit", "-qm", "ssh"]);
+    origin.git(&["push", "--quiet"]);
+    let modes = origin.git(&["show", "HEAD:.dm/modes.toml"]);
+    assert!(
+        modes.contains("path = \".ssh/config\"\nmode = \"0600\""),
+        "{modes}"
+    );
+    assert!(!origin.home.join(".dm").exists());
+
+    chmod(&ssh, 0o644);
+    let output = strip_ansi(&text(&origin.dm(&["--status"])));
+    assert!(output.contains("Permissions:"), "{output}");
+    assert!(output.contains("/.ssh/config  0600 → 0644"), "{output}");
+    assert!(!output.contains("Up to date"));
+
+    let output = origin.dm(&["--restore", &ssh]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(mode(&ssh), 0o600);
+    assert!(strip_ansi(&text(&origin.dm(&["--status"]))).contains("Up to date"));
+
+    let machine = Sandbox::new();
+    let output = machine.dm(&["--clone", &origin.remote_url()]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(mode(&machine.path(".ssh/config")), 0o600);
+    assert!(!machine.home.join(".dm").exists());
+    assert!(strip_ansi(&text(&machine.dm(&["--status"]))).contains("Up to date"));
+}
+
+#[test]
+fn empty_folders_and_symlinks_are_tracked() {
+    use std::os::unix::fs::symlink;
+    let origin = Sandbox::initialized();
+    let state = origin.path(".local/state/foo");
+    std::fs::create_dir_all(&state).unwrap();
+    origin.write(".config/alacritty/themes/dark.toml", "dark\n");
+    origin.write(".config/alacritty/themes/light.toml", "light\n");
+    let theme = origin.path(".config/alacritty/theme.toml");
+    symlink("themes/dark.toml", &theme).unwrap();
+    let dangling = origin.path(".dangling");
+    symlink("nowhere", &dangling).unwrap();
+    for path in [&state, &theme, &dangling] {
+        let output = origin.dm(&["--add", path]);
+        assert!(output.status.success(), "{}", text(&output));
+    }
+    origin.git(&["commit", "-qm", "links"]);
+    origin.git(&["push", "--quiet"]);
+    // Git keeps the links themselves, and dm the empty folder.
+    assert_eq!(
+        origin.git(&["show", "HEAD:.config/alacritty/theme.toml"]),
+        "themes/dark.toml"
+    );
+    let modes = origin.git(&["show", "HEAD:.dm/modes.toml"]);
+    assert!(
+        modes.contains("[[dir]]\npath = \".local/state/foo\""),
+        "{modes}"
+    );
+    let output = strip_ansi(&text(&origin.dm(&["--list"])));
+    assert!(output.contains("-> themes/dark.toml"), "{output}");
+    assert!(output
+        .lines()
+        .any(|l| l.contains("foo/") && l.contains("folder") && l.ends_with("clean")));
+
+    std::fs::remove_file(&theme).unwrap();
+    symlink("themes/light.toml", &theme).unwrap();
+    let output = strip_ansi(&text(&origin.dm(&["--status"])));
+    assert!(
+        output.contains(
+            "/.config/alacritty/theme.toml -> themes/light.toml (was -> themes/dark.toml)"
+        ),
+        "{output}"
+    );
+    origin.git(&["commit", "-qm", "light"]);
+
+    // Following the link keeps the target's content, and git leaves the link alone.
+    let output = origin.dm(&["--add", &theme, "--follow"]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(
+        origin.git(&["show", ":.config/alacritty/theme.toml"]),
+        "light\n"
+    );
+    origin.git(&["commit", "-qm", "follow"]);
+    assert!(strip_ansi(&text(&origin.dm(&["--status"]))).contains("Up to date"));
+    origin.write(".config/alacritty/themes/light.toml", "changed\n");
+    let output = strip_ansi(&text(&origin.dm(&["--status"])));
+    assert!(output.contains("/.config/alacritty/theme.toml"), "{output}");
+    let output = origin.dm(&["--restore", &theme]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(
+        origin.read(".config/alacritty/themes/light.toml"),
+        "light\n"
+    );
+    assert!(std::fs::symlink_metadata(&theme).unwrap().is_symlink());
+    let output = text(&origin.dm(&["--add", &state, "--follow"]));
+    assert!(output.contains("is not a symlink to a file"), "{output}");
+
+    std::fs::remove_dir(&state).unwrap();
+    let output = origin.dm(&["--restore", &state]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert!(std::path::Path::new(&state).is_dir());
+
+    let machine = Sandbox::new();
+    let output = machine.dm(&["--clone", &origin.remote_url()]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert!(machine.home.join(".local/state/foo").is_dir());
+    assert!(machine
+        .tracking_list()
+        .contains(&machine.path(".local/state/foo")));
+    assert_eq!(
+        std::fs::read_link(machine.home.join(".dangling")).unwrap(),
+        std::path::Path::new("nowhere")
+    );
+}
+
+#[test]
+fn restore_brings_back_deleted_entries() {
+    let sb = Sandbox::initialized();
+    sb.write(".config/nvim/init.lua", "a\n");
+    sb.write(".config/nvim/lua/plugins.lua", "b\n");
+    let zshrc = sb.write(".zshrc", "z\n");
+    let nvim = sb.path(".config/nvim");
+    sb.dm(&["--add", &nvim]);
+    sb.dm(&["--add", &zshrc]);
+    sb.git(&["commit", "-qm", "dotfiles"]);
+
+    std::fs::remove_dir_all(&nvim).unwrap();
+    std::fs::remove_file(&zshrc).unwrap();
+    // Status drops vanished entries from the list; restore has to track them again.
+    sb.dm(&["--status"]);
+    assert!(sb.tracking_list().is_empty());
+
+    let output = sb.dm(&["--restore", &nvim]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert!(text(&output).contains("2 file(s)"));
+    sb.dm(&["--restore", &zshrc]);
+    assert_eq!(sb.read(".config/nvim/lua/plugins.lua"), "b\n");
+    assert_eq!(sb.read(".zshrc"), "z\n");
+    assert_eq!(sb.tracking_list(), vec![nvim, zshrc]);
+    assert!(sb.git(&["status", "--porcelain"]).is_empty());
+}
+
+#[test]
+fn git_passthrough_env_and_exec() {
+    let sb = Sandbox::initialized();
+    let output = sb.dm(&["--git", "log", "--format=%s %an"]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(text(&output).trim(), "Initial commit Dm Test");
+    // Exit codes and arguments that look like dm's own options pass through.
+    let output = sb.dm(&["--git", "cat-file", "-e", "HEAD:nope", "--home"]);
+    assert!(!output.status.success());
+    assert_ne!(output.status.code(), Some(0));
+
+    let git_dir = sb.git_dir().to_string_lossy().to_string();
+    let home = sb.home.to_string_lossy().to_string();
+    let output = text(&sb.dm(&["--env"]));
+    assert!(output.contains(&format!("export GIT_DIR='{git_dir}'")));
+    assert!(output.contains(&format!("export GIT_WORK_TREE='{home}'")));
+
+    let output = sb.dm(&[
+        "--exec",
+        "sh",
+        "-c",
+        "echo \"$GIT_DIR|$GIT_WORK_TREE\"; git log --format=%s",
+    ]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(text(&output), format!("{git_dir}|{home}\nInitial commit\n"));
+    assert_eq!(
+        sb.dm(&["--exec", "sh", "-c", "exit 3"]).status.code(),
+        Some(3)
+    );
+}
+
+#[test]
+fn which_and_info_explain_coverage() {
+    let sb = Sandbox::initialized();
+    let init = sb.write(".config/nvim/init.lua", "vim.o.number = true\n");
+    sb.write(".config/nvim/.gitignore", "*.log\n");
+    let log = sb.write(".config/nvim/lsp.log", "noise\n");
+    let other = sb.write(".config/kitty/kitty.conf", "font_size 12\n");
+    sb.dm(&["--add", &sb.path(".config/nvim")]);
+    sb.repo().commit("track nvim").unwrap();
+
+    let output = sb.dm(&["--which", &init]);
+    assert!(output.status.success());
+    assert!(strip_ansi(&text(&output))
+        .contains("Tracked:\t/.config/nvim/init.lua by the entry /.config/nvim"));
+    let output = sb.dm(&["--which", &log]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(text(&output).contains("ignored by .config/nvim/.gitignore:1:*.log"));
+    assert!(text(&sb.dm(&["--which", &other])).contains("is not covered by any entry"));
+    assert!(text(&sb.dm(&["--which", "/etc/hostname"])).contains("is outside the work-tree"));
+    let list = sb.data.join("list").to_string_lossy().to_string();
+    assert!(text(&sb.dm(&["--which", &list])).contains("data directory"));
+
+    std::fs::write(&init, "vim.o.number = false\n").unwrap();
+    let output = strip_ansi(&text(&sb.dm(&["--info", &init])));
+    assert!(output.contains("Entry:\t\t/.config/nvim"));
+    assert!(output.contains("State:\t\tmodified"));
+    assert!(output.contains("Type:\t\tfile"));
+    assert!(output.contains("Size:\t\t21 B"));
+    assert!(output.contains("Mode:\t\t0644 (git 100644)"));
+    assert!(output.contains("track nvim"));
+    assert!(!output.contains("none recorded"));
+    assert!(output.contains("Variants:\tone for every host"));
+    assert!(output.contains("Encrypted:\tno"));
+    assert!(output.contains("Templated:\tno"));
+
+    let mut list = sb.repo().tracking_list().unwrap();
+    list.entries[0].encrypt = true;
+    list.entries[0].template = true;
+    list.entries[0].hosts = vec!["laptop".into(), "desktop".into()];
+    sb.repo().save_list(&list).unwrap();
+    let output = strip_ansi(&text(&sb.dm(&["--info", &init])));
+    assert!(output.contains("Variants:\tlaptop, desktop"), "{output}");
+    assert!(output.contains("Encrypted:\tyes"));
+    assert!(output.contains("Templated:\tyes"));
+
+    let output = strip_ansi(&text(&sb.dm(&["--info", &other])));
+    assert!(output.contains("State:\t\tuntracked"));
+    assert!(output.contains("Last commit:\tnone"));
+}
+
+#[test]
+fn doctor_finds_and_fixes_drift() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "a\n");
+    let ssh = sb.write(".ssh/config", "Host *\n");
+    std::fs::set_permissions(&ssh, std::fs::Permissions::from_mode(0o644)).unwrap();
+    sb.dm(&["--add", &zshrc]);
+    sb.dm(&["--add", &sb.path(".ssh")]);
+    let output = text(&sb.dm(&["--doctor"]));
+    assert!(output.contains("'/.ssh/config' looks secret but is readable by others (0644)"));
+
+    // Drift: hand-edited entries, an orphaned file in the index and stale git config.
+    let tmux = sb.write(".tmux.conf", "set -g mouse on\n");
+    let bashrc = sb.write(".bashrc", "alias l=ls\n");
+    let list = sb.data.join("list");
+    let mut edited = std::fs::read_to_string(&list).unwrap();
+    for path in [zshrc.clone(), sb.path(".vimrc"), tmux.clone()] {
+        edited += &format!("\n[[entry]]\npath = \"{path}\"\n");
+    }
+    std::fs::write(&list, edited).unwrap();
+    sb.git(&["add", &bashrc]);
+    sb.git(&["config", "--unset", "status.showUntrackedFiles"]);
+    sb.git(&["branch", "--unset-upstream"]);
+
+    let output = sb.dm(&["--doctor"]);
+    assert_eq!(output.status.code(), Some(1));
+    let output = text(&output);
+    for message in [
+        "git status lists every untracked file in HOME",
+        "the branch 'main' has no upstream",
+        "'/.zshrc' is listed twice",
+        "'/.vimrc' no longer exists in HOME",
+        "'/.tmux.conf' is listed but git does not track it",
+        "1 file(s) in the repository are not covered by any entry: /.bashrc",
+    ] {
+        assert!(output.contains(message), "missing '{message}' in {output}");
+    }
+
+    assert!(text(&sb.dm(&["--doctor", "--fix"])).contains("fixed:"));
+    let output = text(&sb.dm(&["--doctor"]));
+    for check in ["config:", "remote:", "list:", "index:", "permissions:"] {
+        assert!(!output.contains(check), "{output}");
+    }
+    assert_eq!(sb.tracking_list(), vec![zshrc, sb.path(".ssh"), tmux]);
+    let staged = sb.git(&["ls-files"]);
+    assert!(staged.contains(".tmux.conf"));
+    assert!(!staged.contains(".bashrc"));
+    assert!(std::path::Path::new(&bashrc).exists());
+    let mode = std::fs::metadata(&ssh).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
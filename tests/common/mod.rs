@@ -0,0 +1,330 @@
+#![allow(dead_code)]
+
+use dotmanager::{DotRepo, TrackingList};
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+use std::{
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::{mpsc, Arc, Mutex, Once},
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+pub const DM: &str = env!("CARGO_BIN_EXE_dm");
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+pub const KEY_ENTER: &str = "\r";
+pub const KEY_TAB: &str = "\t";
+pub const KEY_DOWN: &str = "\x1b[B";
+pub const KEY_UP: &str = "\x1b[A";
+
+/// A throw-away HOME, dotmanager data dir and bare `origin` remote.
+pub struct Sandbox {
+    _root: TempDir,
+    pub home: PathBuf,
+    pub data: PathBuf,
+    pub remote: PathBuf,
+    gitconfig: PathBuf,
+}
+
+impl Sandbox {
+    pub fn new() -> Sandbox {
+        let root = tempfile::Builder::new()
+            .prefix("dm-test-")
+            .tempdir()
+            .expect("could not create temporary directory");
+        let home = root.path().join("home");
+        let data = root.path().join("data");
+        let remote = root.path().join("remote.git");
+        let gitconfig = root.path().join("gitconfig");
+        fs::create_dir_all(&home).unwrap();
+        fs::write(
+            &gitconfig,
+            "[user]\n\tname = Dm Test\n\temail = dm@example.com\n[init]\n\tdefaultBranch = main\n",
+        )
+        .unwrap();
+
+        let sandbox = Sandbox {
+            _root: root,
+            home,
+            data,
+            remote,
+            gitconfig,
+        };
+        let output = sandbox
+            .raw_git()
+            .args(["init", "--quiet", "--bare"])
+            .arg(&sandbox.remote)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "could not create remote");
+        sandbox
+    }
+
+    /// A sandbox on which `dm --init` has already run.
+    pub fn initialized() -> Sandbox {
+        let sandbox = Sandbox::new();
+        let output = sandbox.dm(&["--init", sandbox.remote_url().as_str()]);
+        assert!(
+            output.status.success(),
+            "dm --init failed: {}",
+            text(&output)
+        );
+        sandbox
+    }
+
+    pub fn remote_url(&self) -> String {
+        self.remote.to_string_lossy().to_string()
+    }
+
+    pub fn git_dir(&self) -> PathBuf {
+        self.data.join("git")
+    }
+
+    pub fn list_file(&self) -> PathBuf {
+        self.data.join("list")
+    }
+
+    /// Absolute path of `rel` inside the sandboxed HOME, as dm expects it on the command line.
+    pub fn path(&self, rel: &str) -> String {
+        self.home.join(rel).to_string_lossy().to_string()
+    }
+
+    pub fn write(&self, rel: &str, contents: &str) -> String {
+        let path = self.home.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    pub fn read(&self, rel: &str) -> String {
+        fs::read_to_string(self.home.join(rel)).unwrap()
+    }
+
+    /// The paths in the tracking list.
+    pub fn tracking_list(&self) -> Vec<String> {
+        TrackingList::load(&self.list_file().to_string_lossy())
+            .expect("unreadable tracking list")
+            .paths()
+    }
+
+    fn apply_env(&self, cmd: &mut Command) {
+        for (k, v) in self.env() {
+            cmd.env(k, v);
+        }
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("DM_HOME", self.home.to_string_lossy().to_string()),
+            ("DM_DATA_DIR", self.data.to_string_lossy().to_string()),
+            ("HOME", self.home.to_string_lossy().to_string()),
+            (
+                "GIT_CONFIG_GLOBAL",
+                self.gitconfig.to_string_lossy().to_string(),
+            ),
+            ("GIT_CONFIG_NOSYSTEM", "1".to_string()),
+            ("LESS", "".to_string()),
+            ("NO_COLOR", "1".to_string()),
+        ]
+    }
+
+    fn raw_git(&self) -> Command {
+        let mut cmd = Command::new("git");
+        self.apply_env(&mut cmd);
+        cmd
+    }
+
+    /// The sandboxed repository, for driving the library directly.
+    pub fn repo(&self) -> DotRepo {
+        static IDENTITY: Once = Once::new();
+        IDENTITY.call_once(|| {
+            std::env::set_var("GIT_AUTHOR_NAME", "Dm Test");
+            std::env::set_var("GIT_AUTHOR_EMAIL", "dm@example.com");
+            std::env::set_var("GIT_COMMITTER_NAME", "Dm Test");
+            std::env::set_var("GIT_COMMITTER_EMAIL", "dm@example.com");
+        });
+        DotRepo::with_paths(self.home.to_str().unwrap(), self.data.to_str().unwrap())
+    }
+
+    /// Runs dm non-interactively, with stdin closed.
+    pub fn dm(&self, args: &[&str]) -> Output {
+        self.dm_in(&self.home, args)
+    }
+
+    /// Runs dm non-interactively from the directory `cwd`.
+    pub fn dm_in(&self, cwd: &Path, args: &[&str]) -> Output {
+        let mut cmd = Command::new(DM);
+        self.apply_env(&mut cmd);
+        cmd.args(args)
+            .current_dir(cwd)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .expect("could not run dm")
+    }
+
+    /// Runs git against the dotfile repository, the way dm does.
+    pub fn git(&self, args: &[&str]) -> String {
+        let output = self
+            .raw_git()
+            .arg(format!("--git-dir={}", self.git_dir().display()))
+            .arg(format!("--work-tree={}", self.home.display()))
+            .args(args)
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stdout).to_string()
+    }
+
+    /// Runs git from `dir` without pointing it at the repository, as a user would.
+    pub fn git_in(&self, dir: &Path, args: &[&str]) -> Output {
+        self.raw_git().args(args).current_dir(dir).output().unwrap()
+    }
+
+    /// Runs git against the bare remote.
+    pub fn remote_git(&self, args: &[&str]) -> String {
+        let output = self
+            .raw_git()
+            .arg(format!("--git-dir={}", self.remote.display()))
+            .args(args)
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stdout).to_string()
+    }
+
+    /// Runs dm inside a pseudo terminal and plays `script` against it: each step waits until
+    /// the output produced so far contains the expected text, then types the given keys.
+    pub fn dm_scripted(&self, args: &[&str], script: &[(&str, &str)]) -> Session {
+        let pty = native_pty_system()
+            .openpty(PtySize {
+                rows: 40,
+                cols: 160,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .expect("could not open pty");
+        let mut cmd = CommandBuilder::new(DM);
+        cmd.args(args);
+        cmd.cwd(&self.home);
+        for (k, v) in self.env() {
+            cmd.env(k, v);
+        }
+        cmd.env("TERM", "xterm-256color");
+        let mut child = pty.slave.spawn_command(cmd).expect("could not spawn dm");
+        drop(pty.slave);
+
+        let screen = Arc::new(Mutex::new(String::new()));
+        let mut reader = pty.master.try_clone_reader().unwrap();
+        let sink = Arc::clone(&screen);
+        let (done_tx, done_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                sink.lock()
+                    .unwrap()
+                    .push_str(&String::from_utf8_lossy(&buf[..n]));
+            }
+            let _ = done_tx.send(());
+        });
+
+        let mut writer = pty.master.take_writer().unwrap();
+        let mut seen = 0;
+        for (expect, keys) in script {
+            let start = Instant::now();
+            loop {
+                let found = {
+                    let out = screen.lock().unwrap();
+                    out[seen..].find(expect).map(|i| seen + i + expect.len())
+                };
+                if let Some(end) = found {
+                    seen = end;
+                    break;
+                }
+                if start.elapsed() > TIMEOUT {
+                    let _ = child.kill();
+                    panic!(
+                        "timed out waiting for {expect:?}, output so far:\n{}",
+                        screen.lock().unwrap()
+                    );
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+            // Give the program a moment to switch into the mode that reads the next keys.
+            thread::sleep(Duration::from_millis(150));
+            writer.write_all(keys.as_bytes()).unwrap();
+            writer.flush().unwrap();
+        }
+
+        let start = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait().unwrap() {
+                break status;
+            }
+            if start.elapsed() > TIMEOUT {
+                let _ = child.kill();
+                panic!(
+                    "dm did not exit, output so far:\n{}",
+                    screen.lock().unwrap()
+                );
+            }
+            thread::sleep(Duration::from_millis(20));
+        };
+        drop(writer);
+        drop(pty.master);
+        let _ = done_rx.recv_timeout(Duration::from_secs(2));
+
+        let output = screen.lock().unwrap().clone();
+        Session {
+            success: status.success(),
+            output,
+        }
+    }
+}
+
+pub struct Session {
+    pub success: bool,
+    pub output: String,
+}
+
+/// Stdout and stderr of a finished dm invocation, with ANSI escapes removed.
+pub fn text(output: &Output) -> String {
+    let mut s = String::from_utf8_lossy(&output.stdout).to_string();
+    s.push_str(&String::from_utf8_lossy(&output.stderr));
+    strip_ansi(&s)
+}
+
+pub fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('[') => {
+                chars.next();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            Some(_) => {
+                chars.next();
+            }
+            None => {}
+        }
+    }
+    out
+}
+
+pub fn exists(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
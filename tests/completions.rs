@@ -0,0 +1,104 @@
+mod common;
+
+use common::{text, Sandbox};
+use std::process::Command;
+
+#[test]
+fn completion_scripts_cover_commands_and_options() {
+    let sb = Sandbox::new();
+    for shell in ["bash", "zsh", "fish"] {
+        let output = sb.dm(&["--completions", shell]);
+        assert!(output.status.success(), "{}", text(&output));
+        let script = text(&output);
+        for word in [
+            "--add",
+            "-r",
+            "--snapshot",
+            "--side-by-side",
+            "--between",
+            "--force",
+        ] {
+            assert!(script.contains(word), "{shell} script lacks {word}");
+        }
+        assert!(script.contains("--complete entries"));
+        assert!(script.contains("--complete changed"));
+        assert!(!script.contains("--complete\n"));
+    }
+    let script = sb.path("dm.bash");
+    std::fs::write(&script, text(&sb.dm(&["--completions", "bash"]))).unwrap();
+    let syntax = Command::new("bash").args(["-n", &script]).output().unwrap();
+    assert!(syntax.status.success(), "{}", text(&syntax));
+
+    // Paths to add are taken from HOME wherever dm is run, and inserted whole.
+    sb.write(".bashrc", "a\n");
+    sb.write(".bash_profile", "b\n");
+    let complete = |word: &str| {
+        let output = Command::new("bash")
+            .args([
+                "-c",
+                "source \"$0\"; COMP_WORDS=(dm --add \"$1\"); COMP_CWORD=2; _dm; \
+                 printf '%s\\n' \"${COMPREPLY[@]}\" | sort",
+                &script,
+                word,
+            ])
+            .env("HOME", &sb.home)
+            .current_dir(sb.home.parent().unwrap())
+            .output()
+            .unwrap();
+        text(&output)
+    };
+    assert_eq!(
+        complete(".bash"),
+        format!("{}\n{}\n", sb.path(".bash_profile"), sb.path(".bashrc"))
+    );
+    assert_eq!(complete("~/.bashr"), "~/.bashrc\n");
+    assert!(text(&sb.dm(&["--completions", "zsh"])).contains("PREFIX=\"~/$PREFIX\""));
+    assert!(text(&sb.dm(&["--completions", "fish"])).contains("-a '(__dm_home_files)'"));
+
+    let output = sb.dm(&["--completions", "tcsh"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(text(&output).contains("unsupported shell 'tcsh'"));
+}
+
+#[test]
+fn complete_lists_tracked_entries_and_changed_files() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "a\n");
+    sb.write(".config/nvim/init.lua", "set number\n");
+    sb.dm(&["--add", &zshrc]);
+    sb.dm(&["--add", &sb.path(".config/nvim")]);
+
+    let output = text(&sb.dm(&["--complete", "entries"]));
+    assert_eq!(output, "~/.zshrc\n~/.config/nvim\n");
+    let output = text(&sb.dm(&["--complete", "changed"]));
+    assert!(output.contains("~/.zshrc\n"));
+    assert!(output.contains("~/.config/nvim/init.lua\n"));
+
+    // System files are offered under their real paths, as status shows them.
+    let system = sb.home.parent().unwrap().join("etc/pacman.conf");
+    std::fs::create_dir_all(system.parent().unwrap()).unwrap();
+    std::fs::write(&system, "[options]\n").unwrap();
+    let system = system.to_string_lossy().to_string();
+    sb.dm(&["--add", &system]);
+    let output = text(&sb.dm(&["--complete", "changed"]));
+    assert!(output.contains(&format!("{system}\n")), "{output}");
+    assert!(!output.contains(".dm/system"), "{output}");
+
+    sb.dm(&["--snapshot", "create", "before-theme"]);
+    let output = text(&sb.dm(&["--complete", "snapshots"]));
+    assert!(output.starts_with("create\nlist\n"));
+    assert!(output.contains("before-theme\n"));
+}
+
+#[test]
+fn man_page_covers_the_help() {
+    let sb = Sandbox::new();
+    let output = sb.dm(&["--man"]);
+    assert!(output.status.success());
+    let page = text(&output);
+    assert!(page.starts_with(".TH DM 1\n"));
+    assert!(page.contains(".SH SYNOPSIS\n"));
+    assert!(page.contains(".TP\n\\fB\\-a\\fR, \\fB\\-\\-add\\fR (<path>)\nAdds a file or folder"));
+    assert!(page.contains("\\fB\\-\\-completions\\fR <shell>"));
+    assert!(!page.contains("<cyan"));
+}
@@ -0,0 +1,388 @@
+mod common;
+
+use common::Sandbox;
+use dotmanager::diff::{parse, FileStatus, LineKind};
+use dotmanager::render::word_diff;
+use dotmanager::{Layout, Renderer};
+
+const MODIFIED: &str = "\
+diff --git a/.zshrc b/.zshrc
+index 1111111..2222222 100644
+--- a/.zshrc
++++ b/.zshrc
+@@ -1,3 +1,3 @@ # shell
+ export PATH=$PATH:~/bin
+-export EDITOR=vim
++export EDITOR=nvim
+ alias ls='ls --color'
+@@ -10 +10,2 @@
+--- a/literal line
++++ b/literal line
++appended
+\\ No newline at end of file
+";
+
+#[test]
+fn parses_hunks_with_line_numbers() {
+    let files = parse(MODIFIED);
+    assert_eq!(files.len(), 1);
+    let file = &files[0];
+    assert_eq!(file.status, FileStatus::Modified);
+    assert_eq!(file.path(), ".zshrc");
+    assert!(!file.mode_changed());
+    assert_eq!(file.hunks.len(), 2);
+
+    let hunk = &file.hunks[0];
+    assert_eq!(
+        (hunk.old_start, hunk.old_len, hunk.new_start, hunk.new_len),
+        (1, 3, 1, 3)
+    );
+    assert_eq!(hunk.section, "# shell");
+    let numbers: Vec<_> = hunk
+        .lines
+        .iter()
+        .map(|l| (l.kind, l.old_no, l.new_no))
+        .collect();
+    assert_eq!(
+        numbers,
+        vec![
+            (LineKind::Context, Some(1), Some(1)),
+            (LineKind::Removed, Some(2), None),
+            (LineKind::Added, None, Some(2)),
+            (LineKind::Context, Some(3), Some(3)),
+        ]
+    );
+
+    // Content lines that look like file headers stay inside the hunk.
+    let hunk = &file.hunks[1];
+    assert_eq!(hunk.section, "");
+    assert_eq!(hunk.lines.len(), 3);
+    assert_eq!(hunk.lines[0].content, "-- a/literal line");
+    assert_eq!(hunk.lines[1].content, "++ b/literal line");
+    assert_eq!(hunk.lines[1].new_no, Some(10));
+    assert!(hunk.lines[2].no_newline);
+    assert_eq!(file.stats(), (3, 2));
+}
+
+#[test]
+fn missing_newline_on_the_old_side_keeps_the_rest_of_the_hunk() {
+    let text = "\
+diff --git a/.vimrc b/.vimrc
+index 1111111..2222222 100644
+--- a/.vimrc
++++ b/.vimrc
+@@ -1,2 +1,2 @@
+ set number
+-foo
+\\ No newline at end of file
++bar
+\\ No newline at end of file
+";
+    let files = parse(text);
+    let hunk = &files[0].hunks[0];
+    let lines: Vec<_> = hunk
+        .lines
+        .iter()
+        .map(|l| (l.kind, l.content.as_str(), l.no_newline))
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            (LineKind::Context, "set number", false),
+            (LineKind::Removed, "foo", true),
+            (LineKind::Added, "bar", true),
+        ]
+    );
+    assert_eq!(
+        files[0].patch(&[0]),
+        text.replacen("index 1111111..2222222 100644\n", "", 1)
+    );
+
+    let sb = Sandbox::initialized();
+    sb.write(".vimrc", "set number\nfoo");
+    sb.dm(&["--add", &sb.path(".vimrc")]);
+    sb.git(&["commit", "-qm", "vimrc"]);
+    sb.write(".vimrc", "set number\nbar");
+    let output = common::text(&sb.dm(&["--diff", "~/.vimrc"]));
+    assert!(output.contains("-foo"), "{output}");
+    assert!(output.contains("+bar"), "{output}");
+}
+
+#[test]
+fn parses_binary_rename_and_mode_changes() {
+    let text = "\
+diff --git a/bin/tool b/bin/tool
+old mode 100644
+new mode 100755
+diff --git a/.config/old name.conf b/.config/new name.conf
+similarity index 90%
+rename from .config/old name.conf
+rename to .config/new name.conf
+index 1111111..2222222 100644
+--- a/.config/old name.conf
++++ b/.config/new name.conf
+@@ -1 +1 @@
+-a
++b
+diff --git a/wall.png b/wall.png
+new file mode 100644
+index 0000000..3333333
+Binary files /dev/null and b/wall.png differ
+diff --git a/.vimrc b/.vimrc
+deleted file mode 100644
+index 4444444..0000000
+--- a/.vimrc
++++ /dev/null
+@@ -1 +0,0 @@
+-set number
+";
+    let files = parse(text);
+    assert_eq!(files.len(), 4);
+
+    assert!(files[0].mode_changed());
+    assert_eq!(files[0].old_mode.as_deref(), Some("100644"));
+    assert_eq!(files[0].new_mode.as_deref(), Some("100755"));
+    assert!(files[0].hunks.is_empty());
+    assert!(!files[0].is_empty());
+
+    assert_eq!(files[1].status, FileStatus::Renamed);
+    assert_eq!(files[1].old_path.as_deref(), Some(".config/old name.conf"));
+    assert_eq!(files[1].new_path.as_deref(), Some(".config/new name.conf"));
+    assert_eq!(files[1].similarity, Some(90));
+
+    assert_eq!(files[2].status, FileStatus::Added);
+    assert!(files[2].binary);
+    assert_eq!(files[2].old_path, None);
+    assert_eq!(files[2].path(), "wall.png");
+
+    assert_eq!(files[3].status, FileStatus::Deleted);
+    assert_eq!(files[3].new_path, None);
+    assert_eq!(files[3].path(), ".vimrc");
+    assert_eq!(files[3].hunks[0].lines[0].old_no, Some(1));
+}
+
+#[test]
+fn renders_plain_unified_output() {
+    let files = parse(MODIFIED);
+    let output = Renderer::plain().render(&files);
+    assert!(!output.contains('\u{1b}'));
+    assert!(output.starts_with("diff --git a/.zshrc b/.zshrc\n@@ -1,3 +1,3 @@ # shell\n"));
+    assert!(output.contains("\n-export EDITOR=vim\n+export EDITOR=nvim\n"));
+    assert!(output.contains("+appended\n\\ No newline at end of file\n"));
+}
+
+#[test]
+fn renders_word_level_changes() {
+    let (old, new) = word_diff("export EDITOR=vim", "export EDITOR=nvim");
+    assert_eq!(
+        old,
+        vec![
+            ("export EDITOR=".to_string(), false),
+            ("vim".to_string(), true)
+        ]
+    );
+    assert_eq!(
+        new,
+        vec![
+            ("export EDITOR=".to_string(), false),
+            ("nvim".to_string(), true)
+        ]
+    );
+
+    let renderer = Renderer {
+        word_diff: true,
+        ..Renderer::plain()
+    };
+    let output = renderer.render(&parse(MODIFIED));
+    assert!(output.contains("-export EDITOR=[-vim-]\n+export EDITOR={+nvim+}\n"));
+
+    let colored = Renderer {
+        word_diff: true,
+        ..Renderer::default()
+    }
+    .render(&parse(MODIFIED));
+    assert!(colored.contains("\u{1b}[7mnvim"));
+}
+
+#[test]
+fn renders_side_by_side() {
+    let renderer = Renderer {
+        layout: Layout::SideBySide,
+        width: 63,
+        ..Renderer::plain()
+    };
+    let output = renderer.render(&parse(MODIFIED));
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines[2],
+        "1 export PATH=$PATH:~/bin      │ 1 export PATH=$PATH:~/bin"
+    );
+    assert_eq!(
+        lines[3],
+        "2 export EDITOR=vim            │ 2 export EDITOR=nvim"
+    );
+    // Lines without a counterpart leave the other side empty.
+    assert!(lines
+        .iter()
+        .any(|l| l.trim_end().ends_with("│ 11 appended")));
+}
+
+#[test]
+fn side_by_side_truncates_long_lines() {
+    let text = "\
+diff --git a/a b/a
+--- a/a
++++ b/a
+@@ -1 +1 @@
+-aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa
++b
+";
+    let renderer = Renderer {
+        layout: Layout::SideBySide,
+        width: 43,
+        ..Renderer::plain()
+    };
+    let output = renderer.render(&parse(text));
+    let line = output.lines().nth(2).unwrap();
+    assert_eq!(line, "1 aaaaaaaaaaaaaaaaa… │ 1 b");
+}
+
+#[test]
+fn repo_diff_handles_binary_rename_and_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    sb.write(".config/app/old.conf", "one\ntwo\nthree\nfour\n");
+    sb.write(".local/bin/tool", "#!/bin/sh\n");
+    std::fs::write(sb.home.join(".config/app/image.bin"), [0u8, 159, 146, 150]).unwrap();
+    repo.track(&sb.path(".config/app")).unwrap();
+    repo.track(&sb.path(".local/bin")).unwrap();
+    repo.commit("base").unwrap();
+
+    std::fs::rename(
+        sb.home.join(".config/app/old.conf"),
+        sb.home.join(".config/app/new.conf"),
+    )
+    .unwrap();
+    std::fs::write(sb.home.join(".config/app/image.bin"), [1u8, 0, 2, 3]).unwrap();
+    let tool = sb.home.join(".local/bin/tool");
+    std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+    repo.stage_all().unwrap();
+
+    let renamed = repo.diff(&sb.path(".config/app/new.conf")).unwrap();
+    assert_eq!(renamed.status, FileStatus::Renamed);
+    assert_eq!(renamed.old_path.as_deref(), Some(".config/app/old.conf"));
+    let same = repo.diff(&sb.path(".config/app/old.conf")).unwrap();
+    assert_eq!(same, renamed);
+
+    let binary = repo.diff(&sb.path(".config/app/image.bin")).unwrap();
+    assert!(binary.binary);
+    assert!(Renderer::plain()
+        .render_file(&binary)
+        .contains("binary file differs"));
+
+    let mode = repo.diff(&sb.path(".local/bin/tool")).unwrap();
+    assert!(mode.mode_changed());
+    assert!(Renderer::plain()
+        .render_file(&mode)
+        .contains("mode 100644 → 100755"));
+}
+
+/// A repository with two commits of `.zshrc` and `.config/nvim`, and uncommitted edits.
+fn history() -> Sandbox {
+    let sb = Sandbox::initialized();
+    sb.write(".zshrc", "one\n");
+    sb.write(".config/nvim/init.lua", "a\n");
+    sb.dm(&["--add", &sb.path(".zshrc")]);
+    sb.dm(&["--add", &sb.path(".config/nvim")]);
+    sb.git(&["commit", "-qm", "first"]);
+    sb.write(".zshrc", "two\n");
+    sb.write(".config/nvim/init.lua", "b\n");
+    sb.git(&["commit", "-qam", "second"]);
+    sb.git(&["push", "-q"]);
+    sb.write(".zshrc", "three\n");
+    sb
+}
+
+#[test]
+fn diff_of_a_file_prints_plain_output_without_a_terminal() {
+    let sb = history();
+    let output = common::text(&sb.dm(&["--diff", "~/.zshrc"]));
+    assert!(output.starts_with("diff --git a/.zshrc b/.zshrc\n"));
+    assert!(output.contains("-two\n+three\n"));
+    assert!(!output.contains("init.lua"));
+
+    let output = common::text(&sb.dm_in(&sb.home.join(".config"), &["--diff", "../.zshrc"]));
+    assert!(output.contains("+three"));
+}
+
+#[test]
+fn diff_accepts_several_paths_and_tracking_entries() {
+    let sb = history();
+    sb.write(".config/nvim/init.lua", "c\n");
+    let output = common::text(&sb.dm_in(
+        &sb.home.join(".config"),
+        &["--diff", "nvim", &sb.path(".zshrc")],
+    ));
+    assert!(output.contains("diff --git a/.config/nvim/init.lua"));
+    assert!(output.contains("diff --git a/.zshrc"));
+}
+
+#[test]
+fn diff_against_revisions_and_upstream() {
+    let sb = history();
+    let output = common::text(&sb.dm(&["--diff", "--rev", "HEAD~1", "~/.zshrc"]));
+    assert!(output.contains("-one\n+three\n"));
+
+    let output = common::text(&sb.dm(&["--diff", "--between", "HEAD~1", "HEAD"]));
+    assert!(output.contains("-one\n+two\n"));
+    assert!(output.contains("-a\n+b\n"));
+
+    let output = common::text(&sb.dm(&["--diff", "--remote"]));
+    assert!(output.contains("-two\n+three\n"));
+    assert!(!output.contains("init.lua"));
+
+    let output = sb.dm(&["--diff", "--rev", "nope", "~/.zshrc"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(common::text(&output).contains("unknown revision 'nope'"));
+
+    let output = sb.dm(&["--diff", "--rev", "HEAD", "--remote"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn diff_ignores_the_users_prefix_settings() {
+    let sb = history();
+    sb.git(&["config", "--global", "diff.mnemonicPrefix", "true"]);
+    let output = common::text(&sb.dm(&["--diff", "~/.zshrc"]));
+    assert!(
+        output.starts_with("diff --git a/.zshrc b/.zshrc\n"),
+        "{output}"
+    );
+    let output = common::text(&sb.dm(&["--diff", "--between", "HEAD~1", "HEAD"]));
+    assert!(
+        output.contains("diff --git a/.zshrc b/.zshrc\n"),
+        "{output}"
+    );
+
+    // The library reads the repository's own configuration as well.
+    sb.git(&["config", "diff.mnemonicPrefix", "true"]);
+    sb.git(&["config", "diff.noprefix", "true"]);
+    let repo = sb.repo();
+    repo.stage_all().unwrap();
+    let file = repo.diff(&sb.path(".zshrc")).unwrap();
+    assert_eq!(file.path(), ".zshrc");
+    let head = sb.git(&["rev-parse", "HEAD"]);
+    let files = repo.commit_diff(head.trim(), &[]).unwrap();
+    let paths: Vec<&str> = files.iter().map(|f| f.path()).collect();
+    assert_eq!(paths, vec![".config/nvim/init.lua", ".zshrc"]);
+}
+
+#[test]
+fn diff_without_changes_warns() {
+    let sb = history();
+    let output = sb.dm(&["--diff", "~/.config/nvim/init.lua"]);
+    assert!(output.status.success());
+    assert!(common::text(&output).contains("did not find any changes"));
+}
@@ -0,0 +1,191 @@
+mod common;
+
+use common::{strip_ansi, text, Sandbox};
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+
+/// A sandbox on which `dm --init --farm` has already run.
+fn farm() -> Sandbox {
+    let sb = Sandbox::new();
+    let output = sb.dm(&["--init", &sb.remote_url(), "--farm"]);
+    assert!(output.status.success(), "{}", text(&output));
+    sb
+}
+
+fn link_target(sb: &Sandbox, rel: &str) -> String {
+    fs::read_link(sb.home.join(rel))
+        .unwrap_or_else(|_| panic!("~/{rel} is not a symlink"))
+        .to_string_lossy()
+        .to_string()
+}
+
+#[test]
+fn added_files_move_into_the_checkout_and_are_linked_back() {
+    let sb = farm();
+    let checkout = sb.home.join("dotfiles");
+    assert!(checkout.join(".git").is_file());
+    assert!(checkout.join(".github/README.md").is_file());
+    assert!(!sb.home.join(".github").exists());
+
+    sb.write(".bashrc", "alias ll='ls -l'\n");
+    sb.write(".config/nvim/init.lua", "vim.o.number = true\n");
+    for rel in [".bashrc", ".config/nvim"] {
+        let output = sb.dm(&["--add", &sb.path(rel)]);
+        assert!(output.status.success(), "{}", text(&output));
+        assert_eq!(
+            link_target(&sb, rel),
+            checkout.join(rel).to_string_lossy(),
+            "~/{rel} should link into the checkout"
+        );
+    }
+    assert_eq!(
+        sb.tracking_list(),
+        vec![sb.path(".bashrc"), sb.path(".config/nvim")]
+    );
+    assert_eq!(
+        fs::read_to_string(checkout.join(".bashrc")).unwrap(),
+        "alias ll='ls -l'\n"
+    );
+    assert_eq!(sb.read(".config/nvim/init.lua"), "vim.o.number = true\n");
+
+    let output = strip_ansi(&text(&sb.dm(&["--status"])));
+    assert!(
+        output.contains(&checkout.to_string_lossy().to_string()),
+        "{output}"
+    );
+    assert!(output.contains("/.config/nvim/init.lua"), "{output}");
+
+    // The checkout is a normal one: git works inside it without any options.
+    let output = sb.git_in(&checkout, &["commit", "-qm", "dotfiles"]);
+    assert!(output.status.success(), "{}", text(&output));
+    let output = sb.git_in(&checkout, &["status", "--porcelain"]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(text(&output).trim(), "");
+
+    // Edits through the links are edits of the checkout.
+    sb.write(".bashrc", "alias ll='ls -la'\n");
+    let output = strip_ansi(&text(&sb.dm(&["--status"])));
+    assert!(output.contains("/.bashrc"), "{output}");
+    assert!(
+        text(&sb.git_in(&checkout, &["diff", "--cached"])).contains("+alias ll='ls -la'"),
+        "the edit should be staged"
+    );
+
+    // Removing an entry moves its files back into HOME in place of the link.
+    let output = sb.dm(&["--remove", &sb.path(".config/nvim")]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert!(!fs::symlink_metadata(sb.home.join(".config/nvim"))
+        .unwrap()
+        .is_symlink());
+    assert_eq!(sb.read(".config/nvim/init.lua"), "vim.o.number = true\n");
+    assert!(!checkout.join(".config/nvim").exists());
+    assert!(!sb.tracking_list().contains(&sb.path(".config/nvim")));
+    let output = sb.dm(&["--undo"]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(
+        link_target(&sb, ".config/nvim"),
+        checkout.join(".config/nvim").to_string_lossy()
+    );
+    assert_eq!(sb.read(".config/nvim/init.lua"), "vim.o.number = true\n");
+    let output = sb.dm(&["--remove", &sb.path(".config/nvim")]);
+    assert!(output.status.success(), "{}", text(&output));
+    let output = sb.dm(&["--remove", &sb.path(".bashrc")]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(sb.read(".bashrc"), "alias ll='ls -la'\n");
+    assert!(!checkout.join(".bashrc").exists());
+}
+
+#[test]
+fn doctor_finds_broken_links_and_fixes_what_it_safely_can() {
+    let sb = farm();
+    sb.write(".vimrc", "set number\n");
+    sb.write(".gitconfig", "[user]\n");
+    for rel in [".vimrc", ".gitconfig"] {
+        let output = sb.dm(&["--add", &sb.path(rel)]);
+        assert!(output.status.success(), "{}", text(&output));
+    }
+    let output = sb.dm(&["--doctor"]);
+    assert!(output.status.success(), "{}", text(&output));
+
+    fs::remove_file(sb.home.join(".vimrc")).unwrap();
+    fs::remove_file(sb.home.join(".gitconfig")).unwrap();
+    fs::write(sb.home.join(".gitconfig"), "[core]\n").unwrap();
+    let output = sb.dm(&["--doctor"]);
+    assert!(!output.status.success());
+    let output = strip_ansi(&text(&output));
+    assert!(output.contains("'/.vimrc' is not linked"), "{output}");
+    assert!(output.contains("'/.gitconfig' is a real file"), "{output}");
+
+    // The missing link comes back, the real file is never overwritten.
+    let output = strip_ansi(&text(&sb.dm(&["--doctor", "--fix"])));
+    assert!(
+        output.contains("fixed: link it into the checkout"),
+        "{output}"
+    );
+    assert_eq!(sb.read(".vimrc"), "set number\n");
+    assert!(fs::symlink_metadata(sb.home.join(".vimrc"))
+        .unwrap()
+        .is_symlink());
+    assert_eq!(sb.read(".gitconfig"), "[core]\n");
+
+    fs::remove_file(sb.home.join(".gitconfig")).unwrap();
+    symlink("/nonexistent", sb.home.join(".gitconfig")).unwrap();
+    let output = strip_ansi(&text(&sb.dm(&["--doctor"])));
+    assert!(output.contains("links to '/nonexistent'"), "{output}");
+    let output = sb.dm(&["--doctor", "--fix"]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(sb.read(".gitconfig"), "[user]\n");
+}
+
+#[test]
+fn clone_links_the_checkout_into_home_and_reports_conflicts() {
+    let first = farm();
+    first.write(".zshrc", "export EDITOR=vim\n");
+    first.write(".config/kitty/kitty.conf", "font_size 11\n");
+    for rel in [".zshrc", ".config/kitty"] {
+        let output = first.dm(&["--add", &first.path(rel)]);
+        assert!(output.status.success(), "{}", text(&output));
+    }
+    let checkout = first.home.join("dotfiles");
+    for args in [&["commit", "-qm", "dotfiles"][..], &["push", "-q"]] {
+        let output = first.git_in(&checkout, args);
+        assert!(output.status.success(), "{}", text(&output));
+    }
+
+    let second = Sandbox::new();
+    second.write(".zshrc", "# local\n");
+    second.write(".config/kitty/theme.conf", "# local\n");
+    let elsewhere = second.home.parent().unwrap().join("dots");
+    let output = second.dm(&[
+        "--clone",
+        &first.remote_url(),
+        "--farm-dir",
+        &elsewhere.to_string_lossy(),
+    ]);
+    assert!(output.status.success(), "{}", text(&output));
+    let output = strip_ansi(&text(&output));
+    assert!(output.contains("Conflicts:"), "{output}");
+    assert!(output.contains("~/.zshrc"), "{output}");
+
+    // The local file keeps its folder real, only the repository's file in it is linked.
+    assert_eq!(
+        link_target(&second, ".config/kitty/kitty.conf"),
+        elsewhere.join(".config/kitty/kitty.conf").to_string_lossy()
+    );
+    assert_eq!(second.read(".config/kitty/kitty.conf"), "font_size 11\n");
+    assert_eq!(second.read(".config/kitty/theme.conf"), "# local\n");
+    assert_eq!(second.read(".zshrc"), "# local\n");
+    assert_eq!(
+        fs::read_to_string(elsewhere.join(".zshrc")).unwrap(),
+        "export EDITOR=vim\n"
+    );
+    assert_eq!(
+        second.tracking_list(),
+        vec![
+            second.path(".config/kitty/kitty.conf"),
+            second.path(".zshrc")
+        ]
+    );
+    assert!(Path::new(&elsewhere.join(".git")).is_file());
+}
@@ -0,0 +1,298 @@
+mod common;
+
+use common::{strip_ansi, text, Sandbox, KEY_DOWN, KEY_ENTER};
+
+#[test]
+fn update_commits_and_pushes() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "export EDITOR=nvim\n");
+    sb.dm(&["--add", &zshrc]);
+
+    let session = sb.dm_scripted(
+        &["--update"],
+        &[
+            ("Proceed to:", KEY_ENTER),
+            ("Add commit message: ", "add zshrc\r"),
+        ],
+    );
+    assert!(session.success, "{}", session.output);
+
+    let output = strip_ansi(&session.output);
+    assert!(output.contains("new file"));
+    assert!(output.contains("/.zshrc"));
+    assert_eq!(
+        sb.remote_git(&["log", "-1", "--format=%s", "main"]).trim(),
+        "add zshrc"
+    );
+    assert_eq!(
+        sb.remote_git(&["show", "main:.zshrc"]),
+        "export EDITOR=nvim\n"
+    );
+    assert!(sb.git(&["status", "--porcelain"]).is_empty());
+}
+
+#[test]
+fn update_without_changes_does_not_prompt() {
+    let sb = Sandbox::initialized();
+    let session = sb.dm_scripted(&["--update"], &[]);
+    assert!(session.success);
+    let output = strip_ansi(&session.output);
+    assert!(output.contains("Up to date"));
+    assert!(!output.contains("Proceed to:"));
+}
+
+#[test]
+fn update_can_be_aborted() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "a\n");
+    sb.dm(&["--add", &zshrc]);
+
+    let session = sb.dm_scripted(
+        &["--update"],
+        &[(
+            "Proceed to:",
+            &format!("{KEY_DOWN}{KEY_DOWN}{KEY_DOWN}{KEY_ENTER}"),
+        )],
+    );
+    assert!(!session.success);
+    assert!(strip_ansi(&session.output).contains("Terminating."));
+    assert_eq!(
+        sb.remote_git(&["log", "-1", "--format=%s", "main"]).trim(),
+        "Initial commit"
+    );
+}
+
+#[test]
+fn review_pages_selected_file() {
+    let sb = Sandbox::initialized();
+    sb.write(".zshrc", "one\n");
+    sb.write(".vimrc", "set nocompatible\n");
+    sb.dm(&["--add", &sb.path(".zshrc")]);
+    sb.dm(&["--add", &sb.path(".vimrc")]);
+    sb.git(&["commit", "-qm", "dotfiles"]);
+    sb.write(".zshrc", "two\n");
+    sb.write(".vimrc", "set number\n");
+
+    // Entries are sorted by path, so .zshrc is the second row.
+    let session = sb.dm_scripted(
+        &["--diff"],
+        &[
+            ("+set number", "j"),
+            ("+two", KEY_ENTER),
+            ("(END)", "q"),
+            ("", "q"),
+        ],
+    );
+    assert!(session.success, "{}", session.output);
+    let output = strip_ansi(&session.output);
+    assert!(output.contains("-one"));
+    assert!(output.contains("+two"));
+    // The alternate screen is left again on exit.
+    assert!(session.output.contains("\x1b[?1049l"));
+}
+
+#[test]
+fn review_stages_unstages_and_discards() {
+    let sb = Sandbox::initialized();
+    sb.write(".zshrc", "one\n");
+    sb.dm(&["--add", &sb.path(".zshrc")]);
+    sb.git(&["commit", "-qm", "zshrc"]);
+    sb.write(".zshrc", "two\n");
+
+    let session = sb.dm_scripted(&["--diff"], &[("+two", "u"), ("unstaged", "q")]);
+    assert!(session.success, "{}", session.output);
+    assert!(sb.git(&["diff", "--cached", "--name-only"]).is_empty());
+
+    let session = sb.dm_scripted(
+        &["--diff"],
+        &[("+two", "x"), ("discard", "y"), ("discarded", "q")],
+    );
+    assert!(session.success, "{}", session.output);
+    assert_eq!(sb.read(".zshrc"), "one\n");
+}
+
+#[test]
+fn review_commits_with_message_editor() {
+    let sb = Sandbox::initialized();
+    sb.write(".zshrc", "one\n");
+    sb.dm(&["--add", &sb.path(".zshrc")]);
+
+    let session = sb.dm_scripted(
+        &["--diff"],
+        &[
+            ("+one", "c"),
+            ("", "add zshrc"),
+            ("add zshrc", "\x13"),
+            ("committed", "p"),
+            ("pushed", "q"),
+        ],
+    );
+    assert!(session.success, "{}", session.output);
+    assert_eq!(
+        sb.remote_git(&["log", "-1", "--format=%s", "main"]).trim(),
+        "add zshrc"
+    );
+}
+
+#[test]
+fn review_filters_entries() {
+    let sb = Sandbox::initialized();
+    sb.write(".zshrc", "one\n");
+    sb.write(".vimrc", "set number\n");
+    sb.dm(&["--add", &sb.path(".zshrc")]);
+    sb.dm(&["--add", &sb.path(".vimrc")]);
+
+    // Filtering down to .zshrc leaves it as the only row, so staging state changes for it alone.
+    let session = sb.dm_scripted(
+        &["--diff"],
+        &[
+            ("+set number", "/zsh"),
+            ("+one", KEY_ENTER),
+            ("", "u"),
+            ("unstaged", "q"),
+        ],
+    );
+    assert!(session.success, "{}", session.output);
+    let staged = sb.git(&["diff", "--cached", "--name-only"]);
+    assert!(staged.contains(".vimrc"));
+    assert!(!staged.contains(".zshrc"));
+}
+
+#[test]
+fn update_can_review_before_committing() {
+    let sb = Sandbox::initialized();
+    sb.write(".zshrc", "one\n");
+    sb.dm(&["--add", &sb.path(".zshrc")]);
+    sb.git(&["commit", "-qm", "zshrc"]);
+    sb.write(".zshrc", "two\n");
+
+    let session = sb.dm_scripted(
+        &["--update"],
+        &[
+            ("Proceed to:", &format!("{KEY_DOWN}{KEY_ENTER}")),
+            ("+two", "q"),
+            ("Proceed to:", KEY_ENTER),
+            ("Add commit message: ", "edit zshrc\r"),
+        ],
+    );
+    assert!(session.success, "{}", session.output);
+    assert_eq!(
+        sb.remote_git(&["log", "-1", "--format=%s", "main"]).trim(),
+        "edit zshrc"
+    );
+}
+
+#[test]
+fn update_commits_only_the_chosen_files() {
+    let sb = Sandbox::initialized();
+    sb.write(".zshrc", "export EDITOR=nvim\n");
+    sb.write(".config/hypr/hyprland.conf", "monitor=,preferred\n");
+    sb.dm(&["--add", &sb.path(".zshrc")]);
+    sb.dm(&["--add", &sb.path(".config/hypr")]);
+
+    // Both files start selected; untick the first row (hypr) and keep whole files.
+    let session = sb.dm_scripted(
+        &["--update"],
+        &[
+            ("Proceed to:", &format!("{KEY_DOWN}{KEY_DOWN}{KEY_ENTER}")),
+            ("Files to commit", &format!(" {KEY_ENTER}")),
+            ("individual hunks?", "n"),
+            ("Add commit message: ", "zsh only\r"),
+        ],
+    );
+    assert!(session.success, "{}", session.output);
+    assert!(strip_ansi(&session.output).contains("Left out:"));
+    let committed = sb.remote_git(&["ls-tree", "-r", "--name-only", "main"]);
+    assert!(committed.contains(".zshrc"));
+    assert!(!committed.contains("hyprland.conf"));
+
+    let output = strip_ansi(&text(&sb.dm(&["--status"])));
+    assert!(output.contains("/.config/hypr/hyprland.conf"));
+    assert!(!output.contains("/.zshrc"));
+}
+
+#[test]
+fn update_with_tag_commits_only_that_group() {
+    let sb = Sandbox::initialized();
+    sb.write(".zshrc", "export EDITOR=nvim\n");
+    sb.write(".config/nvim/init.lua", "vim.o.number = true\n");
+    sb.dm(&["--add", &sb.path(".zshrc"), "--tag", "shell"]);
+    sb.dm(&["--add", &sb.path(".config/nvim"), "--tag", "nvim"]);
+
+    let session = sb.dm_scripted(
+        &["--update", "--tag", "nvim"],
+        &[
+            ("Proceed to:", KEY_ENTER),
+            ("Add commit message: ", "nvim only\r"),
+        ],
+    );
+    assert!(session.success, "{}", session.output);
+    let output = strip_ansi(&session.output);
+    assert!(output.contains("/.config/nvim/init.lua"));
+    assert!(!output.contains("/.zshrc"));
+    assert!(!output.contains("review"));
+    let committed = sb.remote_git(&["ls-tree", "-r", "--name-only", "main"]);
+    assert!(committed.contains(".config/nvim/init.lua"));
+    assert!(!committed.contains(".zshrc"));
+
+    let output = strip_ansi(&text(&sb.dm(&["--status"])));
+    assert!(output.contains("/.zshrc"));
+}
+
+#[test]
+fn add_without_path_picks_discovered_dotfiles() {
+    let sb = Sandbox::initialized();
+    sb.write(".zshrc", "export EDITOR=nvim\n");
+    sb.write(".config/nvim/init.lua", "vim.o.number = true\n");
+    sb.write(".config/alacritty/alacritty.toml", "[font]\n");
+    sb.dm(&["--add", &sb.path(".config/alacritty")]);
+
+    // Rows: shell ~/.zshrc, editor ~/.config/nvim, terminal ~/.config/alacritty (tracked).
+    let session = sb.dm_scripted(
+        &["--add"],
+        &[("Dotfiles to add", &format!(" {KEY_DOWN} {KEY_ENTER}"))],
+    );
+    assert!(session.success, "{}", session.output);
+    let output = strip_ansi(&session.output);
+    assert!(output.contains("tracked"));
+    assert!(output.contains("Added:"));
+    assert_eq!(
+        sb.tracking_list(),
+        vec![
+            sb.path(".config/alacritty"),
+            sb.path(".zshrc"),
+            sb.path(".config/nvim")
+        ]
+    );
+}
+
+#[test]
+fn list_rebuild_shows_the_difference_before_writing() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "export EDITOR=nvim\n");
+    sb.write(".config/nvim/init.lua", "vim.o.number = true\n");
+    sb.dm(&["--add", &zshrc]);
+    sb.dm(&["--add", &sb.path(".config/nvim")]);
+    let list = sb.data.join("list");
+    std::fs::write(&list, format!("{zshrc}\n{}", sb.path(".vimrc"))).unwrap();
+
+    let session = sb.dm_scripted(
+        &["--list", "--rebuild"],
+        &[("Write the rebuilt tracking list?", "n")],
+    );
+    assert!(session.success, "{}", session.output);
+    let output = strip_ansi(&session.output);
+    assert!(output.contains("- /.vimrc"));
+    assert!(output.contains("+ /.config/nvim"));
+    assert!(output.contains("1 added, 1 removed, 1 kept"));
+    assert_eq!(sb.tracking_list(), vec![zshrc.clone(), sb.path(".vimrc")]);
+
+    let session = sb.dm_scripted(
+        &["--list", "--rebuild"],
+        &[("Write the rebuilt tracking list?", "y")],
+    );
+    assert!(session.success, "{}", session.output);
+    assert_eq!(sb.tracking_list(), vec![sb.path(".config/nvim"), zshrc]);
+    let output = text(&sb.dm(&["--list", "--rebuild"]));
+    assert!(output.contains("already matches the repository"));
+}
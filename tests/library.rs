@@ -0,0 +1,394 @@
+mod common;
+
+use common::Sandbox;
+use dotmanager::{Change, DotRepo, Error, Pick};
+
+#[test]
+fn uninitialized_repo_is_reported() {
+    let sb = Sandbox::new();
+    let repo = sb.repo();
+    assert!(!repo.is_initialized());
+    assert!(matches!(repo.status(), Err(Error::NotInitialized(_))));
+    assert!(matches!(
+        repo.track(&sb.write(".zshrc", "")),
+        Err(Error::NotInitialized(_))
+    ));
+}
+
+#[test]
+fn paths_are_derived_from_home_and_data_dir() {
+    let repo = DotRepo::with_paths("/home/user/", "/data/dotmanager");
+    assert_eq!(repo.home(), "/home/user");
+    assert_eq!(repo.git_dir(), "/data/dotmanager/git");
+    assert_eq!(repo.list_file(), "/data/dotmanager/list");
+    assert_eq!(repo.home_relative("/home/user/.zshrc"), ".zshrc");
+    assert_eq!(
+        repo.home_relative("/home/username/.zshrc"),
+        "/home/username/.zshrc"
+    );
+    assert_eq!(repo.absolute(".config/nvim"), "/home/user/.config/nvim");
+}
+
+#[test]
+fn track_and_untrack_return_errors_instead_of_exiting() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    let nvim = sb.path(".config/nvim");
+    sb.write(".config/nvim/init.lua", "");
+    sb.write(".config/nvim2/init.lua", "");
+
+    repo.track(&nvim).unwrap();
+    assert!(matches!(repo.track(&nvim), Err(Error::AlreadyTracked(_))));
+    match repo.track(&sb.path(".config/nvim/init.lua")) {
+        Err(Error::NestedEntry { entry, .. }) => assert_eq!(entry, nvim),
+        other => panic!("unexpected {other:?}"),
+    }
+    // A sibling sharing the prefix is not nested.
+    repo.track(&sb.path(".config/nvim2")).unwrap();
+    assert!(matches!(
+        repo.track(&sb.path(".missing")),
+        Err(Error::PathNotFound(_))
+    ));
+    assert!(matches!(
+        repo.untrack(&sb.path(".config")),
+        Err(Error::NotTracked(_))
+    ));
+
+    repo.untrack(&nvim).unwrap();
+    assert_eq!(
+        repo.tracking_list().unwrap().paths(),
+        vec![sb.path(".config/nvim2")]
+    );
+}
+
+#[test]
+fn status_diff_commit_push_pull() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    let zshrc = sb.write(".zshrc", "one\n");
+    repo.track(&zshrc).unwrap();
+
+    let status = repo.status().unwrap();
+    assert_eq!(status.remote_url, sb.remote_url());
+    assert_eq!(status.entries.len(), 1);
+    assert_eq!(status.entries[0].change, Change::Added);
+    assert_eq!(status.entries[0].path, ".zshrc");
+    assert_eq!(status.counts(), [1, 0, 0]);
+
+    let diff = repo.diff(&zshrc).unwrap();
+    assert_eq!(diff.hunks[0].lines[0].content, "one");
+    assert_eq!(repo.changed_files().unwrap(), vec![".zshrc"]);
+
+    let hash = repo.commit("add \"zshrc\"").unwrap();
+    repo.push().unwrap();
+    assert!(repo.status().unwrap().is_clean());
+    assert!(matches!(repo.diff(&zshrc), Err(Error::NoChanges(_))));
+    assert_eq!(
+        sb.remote_git(&["log", "-1", "--format=%h %s", "main"])
+            .trim(),
+        format!("{hash} add \"zshrc\"")
+    );
+
+    // Push a change, rewind the local repository and pull it back into HOME.
+    sb.write(".zshrc", "two\n");
+    repo.stage_all().unwrap();
+    repo.commit("edit").unwrap();
+    repo.push().unwrap();
+    sb.git(&["reset", "--hard", "-q", "HEAD~1"]);
+    assert_eq!(sb.read(".zshrc"), "one\n");
+    repo.pull().unwrap();
+    assert_eq!(sb.read(".zshrc"), "two\n");
+}
+
+#[test]
+fn stage_all_prunes_vanished_entries() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    let zshrc = sb.write(".zshrc", "");
+    let vimrc = sb.write(".vimrc", "");
+    repo.track(&zshrc).unwrap();
+    repo.track(&vimrc).unwrap();
+    std::fs::remove_file(&vimrc).unwrap();
+
+    assert_eq!(repo.stage_all().unwrap(), vec![vimrc]);
+    assert_eq!(repo.tracking_list().unwrap().paths(), vec![zshrc]);
+}
+
+#[test]
+fn command_line_paths_resolve_against_home_and_cwd() {
+    use dotmanager::paths::resolve;
+    let home = "/home/user";
+    let cwd = "/home/user/.config";
+    assert_eq!(resolve("~", home, cwd), "/home/user");
+    assert_eq!(resolve("~/.zshrc", home, cwd), "/home/user/.zshrc");
+    assert_eq!(resolve("/etc/hosts", home, cwd), "/etc/hosts");
+    assert_eq!(resolve("nvim/", home, cwd), "/home/user/.config/nvim");
+    assert_eq!(resolve("../.zshrc", home, cwd), "/home/user/.zshrc");
+    assert_eq!(resolve("./a/./b/../c", home, cwd), "/home/user/.config/a/c");
+}
+
+#[test]
+fn commit_picked_leaves_the_rest_staged() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    let lines: Vec<String> = (1..=20).map(|i| format!("line {i}\n")).collect();
+    sb.write(".zshrc", &lines.concat());
+    sb.write(".config/hypr/hyprland.conf", "monitor=,preferred\n");
+    repo.track(&sb.path(".zshrc")).unwrap();
+    repo.track(&sb.path(".config/hypr")).unwrap();
+    repo.commit("dotfiles").unwrap();
+
+    let mut edited = lines.clone();
+    edited[1] = "line two\n".to_string();
+    edited[17] = "line eighteen\n".to_string();
+    sb.write(".zshrc", &edited.concat());
+    sb.write(".config/hypr/hyprland.conf", "monitor=,highres\n");
+    repo.stage_all().unwrap();
+
+    // Only the first hunk of .zshrc; the hypr experiment stays out.
+    let file = repo.diff(&sb.path(".zshrc")).unwrap();
+    assert_eq!(file.hunks.len(), 2);
+    repo.commit_picked("zsh tweak", &[Pick::Hunks(file, vec![0])])
+        .unwrap();
+
+    let committed = sb.git(&["show", "HEAD:.zshrc"]);
+    assert!(committed.contains("line two\n"));
+    assert!(committed.contains("line 18\n"));
+    assert_eq!(
+        sb.git(&["show", "HEAD:.config/hypr/hyprland.conf"]),
+        "monitor=,preferred\n"
+    );
+    assert_eq!(sb.read(".zshrc"), edited.concat());
+    let mut left: Vec<String> = repo
+        .status()
+        .unwrap()
+        .entries
+        .into_iter()
+        .map(|e| e.path)
+        .collect();
+    left.sort();
+    assert_eq!(left, vec![".config/hypr/hyprland.conf", ".zshrc"]);
+
+    // Whole files, including a deletion.
+    std::fs::remove_file(sb.path(".config/hypr/hyprland.conf")).unwrap();
+    repo.stage_all().unwrap();
+    repo.commit_picked(
+        "rest",
+        &[
+            Pick::File(".zshrc".to_string()),
+            Pick::File(".config/hypr/hyprland.conf".to_string()),
+        ],
+    )
+    .unwrap();
+    assert!(repo.status().unwrap().is_clean());
+    assert_eq!(sb.git(&["show", "HEAD:.zshrc"]), edited.concat());
+
+    assert!(matches!(
+        repo.commit_picked("nothing", &[]),
+        Err(Error::NoChanges(_))
+    ));
+}
+
+#[test]
+fn discover_groups_likely_dotfiles_and_skips_noise() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    sb.write(".zshrc", "export EDITOR=nvim\n");
+    sb.write(".gitconfig", "[user]\n");
+    sb.write(".config/nvim/init.lua", "vim.o.number = true\n");
+    sb.write(".config/nvim/lua/plugins.lua", "return {}\n");
+    sb.write(".config/kitty/kitty.conf", "font_size 12\n");
+    sb.write(".zsh_history", ": 1:0;ls\n");
+    sb.write(".cache/nvim/log", "");
+    sb.write(".config/chromium-cache/data", "");
+    sb.write("notes.txt", "not a dotfile\n");
+    for i in 0..1001 {
+        sb.write(&format!(".config/huge/{i}"), "");
+    }
+    repo.track(&sb.path(".config/kitty")).unwrap();
+
+    let found: Vec<(String, &str, usize, bool)> = repo
+        .discover()
+        .unwrap()
+        .iter()
+        .map(|c| {
+            let rel = repo.home_relative(&c.path).to_string();
+            (rel, c.group.title(), c.files, c.tracked)
+        })
+        .collect();
+    let rels: Vec<&str> = found.iter().map(|f| f.0.as_str()).collect();
+    assert!(found.contains(&(".zshrc".into(), "shell", 1, false)));
+    assert!(found.contains(&(".config/nvim".into(), "editor", 2, false)));
+    assert!(found.contains(&(".config/kitty".into(), "terminal", 1, true)));
+    assert!(found.contains(&(".gitconfig".into(), "git", 1, false)));
+    for skipped in [
+        ".zsh_history",
+        ".cache",
+        ".config/chromium-cache",
+        "notes.txt",
+        ".config/huge",
+    ] {
+        assert!(!rels.contains(&skipped), "{skipped} should be skipped");
+    }
+    // Grouped in picker order: shell before editor before terminal before git.
+    let position = |rel: &str| rels.iter().position(|r| *r == rel).unwrap();
+    assert!(position(".zshrc") < position(".config/nvim"));
+    assert!(position(".config/nvim") < position(".config/kitty"));
+    assert!(position(".config/kitty") < position(".gitconfig"));
+}
+
+#[test]
+fn rebuild_list_collapses_fully_tracked_folders() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    sb.write(".zshrc", "export EDITOR=nvim\n");
+    sb.write(".config/nvim/init.lua", "vim.o.number = true\n");
+    sb.write(".config/nvim/lua/plugins.lua", "return {}\n");
+    sb.write(".config/nvim/.gitignore", "*.log\n");
+    sb.write(".config/nvim/lsp.log", "ignored noise\n");
+    sb.write(".config/fish/config.fish", "set -x EDITOR nvim\n");
+    sb.write(".config/fish/fish_variables", "untracked state\n");
+    for path in [".zshrc", ".config/nvim", ".config/fish/config.fish"] {
+        repo.track(&sb.path(path)).unwrap();
+    }
+    let expected = repo.tracking_list().unwrap();
+    std::fs::remove_file(repo.list_file()).unwrap();
+
+    let mut rebuilt = repo.rebuild_list().unwrap().paths();
+    rebuilt.sort();
+    let mut expected = expected.paths();
+    expected.sort();
+    assert_eq!(rebuilt, expected);
+    assert!(repo.tracking_list().unwrap().entries.is_empty());
+
+    // With the index emptied, HEAD is the source.
+    repo.commit("track").unwrap();
+    sb.git(&["rm", "-r", "-q", "--cached", "."]);
+    let mut rebuilt = repo.rebuild_list().unwrap().paths();
+    rebuilt.sort();
+    assert_eq!(rebuilt, expected);
+}
+
+#[test]
+fn tracking_list_migrates_and_keeps_metadata() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    let zshrc = sb.write(".zshrc", "a\n");
+    let nvim = sb.path(".config/nvim");
+    sb.write(".config/nvim/init.lua", "");
+    std::fs::write(repo.list_file(), format!("{zshrc}\n{nvim}\n")).unwrap();
+
+    assert_eq!(
+        repo.tracking_list().unwrap().paths(),
+        vec![zshrc.clone(), nvim.clone()]
+    );
+    let migrated = std::fs::read_to_string(repo.list_file()).unwrap();
+    assert!(migrated.starts_with("version = 1\n"));
+    assert!(migrated.contains(&format!("[[entry]]\npath = \"{zshrc}\"\n")));
+    let backup = std::fs::read_to_string(format!("{}.v0", repo.list_file())).unwrap();
+    assert_eq!(backup, format!("{zshrc}\n{nvim}\n"));
+
+    let with_metadata = migrated.replacen(
+        &format!("path = \"{zshrc}\"\n"),
+        &format!(
+            "path = \"{zshrc}\"\ntags = [\"shell\"]\ndescription = \"zsh setup\"\n\
+             exclude = [\"*.zwc\"]\nencrypt = true\ntemplate = true\nhosts = [\"laptop\"]\n\
+             mode = \"0600\"\n"
+        ),
+        1,
+    );
+    std::fs::write(repo.list_file(), &with_metadata).unwrap();
+    repo.track(&sb.write(".vimrc", "")).unwrap();
+    let list = repo.tracking_list().unwrap();
+    let entry = list.get(&zshrc).unwrap();
+    assert_eq!(entry.tags, vec!["shell"]);
+    assert_eq!(entry.description.as_deref(), Some("zsh setup"));
+    assert_eq!(entry.exclude, vec!["*.zwc"]);
+    assert!(entry.encrypt && entry.template);
+    assert_eq!(entry.hosts, vec!["laptop"]);
+    assert_eq!(entry.mode.as_deref(), Some("0600"));
+    assert_eq!(list.get(&nvim).unwrap().tags, Vec::<String>::new());
+}
+
+#[test]
+fn entry_excludes_hosts_and_modes_are_honoured() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    let nvim = sb.path(".config/nvim");
+    sb.write(".config/nvim/init.lua", "");
+    sb.write(".config/nvim/lazy-lock.json", "{}\n");
+    sb.write(".config/nvim/cache/swap", "");
+    sb.write(".config/nvim/lua/cache/keep.lua", "");
+    let zshrc = sb.write(".zshrc", "");
+    repo.track(&nvim).unwrap();
+    repo.track(&zshrc).unwrap();
+
+    let mut list = repo.tracking_list().unwrap();
+    list.entries[0].exclude = vec!["lazy-lock.json".into(), "/cache/".into()];
+    list.entries[1].mode = Some("0600".into());
+    let mut elsewhere = dotmanager::Entry::new(&sb.path(".xinitrc"));
+    elsewhere.hosts = vec!["some-other-host".into()];
+    list.entries.push(elsewhere);
+    repo.save_list(&list).unwrap();
+
+    // Excluded files leave the index, the other host's entry stays although it is missing.
+    assert_eq!(repo.stage_all().unwrap(), Vec::<String>::new());
+    assert!(repo.tracking_list().unwrap().contains(&sb.path(".xinitrc")));
+    let staged: Vec<String> = repo
+        .status()
+        .unwrap()
+        .entries
+        .into_iter()
+        .map(|e| e.path)
+        .collect();
+    assert!(staged.contains(&".config/nvim/init.lua".to_string()));
+    assert!(staged.contains(&".config/nvim/lua/cache/keep.lua".to_string()));
+    assert!(!staged.iter().any(|f| f.ends_with("lazy-lock.json")));
+    assert!(!staged.iter().any(|f| f.ends_with("cache/swap")));
+
+    sb.git(&["commit", "-qm", "nvim"]);
+    sb.write(".config/nvim/lazy-lock.json", "{\"changed\": true}\n");
+    let nvim_state = repo
+        .entries()
+        .unwrap()
+        .into_iter()
+        .find(|e| e.path == nvim)
+        .unwrap()
+        .state;
+    assert_eq!(nvim_state, dotmanager::EntryState::Clean);
+
+    let problem = repo
+        .doctor()
+        .unwrap()
+        .into_iter()
+        .find(|p| p.message.contains("requires 0600"))
+        .expect("the mode of .zshrc should be reported");
+    repo.repair(problem.repair.as_ref().unwrap()).unwrap();
+    let mode = std::fs::metadata(&zshrc).unwrap().permissions();
+    assert_eq!(
+        std::os::unix::fs::PermissionsExt::mode(&mode) & 0o7777,
+        0o600
+    );
+}
+
+#[test]
+fn malformed_tracking_list_fails_loudly() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    for content in [
+        "version = 1\n[[entry]]\npath = \".zshrc\"\n",
+        "version = 2\n",
+        "version = 1\n[[entry]]\npath = \"/home/x\"\ncolour = \"red\"\n",
+        "version = 1\n[[entry]]\npath = \"/home/x\"\nmode = \"rw\"\n",
+        "/home/x/.zshrc\nnot a path\n",
+    ] {
+        std::fs::write(repo.list_file(), content).unwrap();
+        let err = repo.tracking_list().unwrap_err();
+        assert!(matches!(err, Error::InvalidList { .. }), "{content}: {err}");
+        assert!(err.to_string().contains("invalid tracking list"));
+        assert!(matches!(repo.stage_all(), Err(Error::InvalidList { .. })));
+    }
+    let output = sb.dm(&["--status"]);
+    assert!(!output.status.success());
+    assert!(common::text(&output).contains("invalid tracking list"));
+}
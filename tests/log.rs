@@ -0,0 +1,137 @@
+mod common;
+
+use common::{strip_ansi, text, Sandbox, KEY_DOWN, KEY_ENTER};
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .unwrap()
+        .trim()
+        .to_string()
+}
+
+#[test]
+fn commits_carry_the_host_and_show_in_the_log() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    sb.write(".zshrc", "a\n");
+    repo.track(&sb.path(".zshrc")).unwrap();
+    repo.commit("add zshrc").unwrap();
+
+    let entries = repo.log(None, None).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].subject, "add zshrc");
+    assert_eq!(entries[0].host, hostname());
+    assert_eq!(entries[0].files, vec![".zshrc"]);
+    assert_eq!(entries[1].files, vec![".github/README.md"]);
+    assert_eq!(repo.log(None, Some(1)).unwrap().len(), 1);
+
+    let output = text(&sb.dm(&["--log"]));
+    assert!(output.contains(&entries[0].short));
+    assert!(output.contains("add zshrc"));
+    assert!(output.contains("Initial commit"));
+    assert!(output.contains("/.zshrc"));
+    assert!(output.contains(&hostname()));
+}
+
+#[test]
+fn file_history_follows_renames() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    sb.write(
+        ".config/nvim/init.vim",
+        "set number\nset hidden\nset ruler\n",
+    );
+    sb.write(".vimrc", "x\n");
+    repo.track(&sb.path(".config/nvim")).unwrap();
+    repo.track(&sb.path(".vimrc")).unwrap();
+    repo.commit("nvim").unwrap();
+    std::fs::rename(
+        sb.path(".config/nvim/init.vim"),
+        sb.path(".config/nvim/init.lua"),
+    )
+    .unwrap();
+    repo.stage_all().unwrap();
+    repo.commit("rename init").unwrap();
+
+    let entries = repo
+        .log(Some(&sb.path(".config/nvim/init.lua")), None)
+        .unwrap();
+    let subjects: Vec<&str> = entries.iter().map(|e| e.subject.as_str()).collect();
+    assert_eq!(subjects, vec!["rename init", "nvim"]);
+    assert_eq!(entries[1].files, vec![".config/nvim/init.vim"]);
+
+    // A whole tracking entry covers every file below it.
+    let entries = repo.log(Some(&sb.path(".config/nvim")), None).unwrap();
+    assert_eq!(entries.len(), 2);
+
+    let diff = repo
+        .commit_diff(&entries[1].hash, &entries[1].files)
+        .unwrap();
+    assert_eq!(diff.len(), 1);
+    assert_eq!(diff[0].path(), ".config/nvim/init.vim");
+
+    let output = text(&sb.dm(&["--log", "~/.vimrc"]));
+    assert!(output.contains("nvim"));
+    assert!(!output.contains("rename init"));
+}
+
+#[test]
+fn entry_history_follows_renames_of_the_files_below_it() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    sb.write(".vimrc", "set number\nset hidden\nset ruler\n");
+    repo.track(&sb.path(".vimrc")).unwrap();
+    repo.commit("vimrc").unwrap();
+    sb.write(".config/nvim/plugins.lua", "return {}\n");
+    repo.track(&sb.path(".config/nvim")).unwrap();
+    repo.commit("nvim").unwrap();
+    sb.write(".config/nvim/init.vim", &sb.read(".vimrc"));
+    repo.untrack(&sb.path(".vimrc")).unwrap();
+    repo.stage_all().unwrap();
+    repo.commit("move vimrc into nvim").unwrap();
+    std::fs::rename(
+        sb.path(".config/nvim/init.vim"),
+        sb.path(".config/nvim/init.lua"),
+    )
+    .unwrap();
+    repo.stage_all().unwrap();
+    repo.commit("rename init").unwrap();
+
+    let entries = repo.log(Some(&sb.path(".config/nvim")), None).unwrap();
+    let subjects: Vec<&str> = entries.iter().map(|e| e.subject.as_str()).collect();
+    assert_eq!(
+        subjects,
+        vec!["rename init", "move vimrc into nvim", "nvim", "vimrc"]
+    );
+    assert_eq!(entries[0].files, vec![".config/nvim/init.lua"]);
+    assert_eq!(entries[3].files, vec![".vimrc"]);
+    assert_eq!(
+        repo.log(Some(&sb.path(".config/nvim")), Some(2))
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[test]
+fn interactive_log_shows_commit_diff() {
+    let sb = Sandbox::initialized();
+    sb.write(".zshrc", "one\n");
+    sb.dm(&["--add", &sb.path(".zshrc")]);
+    sb.git(&["commit", "-qm", "first"]);
+    sb.write(".zshrc", "two\n");
+    sb.git(&["commit", "-qam", "second"]);
+
+    let session = sb.dm_scripted(
+        &["--log", "~/.zshrc", "--interactive"],
+        &[
+            ("Show commit", &format!("{KEY_DOWN}{KEY_ENTER}")),
+            ("+one", "q"),
+            ("Show commit", "\x1b"),
+        ],
+    );
+    assert!(session.success, "{}", session.output);
+    let output = strip_ansi(&session.output);
+    assert!(output.contains("+one"));
+    assert!(!output.contains("+two"));
+}
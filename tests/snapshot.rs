@@ -0,0 +1,225 @@
+mod common;
+
+use common::{text, Sandbox};
+
+/// Two commits: `.zshrc` "one", then "two" plus a new `.vimrc`.
+fn history(sb: &Sandbox) {
+    sb.write(".zshrc", "one\n");
+    sb.dm(&["--add", &sb.path(".zshrc")]);
+    sb.git(&["commit", "-qm", "one"]);
+    sb.write(".zshrc", "two\n");
+    sb.write(".vimrc", "set number\n");
+    sb.dm(&["--add", &sb.path(".vimrc")]);
+    sb.git(&["commit", "-qam", "two"]);
+}
+
+#[test]
+fn checkout_at_commit_and_return() {
+    let sb = Sandbox::initialized();
+    history(&sb);
+    sb.write(".zshrc", "three\n");
+
+    let output = sb.dm(&["--checkout", "--at", "HEAD~1"]);
+    assert!(output.status.success(), "{}", text(&output));
+    let out = text(&output);
+    assert!(out.contains("modified"));
+    assert!(out.contains("/.zshrc"));
+    assert!(out.contains("deleted"));
+    assert!(out.contains("/.vimrc"));
+    assert_eq!(sb.read(".zshrc"), "one\n");
+    assert!(!std::path::Path::new(&sb.path(".vimrc")).exists());
+    // HEAD is untouched, the rollback shows up as a change to commit.
+    assert_eq!(sb.git(&["log", "-1", "--format=%s"]).trim(), "two");
+
+    let output = sb.dm(&["--checkout", "--return"]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert!(text(&output).contains("Returned:"));
+    assert_eq!(sb.read(".zshrc"), "three\n");
+    assert_eq!(sb.read(".vimrc"), "set number\n");
+    assert_eq!(
+        sb.tracking_list(),
+        vec![sb.path(".zshrc"), sb.path(".vimrc")]
+    );
+
+    let output = sb.dm(&["--checkout", "--return"]);
+    assert!(!output.status.success());
+    assert!(text(&output).contains("no rolled back checkout"));
+}
+
+#[test]
+fn checkout_at_date() {
+    let sb = Sandbox::initialized();
+    history(&sb);
+
+    let output = sb.dm(&["--checkout", "--at", "1990-01-01"]);
+    assert!(!output.status.success());
+    assert!(text(&output).contains("unknown revision '1990-01-01'"));
+
+    let output = sb.dm(&["--checkout", "--at", "tomorrow"]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert!(text(&output).contains("none"));
+    assert_eq!(sb.read(".zshrc"), "two\n");
+
+    let output = sb.dm(&["--checkout"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn remove_takes_a_snapshot_that_restores_the_files() {
+    let sb = Sandbox::initialized();
+    sb.write(".config/nvim/init.lua", "a\n");
+    let nvim = sb.path(".config/nvim");
+    sb.dm(&["--add", &nvim]);
+    sb.git(&["commit", "-qm", "nvim"]);
+
+    assert!(sb.dm(&["--remove", &nvim]).status.success());
+    assert!(!std::path::Path::new(&nvim).exists());
+
+    let repo = sb.repo();
+    let snapshots = repo.snapshots().unwrap();
+    assert_eq!(snapshots.len(), 1);
+    assert!(snapshots[0].is_automatic());
+    assert_eq!(snapshots[0].label, "before remove .config/nvim");
+    assert!(text(&sb.dm(&["--snapshot", "list"])).contains("before remove .config/nvim"));
+
+    let output = sb.dm(&["--snapshot", "restore", &snapshots[0].id]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(sb.read(".config/nvim/init.lua"), "a\n");
+    assert_eq!(sb.tracking_list(), vec![nvim]);
+    // Restoring took a snapshot of its own.
+    assert_eq!(repo.snapshots().unwrap().len(), 2);
+}
+
+#[test]
+fn named_snapshots_and_pruning() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    sb.write(".zshrc", "one\n");
+    sb.dm(&["--add", &sb.path(".zshrc")]);
+
+    assert!(sb.dm(&["--snapshot", "create", "working"]).status.success());
+    let output = sb.dm(&["--snapshot", "create", "working"]);
+    assert!(!output.status.success());
+    assert!(text(&output).contains("not a valid snapshot name"));
+    assert!(!sb.dm(&["--snapshot", "create", "auto-1"]).status.success());
+    assert!(!sb.dm(&["--snapshot", "create", "a..b"]).status.success());
+
+    for _ in 0..3 {
+        repo.create_snapshot(None, "by test").unwrap();
+    }
+    assert_eq!(repo.snapshots().unwrap().len(), 4);
+    let output = sb.dm(&["--snapshot", "prune", "--keep", "1"]);
+    assert!(text(&output).contains("2 snapshot(s)"));
+    let ids: Vec<String> = repo
+        .snapshots()
+        .unwrap()
+        .into_iter()
+        .map(|s| s.id)
+        .collect();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains(&"working".to_string()));
+
+    // The retention policy is read from config.toml and applied to automatic snapshots.
+    std::fs::write(sb.data.join("config.toml"), "[snapshots]\nkeep = 0\n").unwrap();
+    repo.create_snapshot(None, "by test").unwrap();
+    assert_eq!(repo.snapshots().unwrap().len(), 1);
+
+    sb.write(".zshrc", "two\n");
+    assert!(sb
+        .dm(&["--snapshot", "restore", "working"])
+        .status
+        .success());
+    assert_eq!(sb.read(".zshrc"), "one\n");
+
+    assert!(sb.dm(&["--snapshot", "delete", "working"]).status.success());
+    let output = sb.dm(&["--snapshot", "restore", "working"]);
+    assert!(text(&output).contains("unknown snapshot 'working'"));
+
+    std::fs::write(sb.data.join("config.toml"), "[snapshots]\nkeep = \"all\"\n").unwrap();
+    let output = sb.dm(&["--snapshot", "prune"]);
+    assert!(!output.status.success());
+    assert!(text(&output).contains("invalid configuration"));
+}
+
+#[test]
+fn clone_checks_out_files_and_keeps_replaced_ones() {
+    let origin = Sandbox::initialized();
+    origin.write(".bashrc", "from repo\n");
+    origin.write(".config/nvim/init.lua", "a\n");
+    origin.write(".config/starship.toml", "b\n");
+    origin.dm(&["--add", &origin.path(".bashrc")]);
+    origin.dm(&["--add", &origin.path(".config/nvim")]);
+    origin.dm(&["--add", &origin.path(".config/starship.toml")]);
+    origin.git(&["commit", "-qm", "dotfiles"]);
+    origin.git(&["push", "--quiet"]);
+
+    let machine = Sandbox::new();
+    machine.write(".bashrc", "distro default\n");
+    let output = machine.dm(&["--clone", &origin.remote_url()]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(machine.read(".bashrc"), "from repo\n");
+    assert_eq!(machine.read(".config/nvim/init.lua"), "a\n");
+    assert_eq!(
+        machine.tracking_list(),
+        vec![
+            machine.path(".bashrc"),
+            machine.path(".config/nvim"),
+            machine.path(".config/starship.toml"),
+        ]
+    );
+    assert!(text(&machine.dm(&["--status"])).contains("Up to date"));
+
+    // Pulls go through the configured upstream and snapshot first.
+    origin.write(".bashrc", "updated\n");
+    origin.git(&["commit", "-qam", "update"]);
+    origin.git(&["push", "--quiet"]);
+    assert!(machine.dm(&["--pull"]).status.success());
+    assert_eq!(machine.read(".bashrc"), "updated\n");
+
+    let repo = machine.repo();
+    let labels: Vec<String> = repo
+        .snapshots()
+        .unwrap()
+        .into_iter()
+        .map(|s| s.label)
+        .collect();
+    assert_eq!(labels, vec!["before clone", "before pull"]);
+    let clone = &repo.snapshots().unwrap()[0];
+    let output = machine.dm(&["--snapshot", "restore", &clone.id]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(machine.read(".bashrc"), "distro default\n");
+    assert!(!machine.home.join(".config/nvim/init.lua").exists());
+
+    let output = machine.dm(&["--clone", &origin.remote_url()]);
+    assert!(text(&output).contains("already exists"));
+}
+
+#[test]
+fn clone_keeps_entries_below_shared_folders_and_clear_of_local_files() {
+    let origin = Sandbox::initialized();
+    origin.write(".local/bin/tool", "#!/bin/sh\n");
+    origin.write(".bashrc", "from repo\n");
+    origin.write(".vim/colors/dark.vim", "hi Normal\n");
+    for rel in [".local/bin/tool", ".bashrc", ".vim"] {
+        origin.dm(&["--add", &origin.path(rel)]);
+    }
+    origin.git(&["commit", "-qm", "dotfiles"]);
+    origin.git(&["push", "--quiet"]);
+
+    let machine = Sandbox::new();
+    machine.write(".local/share/app/data.db", "local\n");
+    machine.write(".vim/spell/en.add", "local\n");
+    let output = machine.dm(&["--clone", &origin.remote_url()]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(
+        machine.tracking_list(),
+        vec![
+            machine.path(".bashrc"),
+            machine.path(".local/bin"),
+            machine.path(".vim/colors"),
+        ]
+    );
+    let output = text(&machine.dm(&["--status"]));
+    assert!(output.contains("Up to date"), "{output}");
+    assert!(!output.contains("data.db") && !output.contains("en.add"));
+}
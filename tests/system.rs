@@ -0,0 +1,184 @@
+mod common;
+
+use common::{strip_ansi, text, Sandbox};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+/// A folder outside the sandboxed HOME standing in for `/etc`.
+fn etc(sb: &Sandbox) -> PathBuf {
+    let etc = sb.home.parent().unwrap().join("etc");
+    fs::create_dir_all(etc.join("X11/xorg.conf.d")).unwrap();
+    fs::write(etc.join("pacman.conf"), "[options]\nColor\n").unwrap();
+    fs::write(etc.join("X11/xorg.conf.d/00-keyboard.conf"), "Section\n").unwrap();
+    etc
+}
+
+/// Points `[system] escalate` at a script that logs each write and runs it.
+fn escalate_through_log(sb: &Sandbox) -> PathBuf {
+    let log = sb.data.join("escalate.log");
+    let script = sb.data.join("escalate.sh");
+    fs::write(
+        &script,
+        format!("#!/bin/sh\necho \"$@\" >> {}\nexec \"$@\"\n", log.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        sb.data.join("config.toml"),
+        format!("[system]\nescalate = \"{}\"\n", script.display()),
+    )
+    .unwrap();
+    log
+}
+
+#[test]
+fn system_entries_are_kept_under_a_prefix_with_their_modes() {
+    let sb = Sandbox::initialized();
+    let etc = etc(&sb);
+    let pacman = etc.join("pacman.conf").to_string_lossy().to_string();
+    let xorg = etc.join("X11/xorg.conf.d").to_string_lossy().to_string();
+    fs::set_permissions(&pacman, fs::Permissions::from_mode(0o640)).unwrap();
+    for path in [&pacman, &xorg] {
+        let output = sb.dm(&["--add", path]);
+        assert!(output.status.success(), "{}", text(&output));
+    }
+    let prefix = format!(".dm/system{}", etc.display());
+
+    let output = strip_ansi(&text(&sb.dm(&["--status"])));
+    assert!(
+        output.contains(&format!("/{prefix}/pacman.conf")),
+        "{output}"
+    );
+    assert!(output.contains(&format!("/{prefix}/X11/xorg.conf.d/00-keyboard.conf")));
+    sb.git(&["commit", "-qm", "system"]);
+    // Git keeps the files in the repository only, never in HOME.
+    assert!(!sb.home.join(".dm").exists());
+    assert_eq!(
+        sb.git(&["show", &format!("HEAD:{prefix}/pacman.conf")]),
+        "[options]\nColor\n"
+    );
+    let modes = sb.git(&["show", "HEAD:.dm/modes.toml"]);
+    assert!(modes.contains(&format!("path = \"{prefix}/pacman.conf\"\nmode = \"0640\"")));
+    assert!(modes.contains("owner = "));
+
+    let output = strip_ansi(&text(&sb.dm(&["--list"])));
+    assert!(output.contains(" /\n"), "{output}");
+    assert!(output.contains("pacman.conf"));
+    assert!(output.ends_with("clean\n\n"), "{output}");
+    fs::write(&pacman, "[options]\n").unwrap();
+    let output = strip_ansi(&text(&sb.dm(&["--list"])));
+    assert!(output
+        .lines()
+        .any(|l| l.contains("pacman.conf") && l.ends_with("modified")));
+
+    let output = text(&sb.dm(&["--diff", &pacman]));
+    assert!(output.contains("-Color"), "{output}");
+    let output = text(&sb.dm(&["--which", &pacman]));
+    assert!(output.contains("Tracked:"), "{output}");
+    assert!(text(&sb.dm(&["--doctor"]))
+        .lines()
+        .all(|l| !l.contains("pacman")));
+
+    let output = sb.dm(&["--remove", &xorg]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert!(!sb.git(&["ls-files"]).contains("00-keyboard.conf"));
+}
+
+#[test]
+fn restore_writes_system_files_through_the_escalation_command() {
+    let sb = Sandbox::initialized();
+    let log = escalate_through_log(&sb);
+    let pacman = etc(&sb).join("pacman.conf");
+    let path = pacman.to_string_lossy().to_string();
+    fs::set_permissions(&pacman, fs::Permissions::from_mode(0o600)).unwrap();
+    sb.dm(&["--add", &path]);
+    sb.git(&["commit", "-qm", "pacman"]);
+
+    fs::write(&pacman, "broken\n").unwrap();
+    fs::set_permissions(&pacman, fs::Permissions::from_mode(0o666)).unwrap();
+    let output = sb.dm(&["--restore", &path]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(fs::read_to_string(&pacman).unwrap(), "[options]\nColor\n");
+    let mode = fs::metadata(&pacman).unwrap().permissions().mode() & 0o7777;
+    assert_eq!(mode, 0o600);
+    let escalated = fs::read_to_string(&log).unwrap();
+    assert!(
+        escalated.starts_with("install -D -m 0600 -o "),
+        "{escalated}"
+    );
+    assert!(escalated.trim_end().ends_with(&path));
+    // The live file that was replaced is backed up.
+    let backups = sb.data.join("backups");
+    assert!(fs::read_dir(&backups).unwrap().next().is_some());
+    assert!(strip_ansi(&text(&sb.dm(&["--status"]))).contains("Up to date"));
+
+    fs::write(
+        sb.data.join("config.toml"),
+        "[system]\nescalate = \"false\"\n",
+    )
+    .unwrap();
+    fs::write(&pacman, "broken\n").unwrap();
+    let output = sb.dm(&["--restore", &path]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        text(&output).contains("'false install -D"),
+        "{}",
+        text(&output)
+    );
+}
+
+#[test]
+fn review_stages_and_discards_system_files_at_their_real_paths() {
+    let sb = Sandbox::initialized();
+    escalate_through_log(&sb);
+    let pacman = etc(&sb).join("pacman.conf");
+    let path = pacman.to_string_lossy().to_string();
+    sb.dm(&["--add", &path]);
+    sb.git(&["commit", "-qm", "pacman"]);
+    fs::write(&pacman, "[options]\n").unwrap();
+
+    let session = sb.dm_scripted(&["--diff"], &[("-Color", "u"), ("unstaged", "q")]);
+    assert!(session.success, "{}", session.output);
+    assert!(sb.git(&["diff", "--cached", "--name-only"]).is_empty());
+    let repo = sb.repo();
+    repo.stage(&path).unwrap();
+    let staged = sb.git(&["diff", "--cached"]);
+    assert!(staged.contains("-Color"), "{staged}");
+    assert!(!staged.contains("deleted file"), "{staged}");
+    repo.unstage(&path).unwrap();
+
+    // The next review stages the change again.
+
+    let session = sb.dm_scripted(
+        &["--diff"],
+        &[("-Color", "x"), ("discard", "y"), ("discarded", "q")],
+    );
+    assert!(session.success, "{}", session.output);
+    assert_eq!(fs::read_to_string(&pacman).unwrap(), "[options]\nColor\n");
+}
+
+#[test]
+fn clone_lists_system_entries_without_writing_them() {
+    let origin = Sandbox::initialized();
+    let pacman = etc(&origin)
+        .join("pacman.conf")
+        .to_string_lossy()
+        .to_string();
+    origin.write(".bashrc", "from repo\n");
+    origin.dm(&["--add", &origin.path(".bashrc")]);
+    origin.dm(&["--add", &pacman]);
+    origin.git(&["commit", "-qm", "dotfiles"]);
+    origin.git(&["push", "--quiet"]);
+
+    let machine = Sandbox::new();
+    let output = machine.dm(&["--clone", &origin.remote_url()]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert!(text(&output).contains("1 entry(s) outside HOME left alone"));
+    assert_eq!(
+        machine.tracking_list(),
+        vec![machine.path(".bashrc"), pacman]
+    );
+    assert_eq!(machine.read(".bashrc"), "from repo\n");
+    assert!(!machine.home.join(".dm").exists());
+}
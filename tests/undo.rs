@@ -0,0 +1,134 @@
+mod common;
+
+use common::{text, Sandbox, KEY_ENTER};
+use dotmanager::{Operation, MAX_OPERATIONS};
+
+#[test]
+fn undo_add_and_remove() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "a\n");
+    let nvim = sb.path(".config/nvim");
+    sb.write(".config/nvim/init.lua", "b\n");
+    sb.dm(&["--add", &nvim]);
+    sb.git(&["commit", "-qm", "nvim"]);
+
+    sb.dm(&["--add", &zshrc]);
+    let output = sb.dm(&["--undo"]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert!(text(&output).contains("dm --add"));
+    assert_eq!(sb.tracking_list(), vec![nvim.clone()]);
+    assert!(sb.git(&["diff", "--cached", "--name-only"]).is_empty());
+    assert_eq!(sb.read(".zshrc"), "a\n");
+
+    sb.dm(&["--remove", &nvim]);
+    assert!(!sb.home.join(".config/nvim").exists());
+    let output = sb.dm(&["--undo"]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert_eq!(sb.read(".config/nvim/init.lua"), "b\n");
+    assert_eq!(sb.tracking_list(), vec![nvim.clone()]);
+    assert!(sb.git(&["status", "--porcelain"]).is_empty());
+
+    // The first add is older than the manual commit that followed it.
+    let output = sb.dm(&["--undo"]);
+    assert!(!output.status.success());
+    assert!(text(&output).contains("HEAD moved"));
+}
+
+#[test]
+fn undo_of_pushed_update_needs_force() {
+    let sb = Sandbox::initialized();
+    let zshrc = sb.write(".zshrc", "a\n");
+    sb.dm(&["--add", &zshrc]);
+    let session = sb.dm_scripted(
+        &["--update"],
+        &[
+            ("Proceed to:", KEY_ENTER),
+            ("Add commit message: ", "wrong message\r"),
+        ],
+    );
+    assert!(session.success, "{}", session.output);
+
+    let output = sb.dm(&["--undo"]);
+    assert!(!output.status.success());
+    assert!(text(&output).contains("already pushed"));
+
+    let output = sb.dm(&["--undo", "--force"]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert!(text(&output).contains("--force"));
+    assert_eq!(
+        sb.remote_git(&["log", "-1", "--format=%s", "main"]).trim(),
+        "Initial commit"
+    );
+    assert_eq!(
+        sb.git(&["log", "-1", "--format=%s"]).trim(),
+        "Initial commit"
+    );
+    // The update staged .zshrc, so undoing it leaves the file unstaged but in the list.
+    assert_eq!(sb.tracking_list(), vec![zshrc]);
+    assert!(text(&sb.dm(&["--status"])).contains("/.zshrc"));
+}
+
+#[test]
+fn undo_of_local_commit() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    sb.write(".zshrc", "a\n");
+    repo.track(&sb.path(".zshrc")).unwrap();
+
+    let operation = repo.begin_operation("--update").unwrap();
+    repo.commit("local").unwrap();
+    repo.finish_operation(operation).unwrap();
+    // Read-only commands leave no trace.
+    let operation = repo.begin_operation("--diff").unwrap();
+    repo.finish_operation(operation).unwrap();
+    assert_eq!(repo.journal().unwrap().len(), 1);
+
+    let undone = repo.undo(false).unwrap();
+    assert_eq!(undone.operation.command, "--update");
+    assert!(undone.operation.committed);
+    assert_eq!(
+        sb.git(&["log", "-1", "--format=%s"]).trim(),
+        "Initial commit"
+    );
+    assert!(repo.journal().unwrap()[0].undone);
+    assert!(matches!(
+        repo.undo(false),
+        Err(dotmanager::Error::NothingToUndo)
+    ));
+}
+
+#[test]
+fn journal_keeps_the_latest_operations_and_names_itself_when_broken() {
+    let sb = Sandbox::initialized();
+    let repo = sb.repo();
+    let journal = repo.journal_file();
+    let mut full = String::new();
+    for i in 0..MAX_OPERATIONS {
+        let operation = Operation {
+            command: format!("--add ~/.file{i}"),
+            undone: true,
+            ..Operation::default()
+        };
+        full += &format!("[[operation]]\n{}\n", toml::to_string(&operation).unwrap());
+    }
+    std::fs::write(&journal, full).unwrap();
+
+    let zshrc = sb.write(".zshrc", "a\n");
+    let output = sb.dm(&["--add", &zshrc]);
+    assert!(output.status.success(), "{}", text(&output));
+    let operations = repo.journal().unwrap();
+    assert_eq!(operations.len(), MAX_OPERATIONS);
+    assert_eq!(operations[0].command, "--add ~/.file1");
+    assert!(operations.last().unwrap().command.starts_with("--add"));
+    assert!(!operations.last().unwrap().undone);
+    let output = sb.dm(&["--undo"]);
+    assert!(output.status.success(), "{}", text(&output));
+    assert!(sb.tracking_list().is_empty());
+
+    std::fs::write(&journal, "[[operation]]\ncommand = 1\n").unwrap();
+    let output = sb.dm(&["--undo"]);
+    assert!(!output.status.success());
+    let output = text(&output);
+    assert!(output.contains("journal.toml"), "{output}");
+    assert!(output.contains("invalid journal"), "{output}");
+}